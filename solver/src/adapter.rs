@@ -1,7 +1,13 @@
+//! Each SAT library's impls are compiled in only with its cargo feature.
 use crate::data::pattern::Edge;
+#[cfg(feature = "splr")]
 use splr::types::Lit as SplrLit;
+#[cfg(feature = "varisat")]
 use varisat::{CnfFormula, ExtendFormula, Lit as VLit};
+#[cfg(feature = "z3")]
 use z3::ast::Bool as Z3Bool;
+#[cfg(feature = "z3")]
+use z3::Solver as Z3Solver;
 
 pub trait SlitherlinkerLit {
     fn to_edge(&self) -> Edge;
@@ -14,6 +20,7 @@ pub trait SlitherlinkerFormula<T: SlitherlinkerLit> {
     fn pure_lit(&self, ix: usize) -> T;
 }
 
+#[cfg(feature = "varisat")]
 impl SlitherlinkerFormula<VLit> for CnfFormula {
     fn append_clause(&mut self, clause: Vec<VLit>) {
         self.add_clause(clause.as_slice())
@@ -23,8 +30,10 @@ impl SlitherlinkerFormula<VLit> for CnfFormula {
     }
 }
 
+#[cfg(feature = "splr")]
 pub type SplrRules = Vec<Vec<SplrLit>>;
 
+#[cfg(feature = "splr")]
 impl SlitherlinkerFormula<SplrLit> for SplrRules {
     fn append_clause(&mut self, clause: Vec<SplrLit>) {
         self.append(&mut vec![clause])
@@ -35,6 +44,19 @@ impl SlitherlinkerFormula<SplrLit> for SplrRules {
     }
 }
 
+#[cfg(feature = "z3")]
+/// Clauses go straight into the solver. Variable `ix` is the Z3 constant `e{ix}`, the same
+/// name the z3 backend gives its edge variables.
+impl SlitherlinkerFormula<Z3Bool> for Z3Solver {
+    fn append_clause(&mut self, clause: Vec<Z3Bool>) {
+        self.assert(Z3Bool::or(&clause))
+    }
+
+    fn pure_lit(&self, ix: usize) -> Z3Bool {
+        Z3Bool::new_const(format!("e{ix}"))
+    }
+}
+
 impl SlitherlinkerLit for i32 {
     #[inline]
     fn to_edge(&self) -> Edge {
@@ -51,6 +73,7 @@ impl SlitherlinkerLit for i32 {
     }
 }
 
+#[cfg(feature = "varisat")]
 impl SlitherlinkerLit for VLit {
     #[inline]
     fn to_edge(&self) -> Edge {
@@ -67,6 +90,7 @@ impl SlitherlinkerLit for VLit {
     }
 }
 
+#[cfg(feature = "splr")]
 impl SlitherlinkerLit for SplrLit {
     #[inline]
     fn to_edge(&self) -> Edge {
@@ -80,6 +104,7 @@ impl SlitherlinkerLit for SplrLit {
     }
 }
 
+#[cfg(feature = "z3")]
 impl SlitherlinkerLit for Z3Bool {
     #[inline]
     fn to_edge(&self) -> Edge {
@@ -0,0 +1,121 @@
+//! The engines that search for the loop, and the registry they are picked from.
+//!
+//! A backend is one [`SolverBackend`] impl plus one entry in [`REGISTRY`]; the CLI and the
+//! library find it by name from there.
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use crate::data::puzzle::Puzzle;
+use crate::solve_common::{into_outcome, search_stream, SolutionStream};
+use crate::solve_native::Native;
+#[cfg(feature = "splr")]
+use crate::solve_splr::Splr;
+#[cfg(feature = "varisat")]
+use crate::solve_varisat::Varisat;
+#[cfg(feature = "z3")]
+use crate::solve_z3::Z3;
+use crate::{SolveOptions, SolveOutcome};
+
+/// Which parts of [`SolveOptions`] a backend pays attention to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Looks for single loops by blocking multi-loop SAT models, so `max_iterations` and
+    /// `subtour_cuts` apply.
+    pub blocking: bool,
+    /// Can encode single-loop connectivity when `connectivity` is set.
+    pub connectivity: bool,
+}
+
+pub trait SolverBackend: Send + Sync {
+    /// Name used on the command line and in logs.
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// Every distinct single-loop solution of `puzzle`, found lazily.
+    fn solutions(&self, puzzle: &Puzzle, options: &SolveOptions) -> Box<dyn SolutionStream>;
+
+    /// The first single-loop solution, or the last multi-loop model when there is none.
+    fn solve(&self, puzzle: &Puzzle, options: &SolveOptions) -> SolveOutcome {
+        into_outcome(search_stream(self.solutions(puzzle, options).as_mut(), 1), options)
+    }
+}
+
+/// A registered backend. Two handles are equal when they have the same name.
+#[derive(Clone, Copy)]
+pub struct Backend(&'static dyn SolverBackend);
+
+/// Every backend compiled in, in the order the CLI lists them. The first one is the default.
+/// The SAT backends each sit behind the cargo feature of the same name; `native` is always
+/// there.
+pub static REGISTRY: &[Backend] = &[
+    #[cfg(feature = "varisat")]
+    Backend(&Varisat),
+    #[cfg(feature = "splr")]
+    Backend(&Splr),
+    #[cfg(feature = "z3")]
+    Backend(&Z3),
+    // Backtracking search without a SAT solver, see `solve_native`.
+    Backend(&Native),
+];
+
+impl Backend {
+    pub fn from_name(name: &str) -> Option<Backend> {
+        REGISTRY.iter().copied().find(|b| b.name() == name)
+    }
+}
+
+impl Deref for Backend {
+    type Target = dyn SolverBackend;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        REGISTRY[0]
+    }
+}
+
+impl PartialEq for Backend {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl Eq for Backend {}
+
+impl Hash for Backend {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name().hash(state);
+    }
+}
+
+impl fmt::Debug for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_every_backend_by_name() {
+        for &backend in REGISTRY {
+            assert_eq!(Backend::from_name(backend.name()), Some(backend));
+        }
+        assert_eq!(Backend::from_name("minisat"), None);
+        assert_eq!(Backend::default(), REGISTRY[0]);
+    }
+}
@@ -0,0 +1,825 @@
+pub mod compare;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "varisat")]
+use solver::data::pattern_file::format_pattern;
+use solver::data::solution::{format_puzzle, format_puzzle_diff, ANSI_RED};
+#[cfg(feature = "varisat")]
+use solver::discover::Discovery;
+use solver::grade::grade as grade_puzzle;
+use solver::lookahead::{find_facts_lookahead_traced, Lookahead};
+use solver::parse::{difficulty, from_string, validate as validate_string};
+use solver::pattern_stats::{PatternStats, Usage};
+use solver::patterns::{facts_to_edges, find_facts_traced, next_hint, PatternSet, Step};
+use solver::{
+    check_uniqueness, solutions, solve as solve_puzzle, Backend, Edge, Puzzle, Solution,
+    SolveOptions, SolveOutcome, Uniqueness,
+};
+
+use crate::Format;
+
+/// Expand CLI inputs into puzzle strings. Each input is `-` (stdin), an existing file
+/// (one puzzle per line, `#` starts a comment line), a directory of such files, read in
+/// order of their names, or a puzzle string itself.
+pub fn read_inputs(inputs: &[String]) -> io::Result<Vec<String>> {
+    let stdin = [String::from("-")];
+    let inputs = if inputs.is_empty() { &stdin[..] } else { inputs };
+
+    let mut res = vec![];
+    for input in inputs {
+        let lines: Vec<String> = if input == "-" {
+            io::stdin().lock().lines().collect::<io::Result<_>>()?
+        } else if Path::new(input).is_file() {
+            fs::read_to_string(input)?.lines().map(String::from).collect()
+        } else if Path::new(input).is_dir() {
+            let mut files = fs::read_dir(input)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+            files.retain(|f| f.is_file());
+            files.sort();
+            let mut lines = vec![];
+            for file in files {
+                lines.extend(fs::read_to_string(file)?.lines().map(String::from));
+            }
+            lines
+        } else {
+            vec![input.clone()]
+        };
+        res.extend(
+            lines
+                .into_iter()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty() && !l.starts_with('#')),
+        );
+    }
+    Ok(res)
+}
+
+fn parse_puzzle(s: &str) -> Option<Puzzle> {
+    match from_string(s) {
+        Ok(grid) => Some(Puzzle::from_grid(grid)),
+        Err(e) => {
+            eprintln!("error: {s}: {e}");
+            None
+        }
+    }
+}
+
+pub fn label(backend: Backend, pre_solve: bool) -> String {
+    let pre = if pre_solve { "pre   " } else { "no-pre" };
+    format!("[{:<7} / {pre}] ", backend.name())
+}
+
+// (backend, pre_solve, outcome, elapsed)
+pub type JobResult = (Backend, bool, SolveOutcome, Duration);
+
+/// How long dropping [`Jobs`] waits for cancelled threads before leaving them behind.
+const STOP_GRACE: Duration = Duration::from_millis(100);
+
+/// Solver threads working on one puzzle, each sending what it finds. Dropping them cancels
+/// the ones still running through [`SolveOptions::cancel`], waits up to [`STOP_GRACE`] for
+/// them to stop and detaches the rest, so a SAT call that never checks the flag can't hold
+/// up `--timeout`.
+pub struct Jobs<T> {
+    rx: mpsc::Receiver<T>,
+    cancel: Arc<AtomicBool>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> Jobs<T> {
+    /// Run each of `jobs` on its own thread, with the cancel flag to put in its options.
+    fn start<F>(jobs: Vec<F>) -> Jobs<T>
+    where
+        F: FnOnce(Arc<AtomicBool>, mpsc::Sender<T>) + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel::<T>();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let threads = jobs
+            .into_iter()
+            .map(|job| {
+                let tx = tx.clone();
+                let cancel = cancel.clone();
+                thread::spawn(move || job(cancel, tx))
+            })
+            .collect();
+        Jobs {
+            rx,
+            cancel,
+            threads,
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, mpsc::RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+}
+
+impl<T> Drop for Jobs<T> {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        let deadline = Instant::now() + STOP_GRACE;
+        while self.threads.iter().any(|t| !t.is_finished()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        for thread in self.threads.drain(..) {
+            if thread.is_finished() {
+                thread.join().ok();
+            }
+        }
+    }
+}
+
+/// Run every `(backend, pre_solve)` job on its own thread, with the rest of the options
+/// taken from `base`. Results arrive in completion order.
+pub fn spawn_jobs(
+    puzzle: &Puzzle,
+    jobs: &[(Backend, bool)],
+    base: &SolveOptions,
+    verbose: bool,
+) -> Jobs<JobResult> {
+    Jobs::start(
+        jobs.iter()
+            .map(|&(backend, pre_solve)| {
+                let puzzle = puzzle.clone();
+                let base = base.clone();
+                move |cancel, tx: mpsc::Sender<JobResult>| {
+                    let prefix = label(backend, pre_solve);
+                    if verbose {
+                        println!("{prefix}Starting...");
+                    }
+                    let t0 = Instant::now();
+                    let options = SolveOptions {
+                        backend,
+                        pre_solve,
+                        log_prefix: verbose.then_some(prefix),
+                        cancel,
+                        ..base
+                    };
+                    let outcome = solve_puzzle(&puzzle, &options);
+                    tx.send((backend, pre_solve, outcome, t0.elapsed())).ok();
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Every job, with and without pre-solve, for each backend.
+pub fn all_jobs(backends: &[Backend]) -> Vec<(Backend, bool)> {
+    backends
+        .iter()
+        .flat_map(|&b| [(b, false), (b, true)])
+        .collect()
+}
+
+/// Tell the user about options some of `backends` can't honour.
+fn warn_ignored(backends: &[Backend], base: &SolveOptions) {
+    for backend in backends {
+        let caps = backend.capabilities();
+        if base.connectivity && !caps.connectivity {
+            eprintln!("warning: {backend} ignores --connectivity");
+        }
+        if base.subtour_cuts && !caps.blocking {
+            eprintln!("warning: {backend} ignores --subtour-cuts");
+        }
+    }
+}
+
+/// `1` for filled, `0` for empty and `?` for unknown edges, in edge index order.
+pub fn edges_string(edges: &[Edge]) -> String {
+    edges
+        .iter()
+        .map(|e| match e {
+            Edge::Filled => '1',
+            Edge::Empty => '0',
+            _ => '?',
+        })
+        .collect()
+}
+
+/// Inverse of [`edges_string`].
+pub fn parse_edges(s: &str) -> Result<Vec<Edge>, String> {
+    s.chars()
+        .map(|c| match c {
+            '1' => Ok(Edge::Filled),
+            '0' => Ok(Edge::Empty),
+            '?' => Ok(Edge::Unknown),
+            _ => Err(format!("unexpected {c:?} in edges, expected 1, 0 or ?")),
+        })
+        .collect()
+}
+
+fn json_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Solve every puzzle, racing `backends` with the rest of the options taken from `base`.
+pub fn solve(
+    puzzles: &[String],
+    backends: &[Backend],
+    base: &SolveOptions,
+    timeout: Duration,
+    format: Format,
+    verbose: bool,
+) -> bool {
+    warn_ignored(backends, base);
+    let mut all_solved = true;
+    for puzzle_str in puzzles {
+        let Some(puzzle) = parse_puzzle(puzzle_str) else {
+            all_solved = false;
+            continue;
+        };
+        let jobs: Vec<(Backend, bool)> = backends.iter().map(|&b| (b, base.pre_solve)).collect();
+        let rx = spawn_jobs(&puzzle, &jobs, base, verbose);
+
+        // First single-loop answer wins; otherwise keep whatever came back last.
+        let deadline = Instant::now() + timeout;
+        let mut answer: Option<JobResult> = None;
+        for _ in 0..jobs.len() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(remaining) {
+                Ok(r) => {
+                    let solved = r.2.is_solved();
+                    answer = Some(r);
+                    if solved {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+
+        let solved = answer.as_ref().is_some_and(|r| r.2.is_solved());
+        all_solved &= solved;
+        match format {
+            Format::Text => {
+                println!("Puzzle: {puzzle_str}");
+                match &answer {
+                    Some((backend, _, SolveOutcome::Solved(s), elapsed)) => {
+                        println!("Solved by {backend} in {elapsed:.2?}");
+                        print!("{}", format_puzzle(&s.puzzle, &s.edges));
+                    }
+                    Some((backend, _, SolveOutcome::Unsolved(_), elapsed)) => {
+                        println!("No single-loop solution ({backend}, {elapsed:.2?})");
+                    }
+                    None => println!("Timed out after {timeout:.0?}"),
+                }
+            }
+            Format::Json => {
+                let (backend, elapsed, edges) = match &answer {
+                    Some((b, _, outcome, elapsed)) => (
+                        json_string(b.name()),
+                        elapsed.as_millis().to_string(),
+                        match outcome {
+                            SolveOutcome::Solved(s) => json_string(&edges_string(&s.edges)),
+                            SolveOutcome::Unsolved(_) => String::from("null"),
+                        },
+                    ),
+                    None => (String::from("null"), String::from("null"), String::from("null")),
+                };
+                println!(
+                    "{{\"puzzle\":{},\"solved\":{solved},\"backend\":{backend},\"elapsed_ms\":{elapsed},\"edges\":{edges}}}",
+                    json_string(puzzle_str)
+                );
+            }
+        }
+    }
+    all_solved
+}
+
+pub fn deduce(
+    puzzles: &[String],
+    patterns: &PatternSet,
+    steps: bool,
+    lookahead: &Lookahead,
+    format: Format,
+) -> bool {
+    let mut ok = true;
+    for puzzle_str in puzzles {
+        let Some(puzzle) = parse_puzzle(puzzle_str) else {
+            ok = false;
+            continue;
+        };
+        let traced = if lookahead.depth == 0 {
+            find_facts_traced(&puzzle, patterns)
+        } else {
+            find_facts_lookahead_traced(&puzzle, patterns, lookahead)
+        };
+        let (facts, trace) = match traced {
+            Ok(traced) => traced,
+            Err(c) => {
+                eprintln!("error: {puzzle_str}: contradiction, {c}");
+                ok = false;
+                continue;
+            }
+        };
+        let edges = facts_to_edges(&puzzle, &facts);
+        match format {
+            Format::Text => {
+                println!("Puzzle: {puzzle_str}");
+                if steps {
+                    for (n, step) in trace.iter().enumerate() {
+                        print_step(&puzzle, n + 1, step);
+                    }
+                }
+                println!("Deduced {} of {} edges", facts.len(), edges.len());
+                print!("{}", format_puzzle(&puzzle, &edges));
+            }
+            Format::Json => {
+                let steps_json = if steps {
+                    let items: Vec<String> = trace.iter().map(step_json).collect();
+                    format!(",\"steps\":[{}]", items.join(","))
+                } else {
+                    String::new()
+                };
+                println!(
+                    "{{\"puzzle\":{},\"facts\":{},\"edges\":{}{steps_json}}}",
+                    json_string(puzzle_str),
+                    facts.len(),
+                    json_string(&edges_string(&edges))
+                )
+            }
+        }
+    }
+    ok
+}
+
+/// The board after `step`, with the edges it set in red.
+fn print_step(puzzle: &Puzzle, n: usize, step: &Step) {
+    let (i, j) = step.window;
+    println!(
+        "Step {n}: {} at ({i}, {j}) sets {} edge(s)",
+        step.pattern,
+        step.edges.len()
+    );
+    let highlights: HashMap<usize, &'static str> =
+        step.edges.iter().map(|&(e, _)| (e, ANSI_RED)).collect();
+    print!("{}", format_puzzle_diff(puzzle, &step.after, &highlights));
+}
+
+fn step_json(step: &Step) -> String {
+    let edges: Vec<String> = step
+        .edges
+        .iter()
+        .map(|(e, filled)| format!("[{e},{filled}]"))
+        .collect();
+    format!(
+        "{{\"pattern\":{},\"row\":{},\"column\":{},\"edges\":[{}]}}",
+        json_string(&step.pattern),
+        step.window.0,
+        step.window.1,
+        edges.join(",")
+    )
+}
+
+/// Suggest the next deduction for each puzzle, starting from the marks in `edges`.
+pub fn hint(
+    puzzles: &[String],
+    patterns: &PatternSet,
+    edges: Option<&str>,
+    format: Format,
+) -> bool {
+    let mut ok = true;
+    for puzzle_str in puzzles {
+        let Some(puzzle) = parse_puzzle(puzzle_str) else {
+            ok = false;
+            continue;
+        };
+        let board = match edges.map(parse_edges) {
+            None => vec![Edge::Unknown; puzzle.edge_count()],
+            Some(Ok(board)) if board.len() == puzzle.edge_count() => board,
+            Some(Ok(board)) => {
+                eprintln!(
+                    "error: {puzzle_str}: expected {} edges, got {}",
+                    puzzle.edge_count(),
+                    board.len()
+                );
+                ok = false;
+                continue;
+            }
+            Some(Err(e)) => {
+                eprintln!("error: {e}");
+                ok = false;
+                continue;
+            }
+        };
+        let hint = next_hint(&puzzle, patterns, &board);
+        match format {
+            Format::Text => {
+                println!("Puzzle: {puzzle_str}");
+                match &hint {
+                    Some(hint) => {
+                        println!("{}", hint.text);
+                        let mut after = board.clone();
+                        for &(e, filled) in &hint.edges {
+                            after[e] = if filled { Edge::Filled } else { Edge::Empty };
+                        }
+                        let highlights: HashMap<usize, &'static str> =
+                            hint.edges.iter().map(|&(e, _)| (e, ANSI_RED)).collect();
+                        print!("{}", format_puzzle_diff(&puzzle, &after, &highlights));
+                    }
+                    None => println!("No pattern applies to this board."),
+                }
+            }
+            Format::Json => {
+                let hint_json = match &hint {
+                    Some(hint) => {
+                        let edges: Vec<String> = hint
+                            .edges
+                            .iter()
+                            .map(|(e, filled)| format!("[{e},{filled}]"))
+                            .collect();
+                        format!(
+                            "{{\"pattern\":{},\"row\":{},\"column\":{},\"edges\":[{}],\"text\":{}}}",
+                            json_string(&hint.pattern),
+                            hint.window.0,
+                            hint.window.1,
+                            edges.join(","),
+                            json_string(&hint.text)
+                        )
+                    }
+                    None => String::from("null"),
+                };
+                println!(
+                    "{{\"puzzle\":{},\"hint\":{hint_json}}}",
+                    json_string(puzzle_str)
+                );
+            }
+        }
+    }
+    ok
+}
+
+/// Grade each puzzle, next to the difficulty its source gave it.
+pub fn grade(puzzles: &[String], patterns: &PatternSet, format: Format) -> bool {
+    let mut ok = true;
+    for puzzle_str in puzzles {
+        let Some(puzzle) = parse_puzzle(puzzle_str) else {
+            ok = false;
+            continue;
+        };
+        let g = match grade_puzzle(&puzzle, patterns) {
+            Ok(g) => g,
+            Err(c) => {
+                eprintln!("error: {puzzle_str}: contradiction, {c}");
+                ok = false;
+                continue;
+            }
+        };
+        let source = difficulty(puzzle_str);
+        match format {
+            Format::Text => {
+                let source = source.map_or(String::new(), |d| format!(" (source: d{d})"));
+                println!("{g}{source}  {puzzle_str}");
+            }
+            Format::Json => println!(
+                "{{\"puzzle\":{},\"grade\":{},\"score\":{},\"steps\":{},\"search\":{},\"unknown\":{},\"gave_up\":{},\"source\":{}}}",
+                json_string(puzzle_str),
+                json_string(g.name()),
+                g.score(),
+                g.steps,
+                g.needs_search(),
+                g.unknown,
+                g.gave_up,
+                source.map_or(String::from("null"), |d| d.to_string())
+            ),
+        }
+    }
+    ok
+}
+
+/// Run the pattern engine over every puzzle and show what each pattern did, see
+/// [`solver::pattern_stats`].
+pub fn pattern_stats(puzzles: &[String], patterns: &PatternSet, format: Format) -> bool {
+    let mut ok = true;
+    let mut usage = Usage::new(patterns);
+    // The puzzles `usage` counted, in order.
+    let mut added: Vec<&str> = vec![];
+    for puzzle_str in puzzles {
+        let Some(puzzle) = parse_puzzle(puzzle_str) else {
+            ok = false;
+            continue;
+        };
+        match usage.add(&puzzle) {
+            Ok(()) => added.push(puzzle_str),
+            Err(c) => {
+                eprintln!("error: {puzzle_str}: contradiction, {c}");
+                ok = false;
+            }
+        }
+    }
+
+    let mut stats: Vec<(&String, &PatternStats)> = usage.stats.iter().collect();
+    stats.sort_by_key(|&(name, s)| (Reverse(s.fired), name));
+    match format {
+        Format::Text => {
+            println!("Over {} puzzle(s):", usage.puzzles);
+            println!("{:>7} {:>7} {:>9}  pattern", "fired", "facts", "only way");
+            for (name, s) in stats.iter().filter(|(_, s)| s.fired > 0) {
+                println!("{:>7} {:>7} {:>9}  {name}", s.fired, s.facts, s.only_way.len());
+            }
+            for (name, s) in stats.iter().filter(|(_, s)| !s.only_way.is_empty()) {
+                println!("\nOnly {name} gets past:");
+                for &ix in &s.only_way {
+                    println!("  {}", added[ix]);
+                }
+            }
+            let never: Vec<&str> = usage.never_fired().collect();
+            if !never.is_empty() {
+                println!("\nNever fired:");
+                for name in never {
+                    println!("  {name}");
+                }
+            }
+        }
+        Format::Json => {
+            for (name, s) in stats {
+                let only_way: Vec<String> =
+                    s.only_way.iter().map(|&ix| json_string(added[ix])).collect();
+                println!(
+                    "{{\"pattern\":{},\"fired\":{},\"facts\":{},\"only_way\":[{}]}}",
+                    json_string(name),
+                    s.fired,
+                    s.facts,
+                    only_way.join(",")
+                );
+            }
+        }
+    }
+    ok
+}
+
+/// Print the patterns [`solver::discover::discover`] finds beyond `known`, in the notation
+/// `--patterns` reads.
+#[cfg(feature = "varisat")]
+pub fn discover(known: &PatternSet, limits: &Discovery) -> bool {
+    let found = solver::discover::discover(known, limits);
+    println!("# {} pattern(s) found with {limits:?}", found.len());
+    for (name, pattern) in &found {
+        println!("\n{}", format_pattern(name, pattern).trim_end());
+    }
+    true
+}
+
+pub fn render(puzzles: &[String]) -> bool {
+    let mut ok = true;
+    for puzzle_str in puzzles {
+        let Some(puzzle) = parse_puzzle(puzzle_str) else {
+            ok = false;
+            continue;
+        };
+        println!("Puzzle: {puzzle_str}");
+        print!("{}", format_puzzle(&puzzle, &[]));
+    }
+    ok
+}
+
+pub fn bench(
+    puzzles: &[String],
+    backends: &[Backend],
+    base: &SolveOptions,
+    timeout: Duration,
+) -> bool {
+    warn_ignored(backends, base);
+    let mut ok = true;
+    for puzzle_str in puzzles {
+        let Some(puzzle) = parse_puzzle(puzzle_str) else {
+            ok = false;
+            continue;
+        };
+        println!("Puzzle: {puzzle_str}");
+        let timings = collect(&puzzle, &all_jobs(backends), base, timeout, false);
+        print_timing_matrix(backends, &timings);
+    }
+    ok
+}
+
+pub fn validate(puzzles: &[String]) -> bool {
+    let mut ok = true;
+    for puzzle_str in puzzles {
+        match validate_string(puzzle_str) {
+            Ok((xsize, ysize)) => println!("ok      {xsize}x{ysize} {puzzle_str}"),
+            Err(e) => {
+                ok = false;
+                println!("invalid {puzzle_str}: {e}");
+            }
+        }
+    }
+    ok
+}
+
+/// Check each puzzle for a unique solution. Several backends race, and the first one to reach
+/// a verdict wins.
+pub fn verify(puzzles: &[String], backends: &[Backend], timeout: Duration, format: Format) -> bool {
+    let mut all_unique = true;
+    for puzzle_str in puzzles {
+        let Some(puzzle) = parse_puzzle(puzzle_str) else {
+            all_unique = false;
+            continue;
+        };
+        let rx = Jobs::start(
+            backends
+                .iter()
+                .map(|&backend| {
+                    let puzzle = puzzle.clone();
+                    move |cancel, tx: mpsc::Sender<(Backend, Uniqueness)>| {
+                        let options = SolveOptions {
+                            backend,
+                            cancel,
+                            ..SolveOptions::default()
+                        };
+                        tx.send((backend, check_uniqueness(&puzzle, &options))).ok();
+                    }
+                })
+                .collect(),
+        );
+
+        let deadline = Instant::now() + timeout;
+        let mut answer: Option<(Backend, Uniqueness)> = None;
+        for _ in 0..backends.len() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(remaining) {
+                Ok(r) => {
+                    let decided = !matches!(r.1, Uniqueness::Unknown(_));
+                    answer = Some(r);
+                    if decided {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+
+        all_unique &= matches!(answer, Some((_, Uniqueness::Unique(_))));
+        match format {
+            Format::Text => {
+                println!("Puzzle: {puzzle_str}");
+                match &answer {
+                    Some((backend, result)) => print!("[{backend}] {result}"),
+                    None => println!("Timed out after {timeout:.0?}"),
+                }
+            }
+            Format::Json => {
+                let result = match &answer {
+                    Some((_, Uniqueness::Unique(_))) => "unique",
+                    Some((_, Uniqueness::Multiple(..))) => "multiple",
+                    Some((_, Uniqueness::NoSolution)) => "none",
+                    Some((_, Uniqueness::Unknown(_))) | None => "unknown",
+                };
+                let (first, second) = match &answer {
+                    Some((_, Uniqueness::Unique(s))) => (Some(s), None),
+                    Some((_, Uniqueness::Multiple(a, b))) => (Some(a), Some(b)),
+                    Some((_, Uniqueness::Unknown(s))) => (s.as_ref(), None),
+                    _ => (None, None),
+                };
+                let edges = |s: Option<&Solution>| match s {
+                    Some(s) => json_string(&edges_string(&s.edges)),
+                    None => String::from("null"),
+                };
+                println!(
+                    "{{\"puzzle\":{},\"result\":\"{result}\",\"first\":{},\"second\":{}}}",
+                    json_string(puzzle_str),
+                    edges(first),
+                    edges(second)
+                );
+            }
+        }
+    }
+    all_unique
+}
+
+/// Print up to `limit` distinct solutions of each puzzle as they are found.
+pub fn enumerate(
+    puzzles: &[String],
+    backend: Backend,
+    limit: usize,
+    timeout: Duration,
+    format: Format,
+) -> bool {
+    let mut ok = true;
+    for puzzle_str in puzzles {
+        let Some(puzzle) = parse_puzzle(puzzle_str) else {
+            ok = false;
+            continue;
+        };
+        let job_puzzle = puzzle.clone();
+        let rx = Jobs::start(vec![move |cancel, tx: mpsc::Sender<Solution>| {
+            // Patterns may take uniqueness for granted, which would hide solutions.
+            let options = SolveOptions {
+                backend,
+                pre_solve: false,
+                cancel,
+                ..SolveOptions::default()
+            };
+            for solution in solutions(&job_puzzle, &options).take(limit) {
+                if tx.send(solution).is_err() {
+                    break;
+                }
+            }
+        }]);
+
+        if format == Format::Text {
+            println!("Puzzle: {puzzle_str}");
+        }
+        let deadline = Instant::now() + timeout;
+        let mut count = 0;
+        let mut timed_out = false;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(remaining) {
+                Ok(solution) => {
+                    count += 1;
+                    match format {
+                        Format::Text => {
+                            println!("Solution {count}:");
+                            print!("{}", format_puzzle(&puzzle, &solution.edges));
+                        }
+                        Format::Json => println!(
+                            "{{\"puzzle\":{},\"index\":{count},\"edges\":{}}}",
+                            json_string(puzzle_str),
+                            json_string(&edges_string(&solution.edges))
+                        ),
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    timed_out = true;
+                    break;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        if format == Format::Text {
+            match (timed_out, count == limit) {
+                (true, _) => println!("Timed out after {timeout:.0?}, {count} solution(s) so far"),
+                (false, true) => println!("Stopped at the limit of {limit} solution(s)"),
+                (false, false) => println!("{count} solution(s) in total"),
+            }
+        }
+        ok &= count > 0;
+    }
+    ok
+}
+
+/// Run `jobs` and wait for all of them, or until `timeout` passes.
+pub fn collect(
+    puzzle: &Puzzle,
+    jobs: &[(Backend, bool)],
+    base: &SolveOptions,
+    timeout: Duration,
+    verbose: bool,
+) -> Vec<JobResult> {
+    let rx = spawn_jobs(puzzle, jobs, base, verbose);
+    let deadline = Instant::now() + timeout;
+    let mut results = vec![];
+    for _ in 0..jobs.len() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(remaining) {
+            Ok(r) => {
+                if verbose {
+                    println!(
+                        "{}Completed: {}.",
+                        label(r.0, r.1),
+                        if r.2.is_solved() { "solved" } else { "no solution" }
+                    );
+                }
+                results.push(r)
+            }
+            Err(_) => {
+                println!("Timeout reached, proceeding with available results.");
+                break;
+            }
+        }
+    }
+    results
+}
+
+pub fn print_timing_matrix(backends: &[Backend], results: &[JobResult]) {
+    let get_time = |backend: Backend, pre: bool| -> String {
+        match results.iter().find(|r| r.0 == backend && r.1 == pre) {
+            Some((_, _, _, d)) => format!("{:.2?}", d),
+            None => "timed out".to_string(),
+        }
+    };
+
+    println!("\n=== Timing Matrix ===");
+    println!(
+        "  {:<22} | {:<12} | {:<12}",
+        "", "no pre-solve", "with pre-solve"
+    );
+    for &backend in backends {
+        println!(
+            "  {:<22} | {:<12} | {:<12}",
+            backend.name(),
+            get_time(backend, false),
+            get_time(backend, true)
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use solver::data::solution::{
+    format_puzzle, format_puzzle_diff, format_side_by_side, Solution, ANSI_RED, ANSI_YELLOW_BG,
+};
+use solver::patterns::{facts_to_edges, find_facts};
+use solver::solve_common::single_loop_edge;
+use solver::{Backend, Edge, SolveOptions};
+
+use crate::cli::{all_jobs, collect, label, parse_puzzle, print_timing_matrix};
+
+/// Run every backend with and without pre-solve, then compare the answers. The first
+/// backend in `backends` is the reference the others are compared against.
+pub fn compare(puzzles: &[String], backends: &[Backend], timeout: Duration) -> bool {
+    let mut ok = true;
+    for puzzle_str in puzzles {
+        println!("Puzzle: {puzzle_str}\n");
+        let Some(puzzle) = parse_puzzle(puzzle_str) else {
+            ok = false;
+            continue;
+        };
+
+        let results = collect(
+            &puzzle,
+            &all_jobs(backends),
+            &SolveOptions::default(),
+            timeout,
+            true,
+        );
+        // A contradiction shows up below as the pre-solve runs failing.
+        let facts = find_facts(&puzzle, &SolveOptions::default().patterns).unwrap_or_default();
+        let pre_solve_edges = facts_to_edges(&puzzle, &facts);
+
+        // (backend, pre_solve) -> best solution
+        let solutions: HashMap<(Backend, bool), Option<Solution>> = results
+            .iter()
+            .map(|(b, pre, outcome, _)| ((*b, *pre), outcome.best().cloned()))
+            .collect();
+
+        ok &= analyse(backends, &solutions, &pre_solve_edges);
+        print_timing_matrix(backends, &results);
+    }
+    ok
+}
+
+fn analyse(
+    backends: &[Backend],
+    solutions: &HashMap<(Backend, bool), Option<Solution>>,
+    pre_solve_edges: &[Edge],
+) -> bool {
+    let reference = backends[0];
+
+    // For the comparison section use the reference backend results.
+    let sol_no_pre = solutions.get(&(reference, false)).and_then(Option::as_ref);
+    let sol_pre = solutions.get(&(reference, true)).and_then(Option::as_ref);
+
+    println!("\n=== Result ===\n");
+
+    // Primary display: reference/pre shows pre-solve deductions and final solution together.
+    // Fall back to reference/no-pre if not available.
+    let primary = sol_pre.or(sol_no_pre);
+
+    match sol_no_pre {
+        Some(sol) => {
+            let mut s0 = String::new();
+            s0.push_str("Input puzzle:\n");
+            s0.push_str(format_puzzle(&sol.puzzle, &sol.edges_pre_solve).as_str());
+            println!("Input puzzle pre pre-solve: {}", s0)
+        }
+        None => println!("could not find raw puzzle :("),
+    }
+    match primary {
+        Some(sol) => print!("{}", sol),
+        None => println!("No solution found (solvers timed out or found no solutions)."),
+    }
+
+    // Reference edges (reference/no-pre) for comparing other solvers.
+    let ref_edges = sol_no_pre.map(|s| &s.edges);
+
+    // Print other solver results only when they differ from the reference.
+    for &backend in &backends[1..] {
+        for pre in [false, true] {
+            let name = label(backend, pre);
+            match solutions.get(&(backend, pre)) {
+                Some(Some(sol)) => match ref_edges {
+                    Some(r) if *r == sol.edges => {}
+                    _ => println!("\n{} differs from reference:\n{}", name.trim(), sol),
+                },
+                Some(None) if ref_edges.is_some() => {
+                    println!(
+                        "{} found no solutions (reference has a solution).",
+                        name.trim()
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Ground truth: first valid single-loop from reference / no-pre.
+    let sol_false = match sol_no_pre {
+        Some(s) if single_loop_edge(&s.puzzle, &s.edges) => s,
+        _ => {
+            println!("Cannot compare: {reference}/no-pre did not produce a valid loop.");
+            return false;
+        }
+    };
+    let puzzle = &sol_false.puzzle;
+    let col_width = 2 * puzzle.ysize + 1;
+
+    // Determine whether reference / pre-solve produced a valid loop.
+    match sol_pre {
+        Some(sol_true) if single_loop_edge(&sol_true.puzzle, &sol_true.edges) => {
+            // Level A: compare final SAT solutions between the two runs.
+            let highlights_a: HashMap<usize, &'static str> = sol_false
+                .edges
+                .iter()
+                .enumerate()
+                .filter(|(i, e)| sol_true.edges.get(*i) != Some(e))
+                .map(|(i, _)| (i, ANSI_RED))
+                .collect();
+
+            if highlights_a.is_empty() {
+                println!("\n=== Level A: Final solutions are identical. ===\n");
+            } else {
+                println!(
+                    "\n=== Level A: Final solutions differ on {} edge(s) (red = mismatch) ===\n",
+                    highlights_a.len()
+                );
+                let left = format_puzzle_diff(puzzle, &sol_false.edges, &highlights_a);
+                let right = format_puzzle_diff(puzzle, &sol_true.edges, &highlights_a);
+                print!(
+                    "{}",
+                    format_side_by_side(
+                        &left,
+                        &right,
+                        "SAT only (reference)",
+                        "with pre_solve",
+                        col_width
+                    )
+                );
+            }
+
+            // Level B: compare pre_solve deductions against the ground-truth final solution.
+            let highlights_b: HashMap<usize, &'static str> = sol_true
+                .edges_pre_solve
+                .iter()
+                .enumerate()
+                .filter(|(_, &e)| e != Edge::Unknown)
+                .filter(|(i, &e)| sol_false.edges.get(*i) != Some(&e))
+                .map(|(i, _)| (i, ANSI_RED))
+                .collect();
+
+            if highlights_b.is_empty() {
+                println!("\n=== Level B: All pre_solve deductions match the ground truth. ===\n");
+            } else {
+                println!(
+                    "\n=== Level B: pre_solve made {} wrong deduction(s) vs ground truth (red = wrong) ===\n",
+                    highlights_b.len()
+                );
+                let left = format_puzzle_diff(puzzle, &sol_false.edges, &highlights_b);
+                let right = format_puzzle_diff(puzzle, &sol_true.edges_pre_solve, &highlights_b);
+                print!(
+                    "{}",
+                    format_side_by_side(
+                        &left,
+                        &right,
+                        "Ground truth (SAT only)",
+                        "Pre-solve deductions",
+                        col_width
+                    )
+                );
+            }
+            highlights_b.is_empty()
+        }
+        _ => {
+            // reference/pre reached a dead end (UNSAT, non-loop fallback, or timed out).
+            // Compare pre_solve deductions against ground truth with two-color highlighting:
+            //   red            = wrong deduction (pre_solve asserted the wrong state)
+            //   yellow bg      = missing deduction (ground truth Filled, pre_solve Unknown)
+            let mut highlights: HashMap<usize, &'static str> = HashMap::new();
+            let mut wrong_count = 0usize;
+            let mut missing_count = 0usize;
+
+            for (i, &pre_e) in pre_solve_edges.iter().enumerate() {
+                let ground = sol_false.edges.get(i).copied().unwrap_or(Edge::Unknown);
+                if pre_e != Edge::Unknown && pre_e != ground {
+                    highlights.insert(i, ANSI_RED);
+                    wrong_count += 1;
+                } else if ground == Edge::Filled && pre_e == Edge::Unknown {
+                    highlights.insert(i, ANSI_YELLOW_BG);
+                    missing_count += 1;
+                }
+            }
+
+            let summary = match (wrong_count, missing_count) {
+                (0, 0) => {
+                    println!("\n=== Dead-end analysis: pre_solve deductions are all correct (dead end has another cause). ===\n");
+                    return false;
+                }
+                (w, 0) => format!("{w} wrong (red)"),
+                (0, m) => format!("{m} missing (yellow bg)"),
+                (w, m) => format!("{w} wrong (red), {m} missing (yellow bg)"),
+            };
+            println!("\n=== Dead-end analysis: pre_solve vs ground truth — {summary} ===\n");
+            let left = format_puzzle_diff(puzzle, &sol_false.edges, &highlights);
+            let right = format_puzzle_diff(puzzle, pre_solve_edges, &highlights);
+            print!(
+                "{}",
+                format_side_by_side(
+                    &left,
+                    &right,
+                    "Ground truth (SAT only)",
+                    "Pre-solve output",
+                    col_width
+                )
+            );
+            false
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Inside/outside colouring of cells, the second layer of the deduction engine.
+//!
+//! Every cell is inside or outside the loop, and everything beyond the border is outside.
+//! An edge is filled exactly when the cells on its two sides differ, so known edges tie
+//! cells together, and cells known to match or differ settle the edge between them. Two
+//! local rules tie more cells:
+//!
+//! - a clue cell differs from exactly as many of its four neighbours as its number, so a 3
+//!   next to an outside cell is inside unless the edge between them is empty, and two
+//!   neighbours of a 3 that match each other both differ from it;
+//! - the four cells around a vertex are never coloured like a checkerboard, since that
+//!   would put four edges on the vertex.
+//!
+//! Each rule tries every colouring of its few cells that fits what is known, and ties
+//! whatever they all agree on.
+use crate::data::pattern::Edge;
+use crate::data::puzzle::Puzzle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colour {
+    Inside,
+    Outside,
+    Unknown,
+}
+
+/// Known edges and colour ties contradict each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict {
+    /// Where the colouring broke down: a known edge the ties disagree with, or an edge of the
+    /// clue or vertex no colouring fits.
+    pub edge: usize,
+}
+
+/// Union-find over cells, keeping each cell's colour relative to its parent.
+pub(crate) struct Colours {
+    parent: Vec<usize>,
+    flip: Vec<bool>,
+}
+
+impl Colours {
+    pub(crate) fn new(cells: usize) -> Colours {
+        Colours {
+            parent: (0..cells).collect(),
+            flip: vec![false; cells],
+        }
+    }
+
+    /// The root of `x`, and whether `x` has the other colour.
+    fn root(&mut self, x: usize) -> (usize, bool) {
+        let p = self.parent[x];
+        if p == x {
+            return (x, false);
+        }
+        let (root, flip) = self.root(p);
+        self.parent[x] = root;
+        self.flip[x] ^= flip;
+        (root, self.flip[x])
+    }
+
+    /// Whether `a` and `b` are known to differ, if they are tied together at all.
+    pub(crate) fn relation(&mut self, a: usize, b: usize) -> Option<bool> {
+        let ((ra, fa), (rb, fb)) = (self.root(a), self.root(b));
+        (ra == rb).then_some(fa ^ fb)
+    }
+
+    /// Tie `a` and `b` together. `Some(true)` when that is news, `None` when they are already
+    /// tied the other way.
+    pub(crate) fn tie(&mut self, a: usize, b: usize, differ: bool) -> Option<bool> {
+        let ((ra, fa), (rb, fb)) = (self.root(a), self.root(b));
+        if ra == rb {
+            return (fa ^ fb == differ).then_some(false);
+        }
+        self.parent[ra] = rb;
+        self.flip[ra] = fa ^ fb ^ differ;
+        Some(true)
+    }
+}
+
+/// [`Colours`] of every cell, with the last node standing for everything beyond the border.
+pub struct Colouring {
+    colours: Colours,
+    /// The two cells on either side of each edge.
+    edge_cells: Vec<(usize, usize)>,
+}
+
+impl Colouring {
+    /// Tie cells along every known edge of `edges`, then apply the clue and vertex rules
+    /// until nothing changes.
+    pub fn deduce(puzzle: &Puzzle, edges: &[Edge]) -> Result<Colouring, Conflict> {
+        let mut c = Colouring {
+            colours: Colours::new(puzzle.xsize * puzzle.ysize + 1),
+            edge_cells: edge_cells(puzzle),
+        };
+        for (e, &value) in edges.iter().enumerate() {
+            let (a, b) = c.edge_cells[e];
+            let differ = match value {
+                Edge::Filled => true,
+                Edge::Empty | Edge::EmptyStrict => false,
+                _ => continue,
+            };
+            c.colours.tie(a, b, differ).ok_or(Conflict { edge: e })?;
+        }
+
+        let clues: Vec<(usize, [usize; 5], usize)> = (0..puzzle.xsize)
+            .flat_map(|i| (0..puzzle.ysize).map(move |j| (i, j)))
+            .filter(|&(i, j)| puzzle.cells[i][j] >= 0)
+            .map(|(i, j)| {
+                let (top, bottom, left, right) = puzzle.edges_around_cell(i, j);
+                let across = |e: usize| {
+                    let (a, b) = c.edge_cells[e];
+                    if a == c.cell(puzzle, i, j) {
+                        b
+                    } else {
+                        a
+                    }
+                };
+                let cell = c.cell(puzzle, i, j);
+                let group = [
+                    cell,
+                    across(top),
+                    across(bottom),
+                    across(left),
+                    across(right),
+                ];
+                (puzzle.cells[i][j] as usize, group, top)
+            })
+            .collect();
+        let vertices: Vec<([usize; 4], usize)> = (0..=puzzle.xsize)
+            .flat_map(|i| (0..=puzzle.ysize).map(move |j| (i, j)))
+            .map(|(i, j)| {
+                let at =
+                    |di: usize, dj: usize| match ((i + di).checked_sub(1), (j + dj).checked_sub(1))
+                    {
+                        (Some(a), Some(b)) => c.cell(puzzle, a, b),
+                        _ => c.outside(),
+                    };
+                // Clockwise from the top left.
+                let group = [at(0, 0), at(0, 1), at(1, 1), at(1, 0)];
+                (group, puzzle.edges_around_point(i, j)[0])
+            })
+            .collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (clue, group, edge) in &clues {
+                changed |= c.apply(group, *edge, |colours| {
+                    colours[1..].iter().filter(|&&x| x != colours[0]).count() == *clue
+                })?;
+            }
+            for (group, edge) in &vertices {
+                changed |= c.apply(group, *edge, |colours| {
+                    (0..4)
+                        .filter(|&k| colours[k] != colours[(k + 1) % 4])
+                        .count()
+                        != 4
+                })?;
+            }
+        }
+        Ok(c)
+    }
+
+    fn cell(&self, puzzle: &Puzzle, i: usize, j: usize) -> usize {
+        if i < puzzle.xsize && j < puzzle.ysize {
+            i * puzzle.ysize + j
+        } else {
+            self.outside()
+        }
+    }
+
+    fn outside(&self) -> usize {
+        self.colours.parent.len() - 1
+    }
+
+    /// The colour of cell `(i, j)`.
+    pub fn colour(&mut self, puzzle: &Puzzle, i: usize, j: usize) -> Colour {
+        let (cell, outside) = (self.cell(puzzle, i, j), self.outside());
+        match self.colours.relation(cell, outside) {
+            Some(true) => Colour::Inside,
+            Some(false) => Colour::Outside,
+            None => Colour::Unknown,
+        }
+    }
+
+    /// The value of edge `e` the colours settle, if any.
+    pub fn edge(&mut self, e: usize) -> Option<bool> {
+        let (a, b) = self.edge_cells[e];
+        self.colours.relation(a, b)
+    }
+
+    /// Try every colouring of `group` that `valid` accepts and that fits the known ties, and
+    /// tie every pair the colourings agree on. `Ok(true)` when some tie is news. A conflict
+    /// blames `edge`.
+    fn apply<const N: usize>(
+        &mut self,
+        group: &[usize; N],
+        edge: usize,
+        valid: impl Fn(&[bool; N]) -> bool,
+    ) -> Result<bool, Conflict> {
+        let mut known = [[None; N]; N];
+        for a in 0..N {
+            for b in a + 1..N {
+                known[a][b] = self.colours.relation(group[a], group[b]);
+            }
+        }
+        // Colourings relative to the first cell, as bit masks.
+        let mut agree: [[Option<bool>; N]; N] = [[None; N]; N];
+        let mut any = false;
+        for mask in (0..1u32 << N).step_by(2) {
+            let colours: [bool; N] = std::array::from_fn(|k| mask & (1 << k) != 0);
+            let fits = (0..N).all(|a| {
+                (a + 1..N).all(|b| known[a][b].is_none_or(|d| d == (colours[a] != colours[b])))
+            });
+            if !fits || !valid(&colours) {
+                continue;
+            }
+            for a in 0..N {
+                for b in a + 1..N {
+                    let differ = colours[a] != colours[b];
+                    agree[a][b] = match (any, agree[a][b]) {
+                        (false, _) => Some(differ),
+                        (true, Some(d)) if d == differ => Some(d),
+                        _ => None,
+                    };
+                }
+            }
+            any = true;
+        }
+        if !any {
+            return Err(Conflict { edge });
+        }
+        let mut news = false;
+        for a in 0..N {
+            for b in a + 1..N {
+                if let (None, Some(differ)) = (known[a][b], agree[a][b]) {
+                    news |= self.colours.tie(group[a], group[b], differ).ok_or(Conflict { edge })?;
+                }
+            }
+        }
+        Ok(news)
+    }
+}
+
+/// The two cells on either side of each edge, with everything beyond the border as the last
+/// cell.
+pub(crate) fn edge_cells(p: &Puzzle) -> Vec<(usize, usize)> {
+    let outside = p.xsize * p.ysize;
+    let cell = |i: usize, j: usize| {
+        if i < p.xsize && j < p.ysize {
+            i * p.ysize + j
+        } else {
+            outside
+        }
+    };
+    (0..p.edge_count())
+        .map(|e| {
+            let ((i0, j0), (i1, _)) = p.edge_endpoints(e);
+            match (i0 == i1, i0.checked_sub(1), j0.checked_sub(1)) {
+                (true, Some(above), _) => (cell(above, j0), cell(i0, j0)),
+                (false, _, Some(left)) => (cell(i0, left), cell(i0, j0)),
+                _ => (outside, cell(i0, j0)),
+            }
+        })
+        .collect()
+}
+
+/// The colour of every cell, row by row, as far as `edges` and the rules tell.
+pub fn cell_colours(puzzle: &Puzzle, edges: &[Edge]) -> Result<Vec<Vec<Colour>>, Conflict> {
+    let mut c = Colouring::deduce(puzzle, edges)?;
+    Ok((0..puzzle.xsize)
+        .map(|i| (0..puzzle.ysize).map(|j| c.colour(puzzle, i, j)).collect())
+        .collect())
+}
+
+/// Unknown edges of `edges` the colouring settles, with their values.
+pub fn colouring_edges(puzzle: &Puzzle, edges: &[Edge]) -> Result<Vec<(usize, bool)>, Conflict> {
+    let mut c = Colouring::deduce(puzzle, edges)?;
+    Ok((0..edges.len())
+        .filter(|&e| edges[e] == Edge::Unknown)
+        .filter_map(|e| c.edge(e).map(|filled| (e, filled)))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn border_three_is_inside() {
+        // A 3 in the corner: at least one of its border edges is filled, so it's inside.
+        let p = Puzzle::from(&[[3, -1], [-1, -1]]);
+        let colours = cell_colours(&p, &vec![Edge::Unknown; p.edge_count()]).unwrap();
+        assert_eq!(colours[0][0], Colour::Inside);
+        assert_eq!(colours[1][1], Colour::Unknown);
+    }
+
+    #[test]
+    fn zero_spreads_its_colour() {
+        let p = Puzzle::from(&[[-1, -1, -1], [-1, 0, -1], [-1, -1, -1]]);
+        let mut c = Colouring::deduce(&p, &vec![Edge::Unknown; p.edge_count()]).unwrap();
+        let (top, bottom, left, right) = p.edges_around_cell(1, 1);
+        for e in [top, bottom, left, right] {
+            assert_eq!(c.edge(e), Some(false));
+        }
+        let (above, below) = (c.cell(&p, 0, 1), c.cell(&p, 2, 1));
+        assert_eq!(c.colours.relation(above, below), Some(false));
+    }
+
+    #[test]
+    fn no_checkerboard_around_a_vertex() {
+        let p = Puzzle::from(&[[-1; 2]; 2]);
+        let mut edges = vec![Edge::Unknown; p.edge_count()];
+        // Top-right differs from top-left and bottom-right, so those two match.
+        edges[p.edge_ix(0, 1, false)] = Edge::Filled;
+        edges[p.edge_ix(1, 1, true)] = Edge::Filled;
+        // Bottom-left has to match them too, or the centre vertex would have four edges.
+        let facts = colouring_edges(&p, &edges).unwrap();
+        assert!(facts.contains(&(p.edge_ix(1, 0, true), false)), "{facts:?}");
+        assert!(
+            facts.contains(&(p.edge_ix(1, 1, false), false)),
+            "{facts:?}"
+        );
+    }
+
+    #[test]
+    fn reports_conflicts() {
+        let p = Puzzle::from(&[[0, -1], [-1, -1]]);
+        let mut edges = vec![Edge::Unknown; p.edge_count()];
+        edges[p.edge_ix(0, 0, true)] = Edge::Filled;
+        let conflict = Conflict {
+            edge: p.edge_ix(0, 0, true),
+        };
+        assert_eq!(Colouring::deduce(&p, &edges).err(), Some(conflict));
+        assert_eq!(colouring_edges(&p, &edges), Err(conflict));
+    }
+
+    #[test]
+    fn colours_follow_edges() {
+        let mut colours = Colours::new(4);
+        assert_eq!(colours.tie(0, 1, true), Some(true));
+        assert_eq!(colours.tie(1, 2, false), Some(true));
+        assert_eq!(colours.tie(0, 2, true), Some(false));
+        assert_eq!(colours.relation(0, 2), Some(true));
+        assert_eq!(colours.relation(0, 3), None);
+        assert_eq!(colours.tie(2, 0, false), None);
+    }
+}
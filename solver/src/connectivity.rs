@@ -0,0 +1,205 @@
+//! Single-loop connectivity encoded directly in the formula.
+//!
+//! Every grid vertex gets a binary rank. Exactly one vertex on the loop is the root, and
+//! every other vertex on the loop needs a parent: a neighbour along a filled edge with a
+//! strictly smaller rank. Following parents from any vertex on the loop always ends in the
+//! root, so all filled edges are connected. Together with the "0 or 2 edges per vertex"
+//! clauses that leaves exactly one loop, and the first model the SAT solver returns is final.
+use std::collections::HashMap;
+
+use crate::adapter::{SlitherlinkerFormula, SlitherlinkerLit};
+use crate::data::puzzle::Puzzle;
+
+/// Hands out variable indices past the edge variables, which own `0..edge_count`.
+pub struct VarAlloc {
+    next: usize,
+}
+
+impl VarAlloc {
+    pub fn new(puzzle: &Puzzle) -> VarAlloc {
+        VarAlloc {
+            next: puzzle.edge_count(),
+        }
+    }
+
+    pub fn fresh<T: SlitherlinkerLit>(&mut self, formula: &impl SlitherlinkerFormula<T>) -> T {
+        self.next += 1;
+        formula.pure_lit(self.next - 1)
+    }
+
+    /// Number of variables handed out so far, edge variables included.
+    pub fn count(&self) -> usize {
+        self.next
+    }
+}
+
+/// `(edge, neighbour vertex)` for every edge touching vertex `(i, j)`, in the same order as
+/// [`Puzzle::edges_around_point`].
+fn vertex_neighbours(p: &Puzzle, i: usize, j: usize) -> Vec<(usize, (usize, usize))> {
+    let mut res = vec![];
+    if i > 0 {
+        res.push((p.edge_ix(i - 1, j, false), (i - 1, j)));
+    }
+    if j > 0 {
+        res.push((p.edge_ix(i, j - 1, true), (i, j - 1)));
+    }
+    if i < p.xsize {
+        res.push((p.edge_ix(i, j, false), (i + 1, j)));
+    }
+    if j < p.ysize {
+        res.push((p.edge_ix(i, j, true), (i, j + 1)));
+    }
+    res
+}
+
+/// Append clauses that only admit a single connected loop. Edges already known to be empty
+/// in `facts` are skipped.
+pub fn connectivity_clauses<T: SlitherlinkerLit + Clone>(
+    p: &Puzzle,
+    facts: &HashMap<usize, bool>,
+    formula: &mut impl SlitherlinkerFormula<T>,
+    vars: &mut VarAlloc,
+) {
+    let cols = p.ysize + 1;
+    let vertex_count = (p.xsize + 1) * cols;
+    let bits = (usize::BITS - (vertex_count - 1).leading_zeros()).max(1) as usize;
+    let vertex = |(i, j): (usize, usize)| i * cols + j;
+
+    let ranks: Vec<Vec<T>> = (0..vertex_count)
+        .map(|_| (0..bits).map(|_| vars.fresh(formula)).collect())
+        .collect();
+
+    // The root is the first vertex on the loop in index order. `before[v]` is true when
+    // some vertex before v is on the loop.
+    let mut roots: Vec<T> = Vec::with_capacity(vertex_count);
+    let mut before: Option<T> = None;
+    for v in 0..vertex_count {
+        let (i, j) = (v / cols, v % cols);
+        let edges: Vec<T> = p
+            .edges_around_point(i, j)
+            .into_iter()
+            .map(|e| formula.pure_lit(e))
+            .collect();
+        let root = vars.fresh(formula);
+        // root -> on loop
+        let mut on_loop = vec![root.invert()];
+        on_loop.extend(edges.iter().cloned());
+        formula.append_clause(on_loop);
+        // root -> nothing before; on loop && nothing before -> root
+        if let Some(b) = &before {
+            formula.append_clause(vec![root.invert(), b.invert()]);
+        }
+        for e in &edges {
+            let mut clause = vec![e.invert(), root.clone()];
+            clause.extend(before.iter().cloned());
+            formula.append_clause(clause);
+        }
+        // Carry "seen a vertex on the loop" forward.
+        let next = vars.fresh(formula);
+        for e in &edges {
+            formula.append_clause(vec![e.invert(), next.clone()]);
+        }
+        if let Some(b) = &before {
+            formula.append_clause(vec![b.invert(), next.clone()]);
+        }
+        before = Some(next);
+        roots.push(root);
+    }
+
+    for i in 0..=p.xsize {
+        for j in 0..=p.ysize {
+            let v = vertex((i, j));
+            let mut parents = vec![];
+            for (e, u) in vertex_neighbours(p, i, j) {
+                if facts.get(&e) == Some(&false) {
+                    continue;
+                }
+                // parent -> edge is filled && rank(u) < rank(v)
+                let parent = less_than(&ranks[vertex(u)], &ranks[v], formula, vars);
+                let edge = formula.pure_lit(e);
+                formula.append_clause(vec![parent.invert(), edge]);
+                parents.push(parent);
+            }
+
+            // A vertex on the loop is either the root or has a parent.
+            for e in p.edges_around_point(i, j) {
+                let mut clause = vec![formula.pure_lit(e).invert(), roots[v].clone()];
+                clause.extend(parents.iter().cloned());
+                formula.append_clause(clause);
+            }
+        }
+    }
+}
+
+/// A fresh variable implying `a < b`, comparing the bits (least significant first) as
+/// unsigned numbers.
+fn less_than<T: SlitherlinkerLit + Clone>(
+    a: &[T],
+    b: &[T],
+    formula: &mut impl SlitherlinkerFormula<T>,
+    vars: &mut VarAlloc,
+) -> T {
+    // lt_k -> a[0..=k] < b[0..=k]
+    let mut lt = vars.fresh(formula);
+    formula.append_clause(vec![lt.invert(), a[0].invert()]);
+    formula.append_clause(vec![lt.invert(), b[0].clone()]);
+    for k in 1..a.len() {
+        let next = vars.fresh(formula);
+        formula.append_clause(vec![next.invert(), a[k].invert(), b[k].clone()]);
+        formula.append_clause(vec![next.invert(), a[k].invert(), lt.clone()]);
+        formula.append_clause(vec![next.invert(), b[k].clone(), lt.clone()]);
+        lt = next;
+    }
+    lt
+}
+
+#[cfg(all(test, feature = "varisat"))]
+mod test {
+    use varisat::{CnfFormula, ExtendFormula, Solver};
+
+    use super::*;
+    use crate::data::pattern::Edge;
+    use crate::solve_common::{cell_clauses, edge_clauses, find_loops_edges};
+
+    /// Enumerate every model: each one must already be a single loop.
+    #[test]
+    fn every_model_is_single_loop() {
+        let p = Puzzle::from(&[[3, -1, 3], [-1, -1, -1], [-1, -1, -1]]);
+        let facts = HashMap::new();
+        let mut formula = CnfFormula::new();
+        cell_clauses(&p, &facts, &mut formula, "");
+        edge_clauses(&p, &facts, &mut formula, "");
+        connectivity_clauses(&p, &facts, &mut formula, &mut VarAlloc::new(&p));
+
+        let mut s = Solver::default();
+        s.add_formula(&formula);
+        let mut models = 0;
+        while s.solve().unwrap() {
+            let model = s.model().unwrap();
+            let edges: Vec<Edge> = model[..p.edge_count()].iter().map(|l| l.to_edge()).collect();
+            assert_eq!(find_loops_edges(&p, &edges).len(), 1);
+            let block: Vec<_> = model[..p.edge_count()].iter().map(|l| !*l).collect();
+            s.add_clause(&block);
+            models += 1;
+        }
+        assert!(models > 0);
+    }
+
+    #[test]
+    fn two_forced_loops_are_unsat() {
+        let p = Puzzle::from(&[[-1; 3]; 3]);
+        let (a, b, c, d) = p.edges_around_cell(0, 0);
+        let (e, f, g, h) = p.edges_around_cell(2, 2);
+        let facts = HashMap::new();
+        let mut formula = CnfFormula::new();
+        for ix in [a, b, c, d, e, f, g, h] {
+            formula.add_clause(&[formula.pure_lit(ix)]);
+        }
+        edge_clauses(&p, &facts, &mut formula, "");
+        connectivity_clauses(&p, &facts, &mut formula, &mut VarAlloc::new(&p));
+
+        let mut s = Solver::default();
+        s.add_formula(&formula);
+        assert!(!s.solve().unwrap());
+    }
+}
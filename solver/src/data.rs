@@ -1,4 +1,5 @@
 pub mod puzzle;
 pub mod pattern;
+pub mod pattern_file;
 pub mod baked_in_patterns;
 pub mod solution;
\ No newline at end of file
@@ -1,8 +1,13 @@
 use crate::data::pattern::PatternSolution;
 use std::collections::BTreeMap;
 
-fn push(r: &mut BTreeMap<String, PatternSolution>, prefix: &str, pattern: &PatternSolution) {
-    pattern.rotations().iter().enumerate().for_each(|(i, &p)| {
+/// Add `pattern` under `<prefix>-<n>` for each of its rotations `n`.
+pub(crate) fn push(
+    r: &mut BTreeMap<String, PatternSolution>,
+    prefix: &str,
+    pattern: &PatternSolution,
+) {
+    pattern.rotations().into_iter().enumerate().for_each(|(i, p)| {
         r.insert(format!("{prefix}-{i}"), p);
     });
 }
@@ -10,7 +15,8 @@ fn push(r: &mut BTreeMap<String, PatternSolution>, prefix: &str, pattern: &Patte
 pub fn patterns() -> BTreeMap<String, PatternSolution> {
     let mut r = BTreeMap::new();
 
-    // Each pattern is two strings (input, output), each with 5 non-empty lines:
+    // Each pattern is two strings (input, output) of the same size. A 3x3 window of cells
+    // has 5 non-empty lines, and other sizes have more or fewer of them, longer or shorter:
     //   cell-row:  c v c v c   (5 chars: cells at 0,2,4; vertical edges at 1,3)
     //   horiz-row: h . h . h   (5 chars: horizontal edges at 0,2,4; filler '.' at 1,3)
     //   cell-row
@@ -661,6 +667,6 @@ mod test {
     #[test]
     fn test_patterns_from_str_matches_patterns() {
         let actual = patterns();
-        assert!(actual.is_empty() == false);
+        assert!(!actual.is_empty());
     }
 }
@@ -22,13 +22,13 @@ pub enum Edge {
 }
 
 impl Cell {
-    fn matches(&self, other: &Self) -> bool {
+    pub(crate) fn matches(&self, other: &Self) -> bool {
         *self == Cell::Any || *other == Cell::Any || *self == *other
     }
 }
 
 impl Edge {
-    fn matches(&self, other: &Self) -> bool {
+    pub(crate) fn matches(&self, other: &Self) -> bool {
         *self == Edge::Any
             || *other == Edge::Any
             || *self == *other
@@ -39,17 +39,20 @@ impl Edge {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// The edges between the cells of a pattern with `rows` by `cols` cells.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Pattern {
     pub horizontals: Horizontals,
     pub verticals: Verticals,
 }
 
-pub type Horizontals = [[Edge; 3]; 2];
-pub type Verticals = [[Edge; 2]; 3];
-pub type CellWindow = [[Cell; 3]; 3];
+/// `rows - 1` rows of `cols` edges, each below the cell of the same row and column.
+pub type Horizontals = Vec<Vec<Edge>>;
+/// `rows` rows of `cols - 1` edges, each right of the cell of the same row and column.
+pub type Verticals = Vec<Vec<Edge>>;
+pub type CellWindow = Vec<Vec<Cell>>;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct PatternSolution {
     pub cells: CellWindow,
     pub input: Pattern,
@@ -92,7 +95,7 @@ impl fmt::Display for PatternSolution {
             res.push_str("*\n*");
 
             // horizontals
-            if i < 2 {
+            if i + 1 < self.cells.len() {
                 for j in 0..self.input.horizontals[i].len() {
                     res.push_str(match self.input.horizontals[i][j] {
                         Edge::Filled => ".-",
@@ -139,7 +142,7 @@ impl fmt::Display for PatternSolution {
             res.push_str("*\n");
 
             // horizontals
-            if i < 2 {
+            if i + 1 < self.cells.len() {
                 res.push('*');
                 for j in 0..self.output.horizontals[i].len() {
                     res.push_str(match self.output.horizontals[i][j] {
@@ -158,47 +161,38 @@ impl fmt::Display for PatternSolution {
     }
 }
 
-pub fn rot90<T: Copy, const W: usize, const H: usize>(v: &[[T; W]; H]) -> [[T; H]; W] {
-    let mut r = [[v[0][0]; H]; W];
-    for i in 0..v.len() {
-        for j in 0..v[0].len() {
-            let e = v[i][j];
-            r[j][r[0].len() - i - 1] = e;
-        }
-    }
-    r
+pub fn rot90<T: Copy, R: AsRef<[T]>>(v: &[R]) -> Vec<Vec<T>> {
+    let width = v.first().map_or(0, |row| row.as_ref().len());
+    (0..width)
+        .map(|j| v.iter().rev().map(|row| row.as_ref()[j]).collect())
+        .collect()
 }
 
-fn mirror<T: Copy, const W: usize, const H: usize>(v: &[[T; W]; H]) -> [[T; W]; H] {
-    let mut r = *v;
-    for i in 0..v.len() {
-        for j in 0..v[0].len() {
-            let e = v[i][j];
-            r[i][r[0].len() - j - 1] = e;
-        }
-    }
-    r
+fn mirror<T: Copy, R: AsRef<[T]>>(v: &[R]) -> Vec<Vec<T>> {
+    v.iter()
+        .map(|row| row.as_ref().iter().rev().copied().collect())
+        .collect()
 }
 
 impl Pattern {
     //clokwise rotate
     pub fn rot90(&self) -> Pattern {
-        let mut n = *self;
-        n.verticals = rot90(&self.horizontals);
-        n.horizontals = rot90(&self.verticals);
-        n
+        Pattern {
+            verticals: rot90(&self.horizontals),
+            horizontals: rot90(&self.verticals),
+        }
     }
 
     fn mirror(&self) -> Pattern {
-        let mut n = *self;
-        n.verticals = mirror(&self.verticals);
-        n.horizontals = mirror(&self.horizontals);
-        n
+        Pattern {
+            verticals: mirror(&self.verticals),
+            horizontals: mirror(&self.horizontals),
+        }
     }
 }
 
-fn parse_cell(c: char) -> Cell {
-    match c {
+fn parse_cell(c: char) -> Option<Cell> {
+    Some(match c {
         '*' => Cell::Any,
         '0' => Cell::Zero,
         '1' => Cell::One,
@@ -206,68 +200,107 @@ fn parse_cell(c: char) -> Cell {
         '3' => Cell::Three,
         'B' => Cell::OutOfBounds,
         '.' => Cell::Nothing,
-        _ => panic!("Unknown cell char: {c:?}"),
-    }
+        _ => return None,
+    })
 }
 
-fn parse_vert(c: char) -> Edge {
-    match c {
+fn parse_vert(c: char) -> Option<Edge> {
+    Some(match c {
         '*' => Edge::Any,
         '|' => Edge::Filled,
         'x' => Edge::Empty,
         'X' => Edge::EmptyStrict,
         '%' => Edge::OutOfBounds,
         '?' => Edge::Unknown,
-        _ => panic!("Unknown vertical edge char: {c:?}"),
-    }
+        _ => return None,
+    })
 }
 
-fn parse_horiz(c: char) -> Edge {
-    match c {
+fn parse_horiz(c: char) -> Option<Edge> {
+    Some(match c {
         '*' => Edge::Any,
         '-' => Edge::Filled,
         'x' => Edge::Empty,
         'X' => Edge::EmptyStrict,
         '%' => Edge::OutOfBounds,
         '?' => Edge::Unknown,
-        _ => panic!("Unknown horizontal edge char: {c:?}"),
-    }
+        _ => return None,
+    })
 }
 
-fn parse_cell_row(line: &str) -> ([Cell; 3], [Edge; 2]) {
+/// Where a pattern string went wrong: the row, counting non-empty lines from 0, the
+/// character in that row, and what is wrong.
+pub type RowError = (usize, usize, String);
+
+/// Parse one row, a cell row when `cells` is set and a horizontal edge row otherwise, into
+/// its `width` characters.
+fn parse_row(row: usize, line: &str, cells: bool, width: usize) -> Result<Vec<char>, RowError> {
     let chars: Vec<char> = line.chars().collect();
-    assert_eq!(chars.len(), 5, "Cell row must be 5 chars, got {:?}", line);
-    (
-        [
-            parse_cell(chars[0]),
-            parse_cell(chars[2]),
-            parse_cell(chars[4]),
-        ],
-        [parse_vert(chars[1]), parse_vert(chars[3])],
-    )
+    if chars.len() != width {
+        let kind = if cells { "Cell" } else { "Horizontal edge" };
+        let message = format!("{kind} row must be {width} chars, got {line:?}");
+        return Err((row, chars.len().min(width), message));
+    }
+    for (col, &c) in chars.iter().enumerate() {
+        let (ok, kind) = match (cells, col % 2) {
+            (true, 0) => (parse_cell(c).is_some(), "cell"),
+            (true, _) => (parse_vert(c).is_some(), "vertical edge"),
+            (false, 0) => (parse_horiz(c).is_some(), "horizontal edge"),
+            // Separators between horizontal edges can be anything.
+            (false, _) => (true, ""),
+        };
+        if !ok {
+            return Err((row, col, format!("Unknown {kind} char: {c:?}")));
+        }
+    }
+    Ok(chars)
 }
 
-fn parse_horiz_row(line: &str) -> [Edge; 3] {
-    let chars: Vec<char> = line.chars().collect();
-    assert_eq!(
-        chars.len(),
-        5,
-        "Horizontal edge row must be 5 chars, got {:?}",
-        line
-    );
-    [
-        parse_horiz(chars[0]),
-        parse_horiz(chars[2]),
-        parse_horiz(chars[4]),
-    ]
+/// Parse the non-empty lines of a pattern string, see [`parse_str`].
+pub fn parse_rows(lines: &[&str]) -> Result<(CellWindow, Pattern), RowError> {
+    if lines.len().is_multiple_of(2) {
+        let message = format!(
+            "Pattern string must have an odd number of non-empty lines, got {}",
+            lines.len()
+        );
+        return Err((lines.len().saturating_sub(1), 0, message));
+    }
+    let width = lines[0].chars().count();
+    if width.is_multiple_of(2) {
+        let message = format!("Pattern rows must have an odd number of chars, got {width}");
+        return Err((0, width, message));
+    }
+    let (rows, cols) = (lines.len() / 2 + 1, width / 2 + 1);
+    let mut cells = vec![vec![Cell::Any; cols]; rows];
+    let mut pattern = Pattern {
+        horizontals: vec![vec![Edge::Any; cols]; rows - 1],
+        verticals: vec![vec![Edge::Any; cols - 1]; rows],
+    };
+    for (row, line) in lines.iter().enumerate() {
+        let chars = parse_row(row, line, row % 2 == 0, width)?;
+        if row % 2 == 0 {
+            for j in 0..cols {
+                cells[row / 2][j] = parse_cell(chars[2 * j]).unwrap();
+            }
+            for j in 0..cols - 1 {
+                pattern.verticals[row / 2][j] = parse_vert(chars[2 * j + 1]).unwrap();
+            }
+        } else {
+            for j in 0..cols {
+                pattern.horizontals[row / 2][j] = parse_horiz(chars[2 * j]).unwrap();
+            }
+        }
+    }
+    Ok((cells, pattern))
 }
 
 /// Parse a compact pattern string into a `(CellWindow, Pattern)`.
 ///
-/// The string must have exactly 5 non-empty lines:
+/// A pattern of `rows` by `cols` cells has `2 * rows - 1` non-empty lines of
+/// `2 * cols - 1` chars each, cell rows and horizontal edge rows in turn. For 3 by 3 cells:
 /// ```text
-/// c v c v c   <- cell row (5 chars: cell, vert-edge, cell, vert-edge, cell)
-/// h . h . h   <- horizontal edge row (5 chars; positions 1,3 are ignored separators)
+/// c v c v c   <- cell row (cell, vert-edge, cell, vert-edge, cell)
+/// h . h . h   <- horizontal edge row (positions 1,3 are ignored separators)
 /// c v c v c
 /// h . h . h
 /// c v c v c   <- bottom cell row (no edge row below)
@@ -275,46 +308,44 @@ fn parse_horiz_row(line: &str) -> [Edge; 3] {
 ///
 /// Cell characters: `*`=Any, `0-3`=value, `B`=OutOfBounds, `.`=Nothing
 /// Vertical edge chars (odd positions in cell rows): `*`=Any, `|`=Filled, `x`=Empty, `X`=EmptyStrict, `%`=OutOfBounds, `?`=Unknown
-/// Horizontal edge chars (positions 0,2,4 of horiz rows): `*`=Any, `-`=Filled, `x`=Empty, `X`=EmptyStrict, `%`=OutOfBounds, `?`=Unknown
+/// Horizontal edge chars (even positions of horiz rows): `*`=Any, `-`=Filled, `x`=Empty, `X`=EmptyStrict, `%`=OutOfBounds, `?`=Unknown
 fn parse_str(s: &str) -> (CellWindow, Pattern) {
     let lines: Vec<&str> = s.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
-    assert_eq!(
-        lines.len(),
-        5,
-        "Pattern string must have 5 non-empty lines, got {}",
-        lines.len()
-    );
-    let (c0, v0) = parse_cell_row(lines[0]);
-    let h0 = parse_horiz_row(lines[1]);
-    let (c1, v1) = parse_cell_row(lines[2]);
-    let h1 = parse_horiz_row(lines[3]);
-    let (c2, v2) = parse_cell_row(lines[4]);
-    (
-        [
-            [c0[0], c0[1], c0[2]],
-            [c1[0], c1[1], c1[2]],
-            [c2[0], c2[1], c2[2]],
-        ],
-        Pattern {
-            horizontals: [h0, h1],
-            verticals: [[v0[0], v0[1]], [v1[0], v1[1]], [v2[0], v2[1]]],
-        },
-    )
+    parse_rows(&lines).unwrap_or_else(|(_, _, message)| panic!("{message}"))
 }
 
 impl PatternSolution {
     /// Parse a `PatternSolution` from two compact string representations.
     /// See [`parse_str`] for the string format.
     /// The `output` string's cell characters are ignored; cells are taken from `input`.
+    /// Both strings must be the same size.
     #[allow(dead_code)]
     pub fn parse(input: &str, output: &str) -> PatternSolution {
         let (cells, input_pattern) = parse_str(input);
-        let (_, output_pattern) = parse_str(output);
-        PatternSolution {
+        let (output_cells, output_pattern) = parse_str(output);
+        let res = PatternSolution {
             cells,
             input: input_pattern,
             output: output_pattern,
-        }
+        };
+        let output_size = (output_cells.len(), output_cells[0].len());
+        assert_eq!(res.size(), output_size, "Input and output differ in size");
+        res
+    }
+
+    /// Rows and columns of cells the pattern covers.
+    pub fn size(&self) -> (usize, usize) {
+        (self.cells.len(), self.cells[0].len())
+    }
+
+    /// How many cells and input edges the pattern pins down. Patterns with fewer are easier
+    /// to spot.
+    pub fn constraints(&self) -> usize {
+        let cells = self.cells.iter().flatten().filter(|&&c| c != Cell::Any);
+        let horizontals = self.input.horizontals.iter().flatten();
+        let verticals = self.input.verticals.iter().flatten();
+        let edges = horizontals.chain(verticals).filter(|&&e| e != Edge::Any);
+        cells.count() + edges.count()
     }
 
     fn rot90(&self) -> PatternSolution {
@@ -334,7 +365,7 @@ impl PatternSolution {
     }
     pub fn rotations(&self) -> Vec<PatternSolution> {
         let mut res: Vec<PatternSolution> = vec![];
-        res.push(*self);
+        res.push(self.clone());
         for _ in 0..3 {
             res.push(res.last().unwrap().rot90());
         }
@@ -364,7 +395,7 @@ impl PatternSolution {
         }
 
         let h: HashSet<PatternSolution> = HashSet::from_iter(res);
-        let mut r: Vec<PatternSolution> = h.iter().copied().collect();
+        let mut r: Vec<PatternSolution> = h.into_iter().collect();
 
         r.sort();
         // let r = res.iter().map(|&x| x).collect();
@@ -382,23 +413,27 @@ impl PatternSolution {
         // vec![self.clone(), r1, r2, r3, self_refl, r1_refl, r2_refl, r3_refl]
     }
 
+    /// The window must be the size of the pattern.
     pub fn try_match(
         &self,
-        cells: &CellWindow,
-        horizontals: &Horizontals,
-        verticals: &Verticals,
+        cells: &[Vec<Cell>],
+        horizontals: &[Vec<Edge>],
+        verticals: &[Vec<Edge>],
+    ) -> bool {
+        self.matches_input(cells, horizontals, verticals)
+            && self.outputs_agree(horizontals, verticals)
+    }
+
+    /// The clues and input edges match, whatever the edges the pattern sets already are.
+    pub fn matches_input(
+        &self,
+        cells: &[Vec<Cell>],
+        horizontals: &[Vec<Edge>],
+        verticals: &[Vec<Edge>],
     ) -> bool {
         for (i, h_row) in horizontals.iter().enumerate() {
             for (j, &h_ij) in h_row.iter().enumerate() {
-                let input_edge = self.input.horizontals[i][j];
-                if !input_edge.matches(&h_ij) {
-                    return false;
-                }
-                let output_edge = self.output.horizontals[i][j];
-                if (output_edge == Edge::Empty || output_edge == Edge::Filled)
-                    && (h_ij == Edge::Empty || h_ij == Edge::Filled)
-                    && !output_edge.matches(&h_ij)
-                {
+                if !self.input.horizontals[i][j].matches(&h_ij) {
                     return false;
                 }
             }
@@ -408,24 +443,35 @@ impl PatternSolution {
                 if !self.input.verticals[i][j].matches(&v_ij) {
                     return false;
                 }
-                let output_edge = self.output.verticals[i][j];
-                if (output_edge == Edge::Empty || output_edge == Edge::Filled)
-                    && (v_ij == Edge::Empty || v_ij == Edge::Filled)
-                    && !output_edge.matches(&v_ij)
-                {
-                    return false;
-                }
             }
         }
+        self.cells_match(cells)
+    }
 
-        for (i, c_row) in cells.iter().enumerate() {
-            for (j, &c_ij) in c_row.iter().enumerate() {
-                if !self.cells[i][j].matches(&c_ij) {
-                    return false;
-                }
-            }
-        }
-        true
+    /// No edge the pattern sets is already known to have the other value.
+    fn outputs_agree(&self, horizontals: &[Vec<Edge>], verticals: &[Vec<Edge>]) -> bool {
+        let agrees = |output: Edge, edge: Edge| {
+            !(output == Edge::Empty || output == Edge::Filled)
+                || !(edge == Edge::Empty || edge == Edge::Filled)
+                || output.matches(&edge)
+        };
+        let outputs = &self.output;
+        let pairs = |outputs: &[Vec<Edge>], edges: &[Vec<Edge>]| {
+            outputs
+                .iter()
+                .flatten()
+                .zip(edges.iter().flatten())
+                .all(|(&output, &edge)| agrees(output, edge))
+        };
+        pairs(&outputs.horizontals, horizontals) && pairs(&outputs.verticals, verticals)
+    }
+
+    /// The clue part of [`PatternSolution::try_match`].
+    pub fn cells_match(&self, cells: &[Vec<Cell>]) -> bool {
+        cells
+            .iter()
+            .zip(&self.cells)
+            .all(|(row, pattern_row)| row.iter().zip(pattern_row).all(|(c, p)| p.matches(c)))
     }
 }
 
@@ -502,6 +548,25 @@ pub mod test {
         assert_eq!(p_rot, expected);
     }
 
+    #[test]
+    fn test_rotate_wide_pattern() {
+        let (cells, p) = parse_str("
+            3|.*.
+            -*x**
+            .*.*1
+        ");
+        let (expected_cells, expected) = parse_str("
+            .|3
+            **-
+            .x.
+            ***
+            1*.
+        ");
+        assert_eq!(rot90(&cells), expected_cells);
+        assert_eq!(p.rot90(), expected);
+        assert_eq!(p.rot90().rot90().rot90().rot90(), p);
+    }
+
     #[test]
     fn test_reflect_pattern() {
         let (_, p) = parse_str("
@@ -0,0 +1,243 @@
+//! Pattern libraries in text files, in the notation of [`PatternSolution::parse`].
+//!
+//! ```text
+//! # Two 3s touching at a corner.
+//! pattern two threes diagonally
+//! 3****  *****
+//! *.*.*  *.*.*
+//! **3**  ***|*
+//! *.*.*  *.-.*
+//! *****  *****
+//! ```
+//!
+//! Each pattern is a `pattern <name>` line followed by its rows, the input on the left and
+//! the output on the right, separated by whitespace. The rows run up to the next blank line,
+//! comment, `pattern` line or the end of the file, five of them for a window of 3x3 cells,
+//! seven for 4x4 and so on. As with
+//! [`PatternSolution::parse`], the cells of the output are ignored. Blank lines and lines
+//! starting with `#` are skipped between patterns.
+use std::error::Error;
+use std::fmt;
+
+use crate::data::pattern::{parse_rows, Cell, CellWindow, Edge, Pattern, PatternSolution};
+
+/// A mistake in a pattern file. Lines and columns count from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternFileError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PatternFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for PatternFileError {}
+
+/// Every pattern of a pattern file, with its name, in file order.
+pub fn parse_patterns(text: &str) -> Result<Vec<(String, PatternSolution)>, PatternFileError> {
+    let error = |line: usize, column: usize, message: String| PatternFileError {
+        line: line + 1,
+        column: column + 1,
+        message,
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    let mut res: Vec<(String, PatternSolution)> = vec![];
+    let mut n = 0;
+    while n < lines.len() {
+        let line = lines[n].trim();
+        if line.is_empty() || line.starts_with('#') {
+            n += 1;
+            continue;
+        }
+        let indent = lines[n].len() - lines[n].trim_start().len();
+        let Some(name) = line.strip_prefix("pattern ").map(str::trim) else {
+            let message = format!("expected `pattern <name>`, got {line:?}");
+            return Err(error(n, indent, message));
+        };
+        if name.is_empty() {
+            return Err(error(n, indent, String::from("pattern has no name")));
+        }
+        if res.iter().any(|(other, _)| other == name) {
+            return Err(error(n, indent, format!("pattern {name:?} is defined twice")));
+        }
+        let header = n;
+        n += 1;
+
+        // The rows, each as (line, column of input, input, column of output, output).
+        let mut rows = vec![];
+        while let Some(&row) = lines.get(n) {
+            let row_start = row.trim();
+            if row_start.is_empty()
+                || row_start.starts_with('#')
+                || row_start.starts_with("pattern ")
+            {
+                break;
+            }
+            let halves: Vec<(usize, &str)> = row
+                .split_whitespace()
+                .map(|half| (half.as_ptr() as usize - row.as_ptr() as usize, half))
+                .collect();
+            match halves[..] {
+                [(in_col, input), (out_col, output)] => {
+                    rows.push((n, in_col, input, out_col, output))
+                }
+                _ => {
+                    let message = format!(
+                        "expected an input row and an output row, got {:?}",
+                        row.trim()
+                    );
+                    let column = halves.first().map_or(0, |&(col, _)| col);
+                    return Err(error(n, column, message));
+                }
+            }
+            n += 1;
+        }
+        if rows.is_empty() {
+            let message = format!("pattern {name:?} has no rows");
+            return Err(error(header, indent, message));
+        }
+
+        let inputs: Vec<&str> = rows.iter().map(|r| r.2).collect();
+        let outputs: Vec<&str> = rows.iter().map(|r| r.4).collect();
+        let (cells, input) = parse_rows(&inputs)
+            .map_err(|(row, col, message)| error(rows[row].0, rows[row].1 + col, message))?;
+        let (output_cells, output) = parse_rows(&outputs)
+            .map_err(|(row, col, message)| error(rows[row].0, rows[row].3 + col, message))?;
+        if output_cells[0].len() != cells[0].len() {
+            let message = format!("output rows must be as long as input rows, {:?}", inputs[0]);
+            return Err(error(rows[0].0, rows[0].3, message));
+        }
+        let pattern = PatternSolution {
+            cells,
+            input,
+            output,
+        };
+        res.push((String::from(name), pattern));
+    }
+    Ok(res)
+}
+
+/// `pattern` in the notation of [`parse_patterns`], under `name`, ending in a newline.
+pub fn format_pattern(name: &str, pattern: &PatternSolution) -> String {
+    let cell = |c: Cell| match c {
+        Cell::Any => '*',
+        Cell::OutOfBounds => 'B',
+        Cell::Nothing => '.',
+        Cell::Zero => '0',
+        Cell::One => '1',
+        Cell::Two => '2',
+        Cell::Three => '3',
+    };
+    let edge = |e: Edge, filled: char| match e {
+        Edge::Any => '*',
+        Edge::OutOfBounds => '%',
+        Edge::Unknown => '?',
+        Edge::Empty => 'x',
+        Edge::EmptyStrict => 'X',
+        Edge::Filled => filled,
+    };
+    let (height, width) = pattern.size();
+    let rows = |cells: &CellWindow, edges: &Pattern| -> Vec<String> {
+        (0..2 * height - 1)
+            .map(|i| {
+                (0..2 * width - 1)
+                    .map(|j| match (i % 2, j % 2) {
+                        (0, 0) => cell(cells[i / 2][j / 2]),
+                        (0, _) => edge(edges.verticals[i / 2][j / 2], '|'),
+                        (_, 0) => edge(edges.horizontals[i / 2][j / 2], '-'),
+                        _ => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    };
+    let input = rows(&pattern.cells, &pattern.input);
+    let output = rows(&vec![vec![Cell::Any; width]; height], &pattern.output);
+    let mut res = format!("pattern {name}\n");
+    for (input, output) in input.iter().zip(&output) {
+        res.push_str(&format!("{input}  {output}\n"));
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const THREES: &str = "
+# Two 3s touching at a corner.
+pattern two threes diagonally
+3****  *****
+*.*.*  *.*.*
+**3**  ***|*
+*.*.*  *.-.*
+*****  *****
+
+pattern zero
+  *****  *****
+  *.*.*  *.x.*
+  **0**  *x*x*
+  *.*.*  *.x.*
+  *****  *****
+";
+
+    #[test]
+    fn reads_named_patterns() {
+        let patterns = parse_patterns(THREES).unwrap();
+        let names: Vec<&str> = patterns.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["two threes diagonally", "zero"]);
+        let expected = PatternSolution::parse(
+            "
+            3****
+            *.*.*
+            **3**
+            *.*.*
+            *****
+        ",
+            "
+            *****
+            *.*.*
+            ***|*
+            *.-.*
+            *****
+        ",
+        );
+        assert_eq!(patterns[0].1, expected);
+    }
+
+    #[test]
+    fn formats_what_it_reads() {
+        let patterns = parse_patterns(THREES).unwrap();
+        let text: String = patterns.iter().map(|(name, p)| format_pattern(name, p)).collect();
+        assert_eq!(parse_patterns(&text).unwrap(), patterns);
+        assert!(text.starts_with("pattern two threes diagonally\n3****  *****\n"), "{text}");
+    }
+
+    #[test]
+    fn points_at_the_bad_char() {
+        let text = THREES.replace("**0**  *x*x*", "**0**  *x*y*");
+        let e = parse_patterns(&text).unwrap_err();
+        assert_eq!((e.line, e.column), (13, 13));
+        assert!(e.message.contains("'y'"), "{e}");
+    }
+
+    #[test]
+    fn reports_missing_rows_and_names() {
+        let e = parse_patterns("pattern short\n\n*****  *****\n").unwrap_err();
+        assert_eq!((e.line, e.column), (1, 1));
+        let e = parse_patterns("*****  *****\n").unwrap_err();
+        assert!(e.message.contains("pattern <name>"), "{e}");
+        // A blank line ends the rows, and a pattern can't end on an edge row.
+        let e = parse_patterns("pattern a\n*****  *****\n*.*.*  *.*.*\n\n").unwrap_err();
+        assert_eq!((e.line, e.column), (3, 1));
+        let e = parse_patterns("pattern a\n***  *****\n").unwrap_err();
+        assert_eq!((e.line, e.column), (2, 6));
+        let text = "pattern a\n*****  *****\n*.*.*  *.*.*\n***  *****\n*.*.*  *.*.*\n*****  *****\n";
+        let e = parse_patterns(text).unwrap_err();
+        assert_eq!((e.line, e.column), (4, 4));
+    }
+}
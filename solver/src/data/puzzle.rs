@@ -22,6 +22,23 @@ impl Puzzle {
         }
     }
 
+    /// Build a puzzle from a row-major grid of clues, e.g. the output of
+    /// [`crate::parse::from_string`].
+    pub fn from_grid(cells: Vec<Vec<Cell>>) -> Puzzle {
+        let xsize = cells.len();
+        let ysize = cells.first().map_or(0, Vec::len);
+        Puzzle {
+            cells,
+            xsize,
+            ysize,
+        }
+    }
+
+    /// Total number of edges (horizontal, then vertical) in the puzzle.
+    pub fn edge_count(&self) -> usize {
+        (1 + self.xsize) * self.ysize + (1 + self.ysize) * self.xsize
+    }
+
     pub fn edges<const W1: usize, const H1: usize, const W2: usize, const H2: usize>(
         horizontals: &[[Edge; W1]; H1],
         verticals: &[[Edge; W2]; H2],
@@ -39,7 +56,7 @@ impl Puzzle {
     }
     pub fn edge_ix(&self, i: usize, j: usize, is_horizontal: bool) -> usize {
         if is_horizontal {
-            i * self.xsize + j
+            i * self.ysize + j
         } else {
             ((1 + self.xsize) * self.ysize) + (i * (1 + self.ysize) + j)
         }
@@ -65,7 +82,7 @@ impl Puzzle {
             ix - ((1 + self.xsize) * self.ysize)
         };
         let (i, j) = if is_horizontal {
-            (simpler_ix / self.xsize, simpler_ix % self.ysize)
+            (simpler_ix / self.ysize, simpler_ix % self.ysize)
         } else {
             (simpler_ix / (self.ysize + 1), simpler_ix % (self.ysize + 1))
         };
@@ -137,6 +154,19 @@ impl Puzzle {
         res
     }
 
+    /// The two grid vertices `(row, column)` an edge connects.
+    pub fn edge_endpoints(&self, ix: usize) -> ((usize, usize), (usize, usize)) {
+        let horizontal_count = (1 + self.xsize) * self.ysize;
+        if ix < horizontal_count {
+            let (i, j) = (ix / self.ysize, ix % self.ysize);
+            ((i, j), (i, j + 1))
+        } else {
+            let ix = ix - horizontal_count;
+            let (i, j) = (ix / (1 + self.ysize), ix % (1 + self.ysize));
+            ((i, j), (i + 1, j))
+        }
+    }
+
     pub fn edges_around_point(&self, i: usize, j: usize) -> Vec<usize> {
         let mut res = vec![];
         if i > 0 {
@@ -217,6 +247,19 @@ mod test {
         assert_eq!(e, [3, 5, 8]);
     }
 
+    #[test]
+    fn test_edge_endpoints() {
+        let p = Puzzle::from_grid(vec![vec![-1; 3]; 2]);
+        for ix in 0..p.edge_count() {
+            let ((i0, j0), (i1, j1)) = p.edge_endpoints(ix);
+            let horizontal = i0 == i1;
+            assert_eq!(p.edge_ix(i0, j0, horizontal), ix);
+            assert_eq!((i1 + j1) - (i0 + j0), 1);
+        }
+        assert_eq!(p.edge_endpoints(5), ((1, 2), (1, 3)));
+        assert_eq!(p.edge_endpoints(10), ((0, 1), (1, 1)));
+    }
+
     #[test]
     fn test_edges_around_point() {
         let p = Puzzle::from(&[[-1; 2]; 2]);
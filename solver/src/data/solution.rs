@@ -124,6 +124,7 @@ pub fn format_side_by_side(
     result
 }
 
+#[derive(Debug, Clone)]
 pub struct Solution {
     pub puzzle: Puzzle,
     pub edges: Vec<Edge>,
@@ -169,6 +170,29 @@ fn cell_corner(puzzle: &Puzzle, edges: &[Edge], i: usize, j: usize) -> char {
     }
 }
 
+/// Two edge sets of the same puzzle side-by-side, with the edges they disagree on in red.
+pub fn format_edges_diff(
+    puzzle: &Puzzle,
+    left: &[Edge],
+    right: &[Edge],
+    left_label: &str,
+    right_label: &str,
+) -> String {
+    let highlights: HashMap<usize, &'static str> = left
+        .iter()
+        .enumerate()
+        .filter(|(i, e)| right.get(*i) != Some(e))
+        .map(|(i, _)| (i, ANSI_RED))
+        .collect();
+    format_side_by_side(
+        &format_puzzle_diff(puzzle, left, &highlights),
+        &format_puzzle_diff(puzzle, right, &highlights),
+        left_label,
+        right_label,
+        2 * puzzle.ysize + 1,
+    )
+}
+
 pub fn format_puzzle(puzzle: &Puzzle, edges: &[Edge]) -> String {
     format_puzzle_diff(puzzle, edges, &HashMap::new())
 }
@@ -0,0 +1,356 @@
+//! Finds new patterns by trying small inputs on [`crate::soundness::forced`].
+//!
+//! The inputs are every way to put up to a few clues on the window's cells and a few known
+//! edges between them, on an open window, along the grid's top border, and in its top left
+//! corner. Each input is tried once per symmetry class, fewest clues and edges first, and
+//! those nearer the centre before the others. A forced edge makes it into a new pattern
+//! when no input with one clue or edge fewer forces it too, and no earlier pattern, moved
+//! anywhere in the window, already sets it from part of the input. Inputs and the earlier
+//! patterns they are compared with are all 3x3 windows; larger known patterns are left out
+//! of the comparison.
+use std::collections::{HashMap, HashSet};
+
+use crate::data::pattern::{Cell, Edge, Pattern, PatternSolution};
+use crate::patterns::PatternSet;
+use crate::soundness::forced;
+
+/// How big the inputs get.
+#[derive(Debug, Clone, Copy)]
+pub struct Discovery {
+    /// Clue cells in an input.
+    pub clues: usize,
+    /// Known edges in an input.
+    pub edges: usize,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Discovery { clues: 2, edges: 1 }
+    }
+}
+
+/// One cell or edge of a pattern, at its place in the five rows of the pattern notation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Mark {
+    Cell(Cell),
+    Edge(Edge),
+}
+
+type Marks = Vec<((usize, usize), Mark)>;
+
+/// Patterns that `known` doesn't have, each with the edges its input forces, named
+/// `discovered <n>`.
+pub fn discover(known: &PatternSet, limits: &Discovery) -> Vec<(String, PatternSolution)> {
+    let mut found: Vec<PatternSolution> = vec![];
+    let mut covering: Vec<(Marks, Marks)> = known
+        .iter()
+        .filter(|(_, pattern)| pattern.size() == (3, 3))
+        .map(|(_, pattern)| both_marks(pattern))
+        .collect();
+    let mut forced_by: HashMap<Marks, Option<Pattern>> = HashMap::new();
+    let mut seen: HashSet<PatternSolution> = HashSet::new();
+
+    for input in inputs(limits) {
+        let pattern = solution(&input, &blank());
+        if !seen.insert(pattern.rotations().swap_remove(0)) {
+            continue;
+        }
+        let Some(output) = memo_forced(&mut forced_by, &input) else {
+            continue;
+        };
+        let mut news = blank();
+        for &(at, value) in &marks(&blank_cells(), &output) {
+            let minimal = smaller(&input).iter().all(|fewer| {
+                memo_forced(&mut forced_by, fewer)
+                    .is_none_or(|p| !marks(&blank_cells(), &p).contains(&(at, value)))
+            });
+            if minimal && !covering.iter().any(|c| covers(c, &input, (at, value))) {
+                set(&mut news, at, value);
+            }
+        }
+        if news != blank() {
+            let new = solution(&input, &news);
+            covering.extend(new.rotations().iter().map(both_marks));
+            found.push(new);
+        }
+    }
+    found
+        .into_iter()
+        .enumerate()
+        .map(|(n, pattern)| (format!("discovered {}", n + 1), pattern))
+        .collect()
+}
+
+/// The inputs with one clue or edge of `input` left out, or one side of its border.
+fn smaller(input: &Marks) -> Vec<Marks> {
+    let outside = Mark::Cell(Cell::OutOfBounds);
+    let mut res = vec![];
+    for k in 0..input.len() {
+        if input[k].1 != outside {
+            let mut fewer = input.clone();
+            fewer.remove(k);
+            res.push(fewer);
+        }
+    }
+    let top = [(0, 0), (0, 2), (0, 4)];
+    let left = [(0, 0), (2, 0), (4, 0)];
+    let has = |side: &[(usize, usize)]| side.iter().all(|&at| input.contains(&(at, outside)));
+    for (side, other) in [(top, left), (left, top)] {
+        if has(&side) {
+            let keep = |at: &(usize, usize)| {
+                !side.contains(at) || (has(&other) && other.contains(at))
+            };
+            res.push(input.iter().filter(|(at, _)| keep(at)).copied().collect());
+        }
+    }
+    res
+}
+
+/// [`forced`] for `input`, remembered.
+fn memo_forced(memo: &mut HashMap<Marks, Option<Pattern>>, input: &Marks) -> Option<Pattern> {
+    memo.entry(input.clone())
+        .or_insert_with(|| forced(&solution(input, &blank())))
+        .clone()
+}
+
+/// Every input within `limits`, fewest marks first, and those nearer the centre first
+/// among inputs with as many marks.
+fn inputs(limits: &Discovery) -> Vec<Marks> {
+    let borders: [&[(usize, usize)]; 3] = [
+        &[],
+        &[(0, 0), (0, 1), (0, 2)],
+        &[(0, 0), (0, 1), (0, 2), (1, 0), (2, 0)],
+    ];
+    let mut res = vec![];
+    for border in borders {
+        let outside: Marks = border
+            .iter()
+            .map(|&(i, j)| ((2 * i, 2 * j), Mark::Cell(Cell::OutOfBounds)))
+            .collect();
+        let clue_cells: Vec<(usize, usize)> = (0..3)
+            .flat_map(|i| (0..3).map(move |j| (2 * i, 2 * j)))
+            .filter(|&(i, j)| !border.contains(&(i / 2, j / 2)))
+            .collect();
+        // Edges with a cell of the grid on at least one side.
+        let edges: Vec<(usize, usize)> = (0..5)
+            .flat_map(|i| (0..5).map(move |j| (i, j)))
+            .filter(|&(i, j)| (i + j) % 2 == 1)
+            .filter(|&(i, j)| {
+                let sides = if i % 2 == 1 {
+                    [(i - 1, j), (i + 1, j)]
+                } else {
+                    [(i, j - 1), (i, j + 1)]
+                };
+                sides
+                    .iter()
+                    .any(|&(a, b)| !border.contains(&(a / 2, b / 2)))
+            })
+            .collect();
+        let clues = [Cell::Zero, Cell::One, Cell::Two, Cell::Three].map(Mark::Cell);
+        let values = [Edge::Filled, Edge::Empty].map(Mark::Edge);
+        for with_clues in choose(&clue_cells, &clues, limits.clues) {
+            for with_edges in choose(&edges, &values, limits.edges) {
+                let mut input = outside.clone();
+                input.extend(&with_clues);
+                input.extend(&with_edges);
+                res.push(input);
+            }
+        }
+    }
+    let distance = |input: &Marks| {
+        let moves = input.iter().filter(|(_, m)| *m != Mark::Cell(Cell::OutOfBounds));
+        moves.map(|&((i, j), _)| i.abs_diff(2) + j.abs_diff(2)).sum::<usize>()
+    };
+    res.sort_by_key(|input| (input.len(), distance(input)));
+    res
+}
+
+/// Every way to give up to `most` of `places` one of `values` each.
+fn choose(places: &[(usize, usize)], values: &[Mark], most: usize) -> Vec<Marks> {
+    let mut res = vec![vec![]];
+    let mut last = vec![(vec![], 0)];
+    for _ in 0..most {
+        let mut next = vec![];
+        for (marks, from) in &last {
+            for (k, &place) in places.iter().enumerate().skip(*from) {
+                for &value in values {
+                    let mut more: Marks = marks.clone();
+                    more.push((place, value));
+                    next.push((more, k + 1));
+                }
+            }
+        }
+        res.extend(next.iter().map(|(marks, _)| marks.clone()));
+        last = next;
+    }
+    res
+}
+
+/// Some placement of `pattern`, given as its input and output marks, needs only marks of
+/// `input` and sets `edge` to the same value. Cells beyond the window are outside the grid
+/// when the window cell nearest to them is, since the inputs' borders are whole sides.
+fn covers(pattern: &(Marks, Marks), input: &Marks, (at, value): ((usize, usize), Mark)) -> bool {
+    let (needs, sets) = pattern;
+    let outside = Mark::Cell(Cell::OutOfBounds);
+    let shifts = [-4, -2, 0, 2, 4];
+    shifts.iter().any(|&di| {
+        shifts.iter().any(|&dj| {
+            let moved = |(i, j): (usize, usize)| (i as isize + di, j as isize + dj);
+            let inside = |(i, j): (isize, isize)| (0..5).contains(&i) && (0..5).contains(&j);
+            let has = |place: (usize, usize), mark: Mark| {
+                let (i, j) = moved(place);
+                if inside((i, j)) {
+                    input.contains(&((i as usize, j as usize), mark))
+                } else {
+                    let nearest = (i.clamp(0, 4) as usize, j.clamp(0, 4) as usize);
+                    mark == outside && input.contains(&(nearest, outside))
+                }
+            };
+            needs.iter().all(|&(place, mark)| has(place, mark))
+                && sets.iter().any(|&(place, mark)| {
+                    let (i, j) = moved(place);
+                    inside((i, j)) && (i as usize, j as usize) == at && mark == value
+                })
+        })
+    })
+}
+
+/// The input and output marks of `pattern`, with `OutOfBounds` edges as the `Empty` they
+/// also match.
+fn both_marks(pattern: &PatternSolution) -> (Marks, Marks) {
+    let same = |marks: Marks| {
+        marks
+            .into_iter()
+            .map(|(at, mark)| match mark {
+                Mark::Edge(Edge::OutOfBounds) => (at, Mark::Edge(Edge::Empty)),
+                _ => (at, mark),
+            })
+            .collect()
+    };
+    (
+        same(marks(&pattern.cells, &pattern.input)),
+        same(marks(&blank_cells(), &pattern.output)),
+    )
+}
+
+/// The cells and edges of a pattern that aren't `Any`.
+fn marks(cells: &[Vec<Cell>], edges: &Pattern) -> Marks {
+    let mut res = vec![];
+    for i in 0..2 * cells.len() - 1 {
+        for j in 0..2 * cells[0].len() - 1 {
+            let mark = match (i % 2, j % 2) {
+                (0, 0) => Mark::Cell(cells[i / 2][j / 2]),
+                (1, 0) => Mark::Edge(edges.horizontals[i / 2][j / 2]),
+                (0, 1) => Mark::Edge(edges.verticals[i / 2][j / 2]),
+                _ => continue,
+            };
+            if mark != Mark::Cell(Cell::Any) && mark != Mark::Edge(Edge::Any) {
+                res.push(((i, j), mark));
+            }
+        }
+    }
+    res
+}
+
+fn set(pattern: &mut Pattern, (i, j): (usize, usize), mark: Mark) {
+    if let Mark::Edge(edge) = mark {
+        if i % 2 == 1 {
+            pattern.horizontals[i / 2][j / 2] = edge;
+        } else {
+            pattern.verticals[i / 2][j / 2] = edge;
+        }
+    }
+}
+
+fn blank() -> Pattern {
+    Pattern {
+        horizontals: vec![vec![Edge::Any; 3]; 2],
+        verticals: vec![vec![Edge::Any; 2]; 3],
+    }
+}
+
+fn blank_cells() -> Vec<Vec<Cell>> {
+    vec![vec![Cell::Any; 3]; 3]
+}
+
+/// The pattern with the marks of `input` and the edges of `output`.
+fn solution(input: &Marks, output: &Pattern) -> PatternSolution {
+    let mut cells = blank_cells();
+    let mut edges = blank();
+    for &((i, j), mark) in input {
+        match mark {
+            Mark::Cell(cell) => cells[i / 2][j / 2] = cell,
+            Mark::Edge(_) => set(&mut edges, (i, j), mark),
+        }
+    }
+    PatternSolution {
+        cells,
+        input: edges,
+        output: output.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::soundness::check;
+
+    #[test]
+    fn finds_zero_from_scratch() {
+        let limits = Discovery { clues: 1, edges: 0 };
+        let found = discover(&PatternSet::empty(), &limits);
+        let zero = PatternSolution::parse(
+            "
+            *****
+            *.*.*
+            **0**
+            *.*.*
+            *****
+        ",
+            "
+            *****
+            *.x.*
+            *x*x*
+            *.x.*
+            *****
+        ",
+        );
+        assert!(found.iter().any(|(_, p)| *p == zero), "{found:?}");
+        for (name, pattern) in &found {
+            assert_eq!(check(pattern), Ok(()), "{name}");
+        }
+        // A 0 anywhere else in an open window is the same pattern moved.
+        let open_zeros = found.iter().filter(|(_, p)| {
+            let cells = p.cells.concat();
+            cells.contains(&Cell::Zero) && !cells.contains(&Cell::OutOfBounds)
+        });
+        assert_eq!(open_zeros.count(), 1);
+        // The border only adds what the zeros can't do alone, like a 3 in the corner.
+        let corner_three = PatternSolution::parse(
+            "
+            B*B*B
+            *.*.*
+            B*3**
+            *.*.*
+            B****
+        ",
+            "
+            *****
+            *.-.*
+            *|***
+            *.*.*
+            *****
+        ",
+        );
+        assert!(found.iter().any(|(_, p)| *p == corner_three), "{found:?}");
+    }
+
+    #[test]
+    fn skips_what_is_known() {
+        let limits = Discovery { clues: 1, edges: 0 };
+        let found = discover(&PatternSet::empty(), &limits);
+        let mut known = PatternSet::empty();
+        known.add(found);
+        assert!(discover(&known, &limits).is_empty());
+    }
+}
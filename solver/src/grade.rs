@@ -0,0 +1,225 @@
+//! Difficulty grading by the deduction techniques a puzzle needs.
+//!
+//! The grader plays like a person would: it uses the easiest tier of patterns until it is
+//! stuck, then takes a single step with the easiest tier that allows one and drops back to
+//! the easiest tier again. When no pattern applies, it settles one edge by trial and error
+//! ([`crate::lookahead`]) and goes on. The grade is the hardest tier it had to reach, or
+//! search when even trial and error runs out before the loop is complete. Trial and error
+//! gets a fixed number of trials, so a grade doesn't depend on how fast the machine is.
+use std::fmt;
+
+use crate::data::pattern::Edge;
+use crate::data::puzzle::Puzzle;
+use crate::lookahead::trial_edge;
+use crate::patterns::{Contradiction, Engine, PatternSet, Step, Unchecked};
+
+/// A level of the deduction engine: every pattern with at most `max_constraints`
+/// [`crate::data::pattern::PatternSolution::constraints`]. The last tier is trial and error
+/// on top of every pattern.
+pub struct Tier {
+    pub name: &'static str,
+    pub max_constraints: usize,
+}
+
+/// The tiers, easiest first.
+pub const TIERS: [Tier; 4] = [
+    // A clue and the edges right around it.
+    Tier {
+        name: "basic",
+        max_constraints: 2,
+    },
+    Tier {
+        name: "local",
+        max_constraints: 4,
+    },
+    Tier {
+        name: "advanced",
+        max_constraints: usize::MAX,
+    },
+    Tier {
+        name: "lookahead",
+        max_constraints: usize::MAX,
+    },
+];
+
+/// Index into [`TIERS`] of trial and error.
+pub const LOOKAHEAD_TIER: usize = TIERS.len() - 1;
+
+/// Edges trial and error may try over the whole grade before the grader gives up on it.
+pub const MAX_TRIALS: usize = 20_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grade {
+    /// Index into [`TIERS`] of the hardest tier needed.
+    pub tier: usize,
+    /// Pattern matches and trials that set an edge.
+    pub steps: usize,
+    /// Edges still unknown when the deductions ran out.
+    pub unknown: usize,
+    /// Trial and error used up [`MAX_TRIALS`] with edges still unknown, so more trials
+    /// might have finished the puzzle.
+    pub gave_up: bool,
+}
+
+impl Grade {
+    /// The deductions alone don't finish the puzzle.
+    pub fn needs_search(&self) -> bool {
+        self.unknown > 0 && !self.gave_up
+    }
+
+    /// `1` for the easiest tier, one more for each harder tier, and `TIERS.len() + 1` when
+    /// search is needed or the grader gave up.
+    pub fn score(&self) -> usize {
+        if self.unknown > 0 {
+            TIERS.len() + 1
+        } else {
+            self.tier + 1
+        }
+    }
+
+    /// Name of the hardest tier needed, `search`, or `unknown` when the grader gave up.
+    pub fn name(&self) -> &'static str {
+        if self.gave_up {
+            "unknown"
+        } else if self.needs_search() {
+            "search"
+        } else {
+            TIERS[self.tier].name
+        }
+    }
+}
+
+impl fmt::Display for Grade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}/{}), {} step(s)",
+            self.name(),
+            self.score(),
+            TIERS.len() + 1,
+            self.steps
+        )?;
+        if self.gave_up {
+            write!(f, ", gave up after {MAX_TRIALS} trials with {} edge(s)", self.unknown)?;
+        } else if self.needs_search() {
+            write!(f, ", {} edge(s) left for search", self.unknown)?;
+        }
+        Ok(())
+    }
+}
+
+/// Grade `puzzle` by the hardest tier of deductions `patterns` offer that it needs. A
+/// [`Contradiction`] means the puzzle has no solution.
+pub fn grade(puzzle: &Puzzle, patterns: &PatternSet) -> Result<Grade, Contradiction> {
+    let engines: Vec<Engine> = TIERS[..LOOKAHEAD_TIER]
+        .iter()
+        .map(|t| Engine::new(puzzle, patterns, t.max_constraints))
+        .collect();
+    let mut board = vec![Edge::Unknown; puzzle.edge_count()];
+    let mut tier = 0;
+    let mut steps = 0;
+    let mut trials = MAX_TRIALS;
+    let mut next_trial = 0;
+    // Windows each harder tier has yet to look at. The easiest tier reruns instead.
+    let mut unchecked: Vec<Unchecked> = engines.iter().map(Engine::unchecked).collect();
+    // Edges set since the easiest tier was last done with the board; `None` at the start.
+    let mut changed: Option<Vec<usize>> = None;
+    loop {
+        let mut trace: Vec<Step> = vec![];
+        let facts = match &changed {
+            Some(changed) => engines[0].rerun(&board, changed, Some(&mut trace))?,
+            None => engines[0].run(&board, Some(&mut trace))?,
+        };
+        steps += trace.len();
+        for (e, filled) in facts {
+            board[e] = if filled { Edge::Filled } else { Edge::Empty };
+        }
+        let set: Vec<usize> = trace.iter().flat_map(|s| &s.edges).map(|&(e, _)| e).collect();
+        for (engine, unchecked) in engines.iter().zip(&mut unchecked).skip(1) {
+            engine.changed(unchecked, &set);
+        }
+
+        let mut harder = None;
+        for (level, engine) in engines.iter().enumerate().skip(1) {
+            if let Some(step) = engine.first_step(&board, &mut unchecked[level])? {
+                harder = Some((level, step.edges));
+                break;
+            }
+        }
+        let (step_tier, edges) = match harder {
+            Some(step) => step,
+            None => {
+                let engine = &engines[LOOKAHEAD_TIER - 1];
+                match trial_edge(engine, &board, &mut next_trial, &mut trials) {
+                    Some(edge) => (LOOKAHEAD_TIER, vec![edge]),
+                    None => break,
+                }
+            }
+        };
+        steps += 1;
+        tier = tier.max(step_tier);
+        for &(e, filled) in &edges {
+            board[e] = if filled { Edge::Filled } else { Edge::Empty };
+        }
+        let set: Vec<usize> = edges.into_iter().map(|(e, _)| e).collect();
+        for (engine, unchecked) in engines.iter().zip(&mut unchecked).skip(1) {
+            engine.changed(unchecked, &set);
+        }
+        changed = Some(set);
+    }
+
+    let unknown = board.iter().filter(|&&e| e == Edge::Unknown).count();
+    Ok(Grade {
+        tier,
+        steps,
+        unknown,
+        gave_up: unknown > 0 && trials == 0,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn easy_puzzle_needs_no_search() {
+        let grid = parse::from_string("5x5:3322121021211311212201123").unwrap();
+        let g = grade(&Puzzle::from_grid(grid), &PatternSet::default()).unwrap();
+        assert!(!g.needs_search(), "{g}");
+        assert!(g.steps > 0);
+        assert_eq!(g.score(), g.tier + 1);
+    }
+
+    #[test]
+    fn lookahead_puzzle_gets_its_own_tier() {
+        let grid = parse::from_string("5x5:33a2a2b21a113a12a2c12a").unwrap();
+        let g = grade(&Puzzle::from_grid(grid), &PatternSet::default()).unwrap();
+        assert!(!g.needs_search(), "{g}");
+        assert_eq!(g.tier, LOOKAHEAD_TIER);
+        assert_eq!(g.name(), "lookahead");
+    }
+
+    #[test]
+    fn running_out_of_trials_is_no_search() {
+        let g = Grade {
+            tier: LOOKAHEAD_TIER,
+            steps: 10,
+            unknown: 4,
+            gave_up: true,
+        };
+        assert!(!g.needs_search());
+        assert_eq!(g.name(), "unknown");
+        assert_eq!(g.score(), TIERS.len() + 1);
+        assert!(g.to_string().contains("gave up"), "{g}");
+    }
+
+    #[test]
+    fn empty_grid_needs_search() {
+        let g = grade(&Puzzle::from(&[[-1; 3]; 3]), &PatternSet::default()).unwrap();
+        assert!(g.needs_search());
+        assert_eq!(g.steps, 0);
+        assert_eq!(g.name(), "search");
+        assert_eq!(g.score(), TIERS.len() + 1);
+    }
+}
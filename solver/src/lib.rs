@@ -0,0 +1,365 @@
+//! Slitherlink solver.
+//!
+//! The main entry point is [`solve`], which takes a [`Puzzle`] and a set of
+//! [`SolveOptions`] and returns a [`SolveOutcome`]:
+//!
+//! ```no_run
+//! use solver::{solve, Puzzle, SolveOptions, SolveOutcome};
+//!
+//! let grid = solver::parse::from_string("2x2:32a").unwrap();
+//! let puzzle = Puzzle::from_grid(grid);
+//! match solve(&puzzle, &SolveOptions::default()) {
+//!     SolveOutcome::Solved(solution) => print!("{solution}"),
+//!     SolveOutcome::Unsolved(_) => println!("no single-loop solution"),
+//! }
+//! ```
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+pub mod adapter;
+pub mod backend;
+pub mod colouring;
+pub mod connectivity;
+pub mod data;
+#[cfg(feature = "varisat")]
+pub mod discover;
+pub mod grade;
+pub mod lookahead;
+pub mod parse;
+pub mod pattern_stats;
+pub mod patterns;
+pub mod solve_common;
+pub mod solve_native;
+#[cfg(feature = "splr")]
+pub mod solve_splr;
+#[cfg(feature = "varisat")]
+pub mod solve_varisat;
+#[cfg(feature = "z3")]
+pub mod solve_z3;
+#[cfg(feature = "varisat")]
+pub mod soundness;
+
+pub use backend::{Backend, Capabilities, SolverBackend, REGISTRY};
+pub use data::pattern::Edge;
+pub use data::puzzle::Puzzle;
+pub use data::solution::Solution;
+
+use data::solution::{format_edges_diff, format_puzzle};
+use patterns::PatternSet;
+use solve_common::{search_stream, Search, SolutionStream};
+
+#[derive(Debug, Clone)]
+pub struct SolveOptions {
+    /// Engine used to search for the loop, see [`REGISTRY`].
+    pub backend: Backend,
+    /// Run the pattern engine ([`patterns::find_facts`]) before handing the puzzle to SAT.
+    /// When the patterns contradict each other, every backend goes on without them.
+    pub pre_solve: bool,
+    /// The patterns `pre_solve` matches.
+    pub patterns: PatternSet,
+    /// Encode single-loop connectivity in the formula itself (see [`connectivity`]), so the
+    /// first model is already a valid loop instead of blocking multi-loop models one by one.
+    /// The encoding is large, so this is usually slower unless the puzzle produces a lot of
+    /// multi-loop models. Z3 gets integer labels instead of the bit-level ranks, so a single
+    /// `check()` decides the puzzle.
+    pub connectivity: bool,
+    /// Block multi-loop models with subtour-elimination cuts on top of the per-loop blocking
+    /// clauses (see [`solve_common::blocking_clauses`]). Fewer SAT calls, but the cuts are
+    /// long clauses and each call gets slower.
+    pub subtour_cuts: bool,
+    /// Upper bound on SAT calls spent blocking multi-loop models.
+    pub max_iterations: usize,
+    /// Progress messages are printed to stdout with this prefix; `None` keeps the solver quiet.
+    pub log_prefix: Option<String>,
+    /// Set it from another thread to stop the search before its next SAT call or search
+    /// node. A cancelled search ends as if it had run out of iterations. Clones of the
+    /// options share the flag.
+    pub cancel: Arc<AtomicBool>,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        SolveOptions {
+            backend: Backend::default(),
+            pre_solve: true,
+            patterns: PatternSet::default(),
+            connectivity: false,
+            subtour_cuts: false,
+            max_iterations: 10000,
+            log_prefix: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl SolveOptions {
+    pub(crate) fn log(&self, args: fmt::Arguments) {
+        if let Some(prefix) = &self.log_prefix {
+            println!("{prefix}{args}");
+        }
+    }
+
+    pub(crate) fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+pub enum SolveOutcome {
+    /// A single closed loop satisfying every clue.
+    Solved(Solution),
+    /// The search ended without a single loop. Carries the last multi-loop model, if any.
+    Unsolved(Option<Solution>),
+}
+
+impl SolveOutcome {
+    /// The single-loop solution, or the last multi-loop model when there is none.
+    pub fn best(&self) -> Option<&Solution> {
+        match self {
+            SolveOutcome::Solved(s) => Some(s),
+            SolveOutcome::Unsolved(s) => s.as_ref(),
+        }
+    }
+
+    pub fn into_best(self) -> Option<Solution> {
+        match self {
+            SolveOutcome::Solved(s) => Some(s),
+            SolveOutcome::Unsolved(s) => s,
+        }
+    }
+
+    pub fn is_solved(&self) -> bool {
+        matches!(self, SolveOutcome::Solved(_))
+    }
+}
+
+/// Result of checking that a puzzle has exactly one solution.
+pub enum Uniqueness {
+    Unique(Solution),
+    /// The first two solutions found.
+    Multiple(Solution, Solution),
+    NoSolution,
+    /// The search hit `max_iterations` or was cancelled before it could tell. Carries the
+    /// solution found so far, if any.
+    Unknown(Option<Solution>),
+}
+
+impl fmt::Display for Uniqueness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Uniqueness::Unique(s) => {
+                writeln!(f, "Unique solution:")?;
+                write!(f, "{}", format_puzzle(&s.puzzle, &s.edges))
+            }
+            Uniqueness::Multiple(first, second) => {
+                writeln!(f, "Multiple solutions (red = edges that differ):")?;
+                let diff = format_edges_diff(
+                    &first.puzzle,
+                    &first.edges,
+                    &second.edges,
+                    "First solution",
+                    "Second solution",
+                );
+                write!(f, "{diff}")
+            }
+            Uniqueness::NoSolution => writeln!(f, "No solution."),
+            Uniqueness::Unknown(None) => {
+                writeln!(f, "Unknown: gave up before finding any solution.")
+            }
+            Uniqueness::Unknown(Some(s)) => {
+                writeln!(f, "Unknown: gave up looking for a second solution after:")?;
+                write!(f, "{}", format_puzzle(&s.puzzle, &s.edges))
+            }
+        }
+    }
+}
+
+/// Solve `puzzle` with the backend selected in `options`.
+pub fn solve(puzzle: &Puzzle, options: &SolveOptions) -> SolveOutcome {
+    options.backend.solve(puzzle, options)
+}
+
+/// Every distinct single-loop solution of `puzzle`, found lazily with the backend selected in
+/// `options`. Use [`Iterator::take`] to stop after a number of them. Turn `pre_solve` off
+/// to make sure none are missed: a pattern may take uniqueness for granted.
+///
+/// ```no_run
+/// use solver::{solutions, Puzzle, SolveOptions};
+///
+/// let puzzle = Puzzle::from_grid(solver::parse::from_string("2x2:2c").unwrap());
+/// let options = SolveOptions {
+///     pre_solve: false,
+///     ..SolveOptions::default()
+/// };
+/// for solution in solutions(&puzzle, &options).take(10) {
+///     print!("{solution}");
+/// }
+/// ```
+pub fn solutions(puzzle: &Puzzle, options: &SolveOptions) -> Box<dyn SolutionStream> {
+    options.backend.solutions(puzzle, options)
+}
+
+/// Look for up to `limit` distinct single-loop solutions with the backend selected in
+/// `options`.
+pub fn search(puzzle: &Puzzle, options: &SolveOptions, limit: usize) -> Search {
+    search_stream(solutions(puzzle, options).as_mut(), limit)
+}
+
+/// Keep searching after the first solution to tell whether `puzzle` has exactly one.
+///
+/// Pre-solve is always off here: a pattern that takes uniqueness for granted would hide the
+/// second solution.
+pub fn check_uniqueness(puzzle: &Puzzle, options: &SolveOptions) -> Uniqueness {
+    let options = SolveOptions {
+        pre_solve: false,
+        ..options.clone()
+    };
+    let Search {
+        mut solutions,
+        exhausted,
+        ..
+    } = search(puzzle, &options, 2);
+    match (solutions.pop(), solutions.pop()) {
+        (Some(second), Some(first)) => Uniqueness::Multiple(first, second),
+        (Some(only), None) if exhausted => Uniqueness::Unique(only),
+        (None, _) if exhausted => Uniqueness::NoSolution,
+        (only, _) => Uniqueness::Unknown(only),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn every_backend_solves_2x2() {
+        let p = Puzzle::from(&[[3, 2], [-1, -1]]);
+        for &backend in REGISTRY {
+            for connectivity in [false, true] {
+                let options = SolveOptions {
+                    backend,
+                    connectivity,
+                    ..SolveOptions::default()
+                };
+                assert!(solve(&p, &options).is_solved(), "{backend} failed");
+            }
+        }
+    }
+
+    #[test]
+    fn reports_unique_puzzle() {
+        let grid = parse::from_string("5x5:3322121021211311212201123").unwrap();
+        let p = Puzzle::from_grid(grid);
+        for &backend in REGISTRY {
+            let options = SolveOptions {
+                backend,
+                ..SolveOptions::default()
+            };
+            let result = check_uniqueness(&p, &options);
+            assert!(matches!(result, Uniqueness::Unique(_)), "{backend} failed");
+        }
+    }
+
+    #[test]
+    fn reports_second_solution() {
+        // A lone 2 in the corner of a 2x2 grid: the loop can go around the whole grid, or
+        // around every cell but the 2 or the opposite corner.
+        let p = Puzzle::from(&[[2, -1], [-1, -1]]);
+        for &backend in REGISTRY {
+            let options = SolveOptions {
+                backend,
+                ..SolveOptions::default()
+            };
+            match check_uniqueness(&p, &options) {
+                Uniqueness::Multiple(first, second) => {
+                    assert_ne!(first.edges, second.edges, "{backend} repeated a solution")
+                }
+                _ => panic!("{backend} missed the second solution"),
+            }
+        }
+    }
+
+    #[test]
+    fn reports_no_solution() {
+        // The patterns contradict each other here too, and rightly so.
+        let p = Puzzle::from(&[[0, 0], [0, 2]]);
+        for &backend in REGISTRY {
+            let options = SolveOptions {
+                backend,
+                ..SolveOptions::default()
+            };
+            let result = check_uniqueness(&p, &options);
+            assert!(matches!(result, Uniqueness::NoSolution), "{backend} failed");
+        }
+    }
+
+    #[test]
+    fn every_backend_gets_past_a_pattern_contradiction() {
+        use crate::data::pattern::PatternSolution;
+
+        // A wrong pattern draws the edge between the 1 and the 0 that "zero" crosses out.
+        let mut patterns = PatternSet::default();
+        let wrong = PatternSolution::parse("1*0", "1|0");
+        patterns.add(vec![(String::from("wrong"), wrong)]);
+        let grid = parse::from_string("5x5:3322121021211311212201123").unwrap();
+        let p = Puzzle::from_grid(grid);
+        assert!(patterns::find_facts(&p, &patterns).is_err());
+        for &backend in REGISTRY {
+            let options = SolveOptions {
+                backend,
+                patterns: patterns.clone(),
+                ..SolveOptions::default()
+            };
+            let result = check_uniqueness(&p, &options);
+            assert!(matches!(result, Uniqueness::Unique(_)), "{backend} failed");
+        }
+    }
+
+    #[test]
+    fn cancelled_search_gives_up() {
+        let grid = parse::from_string("5x5:3322121021211311212201123").unwrap();
+        let p = Puzzle::from_grid(grid);
+        for &backend in REGISTRY {
+            let options = SolveOptions {
+                backend,
+                ..SolveOptions::default()
+            };
+            options.cancel.store(true, Ordering::Relaxed);
+            let result = check_uniqueness(&p, &options);
+            assert!(matches!(result, Uniqueness::Unknown(None)), "{backend} went on");
+        }
+    }
+
+    #[test]
+    fn connectivity_agrees_with_blocking_loop() {
+        let grid = parse::from_string("5x5:3322121021211311212201123").unwrap();
+        let p = Puzzle::from_grid(grid);
+        let blocking = solve(&p, &SolveOptions::default()).into_best().unwrap();
+        for &backend in REGISTRY {
+            let options = SolveOptions {
+                backend,
+                connectivity: true,
+                ..SolveOptions::default()
+            };
+            let connected = solve(&p, &options).into_best().unwrap();
+            assert_eq!(blocking.edges, connected.edges, "{backend} differs");
+        }
+    }
+
+    #[test]
+    fn subtour_cuts_agree_with_blocking_loop() {
+        let grid = parse::from_string("5x5:3322121021211311212201123").unwrap();
+        let p = Puzzle::from_grid(grid);
+        let blocking = solve(&p, &SolveOptions::default()).into_best().unwrap();
+        for &backend in REGISTRY {
+            let options = SolveOptions {
+                backend,
+                pre_solve: false,
+                subtour_cuts: true,
+                ..SolveOptions::default()
+            };
+            let cut = solve(&p, &options).into_best().unwrap();
+            assert_eq!(blocking.edges, cut.edges, "{backend} differs");
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! Trial and error on top of the pattern engine, for when the patterns are stuck.
+//!
+//! Each unknown edge is assumed filled, then empty, and the pattern engine runs on the
+//! assumption until nothing changes. When the board it ends up with contradicts itself, the
+//! edge takes the other value. With a depth above one, the assumption is followed by more
+//! trials inside it before giving up on finding a contradiction. The search stops early at
+//! its time limit, keeping whatever it settled so far.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::colouring::Colouring;
+use crate::data::pattern::Edge;
+use crate::data::puzzle::Puzzle;
+use crate::patterns::{
+    facts_to_edges, run_patterns, Contradiction, Deduction, Engine, PatternSet, Paths, Step,
+    COLOURING, GIVEN, LOOP_CLOSURE,
+};
+use crate::solve_common::satisfies_clues;
+
+/// Name of the trial-and-error rule in [`Step`]s.
+pub const LOOKAHEAD: &str = "lookahead";
+
+/// Name of the check that a clue still has room for its number, in [`Contradiction`]s.
+pub const CLUE: &str = "clue";
+
+/// Name of the check that a vertex has no dead end or branch, in [`Contradiction`]s.
+pub const VERTEX: &str = "vertex";
+
+/// How far trial and error may go.
+#[derive(Debug, Clone, Copy)]
+pub struct Lookahead {
+    /// Assumptions stacked on top of each other before one trial is given up; `0` turns
+    /// trial and error off.
+    pub depth: usize,
+    /// Wall-clock budget for one call.
+    pub time_limit: Duration,
+}
+
+impl Default for Lookahead {
+    fn default() -> Self {
+        Lookahead {
+            depth: 1,
+            time_limit: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Like [`crate::patterns::find_facts`], but with trial and error once the patterns are stuck.
+/// Only contradictions on the board itself are errors, when the patterns disagree or an edge
+/// can be neither filled nor empty; the ones in trials are what trial and error is looking
+/// for.
+pub fn find_facts_lookahead(
+    puzzle: &Puzzle,
+    patterns: &PatternSet,
+    limits: &Lookahead,
+) -> Result<HashMap<usize, bool>, Contradiction> {
+    let edges = vec![Edge::Unknown; puzzle.edge_count()];
+    extend_facts_lookahead(puzzle, patterns, &edges, limits)
+}
+
+/// Like [`crate::patterns::extend_facts`], but with trial and error once the patterns are
+/// stuck.
+pub fn extend_facts_lookahead(
+    puzzle: &Puzzle,
+    patterns: &PatternSet,
+    edges: &[Edge],
+    limits: &Lookahead,
+) -> Result<HashMap<usize, bool>, Contradiction> {
+    let deadline = Instant::now() + limits.time_limit;
+    let facts = run_patterns(puzzle, patterns, edges, usize::MAX, None)?;
+    if limits.depth == 0 {
+        return Ok(facts);
+    }
+    let board = checked(puzzle, facts_to_edges(puzzle, &facts)).map_err(|c| {
+        let mut steps = vec![];
+        run_patterns(puzzle, patterns, edges, usize::MAX, Some(&mut steps)).ok();
+        blame(c, &steps)
+    })?;
+    let board = settle(puzzle, patterns, board, limits.depth, deadline, None)?;
+    Ok(known(&board))
+}
+
+/// Like [`find_facts_lookahead`], but also returns every step that led to the facts, in
+/// order. Each edge trial and error settles is one [`LOOKAHEAD`] step.
+pub fn find_facts_lookahead_traced(
+    puzzle: &Puzzle,
+    patterns: &PatternSet,
+    limits: &Lookahead,
+) -> Result<(HashMap<usize, bool>, Vec<Step>), Contradiction> {
+    let edges = vec![Edge::Unknown; puzzle.edge_count()];
+    let deadline = Instant::now() + limits.time_limit;
+    let mut steps = vec![];
+    let facts = run_patterns(puzzle, patterns, &edges, usize::MAX, Some(&mut steps))?;
+    if limits.depth == 0 {
+        return Ok((facts, steps));
+    }
+    let board = checked(puzzle, facts_to_edges(puzzle, &facts)).map_err(|c| blame(c, &steps))?;
+    let board = settle(puzzle, patterns, board, limits.depth, deadline, Some(&mut steps))?;
+    Ok((known(&board), steps))
+}
+
+/// An unknown edge of `edges` a single trial settles, with its value. `engine` has to be
+/// done with `edges` already. Edges are tried from `*next` on, going round the board, and
+/// `*next` moves past the edge found, so that calls one after another take turns. Each edge
+/// tried uses up one of `trials`. `None` when the board contradicts itself, when no trial
+/// runs into a contradiction, or when `trials` runs out first.
+pub(crate) fn trial_edge(
+    engine: &Engine,
+    edges: &[Edge],
+    next: &mut usize,
+    trials: &mut usize,
+) -> Option<(usize, bool)> {
+    let puzzle = engine.puzzle;
+    if contradicts(puzzle, edges) {
+        return None;
+    }
+    let breaks = |e: usize, filled: bool| {
+        let mut trial = edges.to_vec();
+        trial[e] = if filled { Edge::Filled } else { Edge::Empty };
+        let Ok(facts) = engine.rerun(&trial, &[e], None) else {
+            return true;
+        };
+        for (e, filled) in facts {
+            trial[e] = if filled { Edge::Filled } else { Edge::Empty };
+        }
+        contradicts(puzzle, &trial)
+    };
+    let start = *next;
+    for e in (start..edges.len()).chain(0..start.min(edges.len())) {
+        if edges[e] != Edge::Unknown {
+            continue;
+        }
+        if *trials == 0 {
+            return None;
+        }
+        *trials -= 1;
+        if let Some(filled) = [true, false].into_iter().find(|&filled| breaks(e, filled)) {
+            *next = e + 1;
+            return Some((e, !filled));
+        }
+    }
+    None
+}
+
+/// Settle unknown edges of `board`, which the patterns are done with, by trial and error
+/// `depth` deep until no trial runs into a contradiction. An error when the board turns out
+/// to contradict itself.
+fn settle(
+    puzzle: &Puzzle,
+    patterns: &PatternSet,
+    mut board: Vec<Edge>,
+    depth: usize,
+    deadline: Instant,
+    mut steps: Option<&mut Vec<Step>>,
+) -> Result<Vec<Edge>, Contradiction> {
+    let mut progress = true;
+    while progress {
+        progress = false;
+        for e in 0..board.len() {
+            if Instant::now() >= deadline {
+                return Ok(board);
+            }
+            if board[e] != Edge::Unknown {
+                continue;
+            }
+            let Some((filled, why)) = ruled_out(puzzle, patterns, &board, e, depth - 1, deadline)
+            else {
+                continue;
+            };
+            let before = board.clone();
+            board[e] = if filled { Edge::Empty } else { Edge::Filled };
+            if let Some(steps) = steps.as_mut() {
+                let ((i, j), _) = puzzle.edge_endpoints(e);
+                steps.push(Step {
+                    pattern: String::from(LOOKAHEAD),
+                    window: (i as isize, j as isize),
+                    edges: vec![(e, !filled)],
+                    before,
+                    after: board.clone(),
+                });
+            }
+            board = propagate(puzzle, patterns, &board, steps.as_deref_mut()).map_err(|c| {
+                let (if_filled, if_empty) = if filled { (why, c) } else { (c, why) };
+                neither_way(e, if_filled, if_empty)
+            })?;
+            progress = true;
+        }
+    }
+    Ok(board)
+}
+
+/// The value of unknown edge `e` that leads to a contradiction `depth` deep, if one does,
+/// with the contradiction: `true` when assuming it filled breaks the board.
+fn ruled_out(
+    puzzle: &Puzzle,
+    patterns: &PatternSet,
+    board: &[Edge],
+    e: usize,
+    depth: usize,
+    deadline: Instant,
+) -> Option<(bool, Contradiction)> {
+    let mut trial = board.to_vec();
+    [true, false].into_iter().find_map(|filled| {
+        trial[e] = if filled { Edge::Filled } else { Edge::Empty };
+        let settled = propagate(puzzle, patterns, &trial, None).and_then(|board| match depth {
+            0 => Ok(board),
+            _ => settle(puzzle, patterns, board, depth, deadline, None),
+        });
+        settled.err().map(|c| (filled, c))
+    })
+}
+
+/// The board once the pattern engine is done with `edges`. An error when the patterns
+/// disagree on it, or when it fails one of the checks of [`contradicts`].
+fn propagate(
+    puzzle: &Puzzle,
+    patterns: &PatternSet,
+    edges: &[Edge],
+    steps: Option<&mut Vec<Step>>,
+) -> Result<Vec<Edge>, Contradiction> {
+    let mut board = edges.to_vec();
+    for (e, filled) in run_patterns(puzzle, patterns, edges, usize::MAX, steps)? {
+        board[e] = if filled { Edge::Filled } else { Edge::Empty };
+    }
+    checked(puzzle, board)
+}
+
+/// `board`, unless it fails one of the checks of [`contradicts`].
+fn checked(puzzle: &Puzzle, board: Vec<Edge>) -> Result<Vec<Edge>, Contradiction> {
+    match failed_check(puzzle, &board) {
+        Some(c) => Err(c),
+        None => Ok(board),
+    }
+}
+
+/// `c`, a check the board `steps` led to failed, with the step that set its edge as `first`
+/// rather than [`GIVEN`].
+fn blame(c: Contradiction, steps: &[Step]) -> Contradiction {
+    let set_by = steps.iter().find_map(|step| {
+        let &(_, filled) = step.edges.iter().find(|&&(e, _)| e == c.edge)?;
+        Some(Deduction {
+            pattern: step.pattern.clone(),
+            window: step.window,
+            filled,
+        })
+    });
+    Contradiction {
+        first: set_by.unwrap_or(c.first),
+        ..c
+    }
+}
+
+/// Edge `e` leads to a contradiction both filled and empty: the rule that ran into the one
+/// when filled says it is empty, and the one that ran into the other when empty says it is
+/// filled.
+fn neither_way(e: usize, if_filled: Contradiction, if_empty: Contradiction) -> Contradiction {
+    Contradiction {
+        edge: e,
+        first: Deduction {
+            filled: false,
+            ..if_filled.second
+        },
+        second: Deduction {
+            filled: true,
+            ..if_empty.second
+        },
+    }
+}
+
+/// The known edges of `board`.
+fn known(board: &[Edge]) -> HashMap<usize, bool> {
+    board
+        .iter()
+        .enumerate()
+        .filter(|(_, &e)| e == Edge::Filled || e == Edge::Empty)
+        .map(|(e, &v)| (e, v == Edge::Filled))
+        .collect()
+}
+
+/// The board can't be part of any solution: a clue has too many or too few edges left, a
+/// vertex has a dead end or a branch, a loop closed before the puzzle is done, or the cells
+/// can't be coloured inside and outside.
+pub fn contradicts(puzzle: &Puzzle, edges: &[Edge]) -> bool {
+    failed_check(puzzle, edges).is_some()
+}
+
+/// The first check of [`contradicts`] the board fails, blamed on an edge where it failed:
+/// [`CLUE`] at the clue cell, [`VERTEX`] at the vertex, [`LOOP_CLOSURE`] at the edge that
+/// closed the loop, or [`COLOURING`] at the edge the colouring broke down at. A known edge is
+/// [`GIVEN`] and the check wants the other value; an unknown one fits the check neither way.
+fn failed_check(puzzle: &Puzzle, edges: &[Edge]) -> Option<Contradiction> {
+    let failed = |check: &str, window: (usize, usize), edge: usize| {
+        let rule = |pattern: &str, window: (usize, usize), filled| Deduction {
+            pattern: String::from(pattern),
+            window: (window.0 as isize, window.1 as isize),
+            filled,
+        };
+        let (first, second) = match edges[edge] {
+            Edge::Unknown => (rule(check, window, true), rule(check, window, false)),
+            value => {
+                let filled = value == Edge::Filled;
+                let given = rule(GIVEN, puzzle.edge_endpoints(edge).0, filled);
+                (given, rule(check, window, !filled))
+            }
+        };
+        Some(Contradiction {
+            edge,
+            first,
+            second,
+        })
+    };
+    let set_to = |around: &[usize], filled: bool| {
+        let is = |e: usize| edges[e] != Edge::Unknown && (edges[e] == Edge::Filled) == filled;
+        around.iter().copied().find(|&e| is(e))
+    };
+    let count = |around: &[usize]| {
+        let filled = around.iter().filter(|&&e| edges[e] == Edge::Filled).count();
+        let unknown = around.iter().filter(|&&e| edges[e] == Edge::Unknown).count();
+        (filled, unknown)
+    };
+    for i in 0..puzzle.xsize {
+        for j in 0..puzzle.ysize {
+            let clue = puzzle.cells[i][j];
+            if clue < 0 {
+                continue;
+            }
+            let (top, bottom, left, right) = puzzle.edges_around_cell(i, j);
+            let around = [top, bottom, left, right];
+            let (filled, unknown) = count(&around);
+            if filled > clue as usize {
+                return failed(CLUE, (i, j), set_to(&around, true)?);
+            }
+            if filled + unknown < clue as usize {
+                return failed(CLUE, (i, j), set_to(&around, false)?);
+            }
+        }
+    }
+    for i in 0..=puzzle.xsize {
+        for j in 0..=puzzle.ysize {
+            let around = puzzle.edges_around_point(i, j);
+            match count(&around) {
+                (3.., _) => return failed(VERTEX, (i, j), set_to(&around, true)?),
+                (1, 0) => return failed(VERTEX, (i, j), set_to(&around, false)?),
+                _ => {}
+            }
+        }
+    }
+
+    let cols = puzzle.ysize + 1;
+    let vertex = |(i, j): (usize, usize)| i * cols + j;
+    let mut paths = Paths::new((puzzle.xsize + 1) * cols);
+    let mut loops = vec![];
+    let mut filled = 0;
+    for e in (0..edges.len()).filter(|&e| edges[e] == Edge::Filled) {
+        let (a, b) = puzzle.edge_endpoints(e);
+        let (a, b) = (vertex(a), vertex(b));
+        if paths.find(a) == paths.find(b) {
+            loops.push((e, a));
+        }
+        paths.join(a, b);
+        filled += 1;
+    }
+    let early_loop = loops.iter().find(|&&(_, v)| {
+        let root = paths.find(v);
+        paths.edges[root] < filled || !satisfies_clues(puzzle, edges)
+    });
+    if let Some(&(e, _)) = early_loop {
+        return failed(LOOP_CLOSURE, puzzle.edge_endpoints(e).0, e);
+    }
+    match Colouring::deduce(puzzle, edges) {
+        Err(conflict) => failed(COLOURING, puzzle.edge_endpoints(conflict.edge).0, conflict.edge),
+        Ok(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+    use crate::patterns::find_facts;
+
+    #[test]
+    fn spots_contradictions() {
+        let p = Puzzle::from(&[[3, -1], [-1, -1]]);
+        let mut edges = vec![Edge::Unknown; p.edge_count()];
+        assert!(!contradicts(&p, &edges));
+        // The 3 can't get three edges any more.
+        let (top, _, left, _) = p.edges_around_cell(0, 0);
+        edges[top] = Edge::Empty;
+        edges[left] = Edge::Empty;
+        assert!(contradicts(&p, &edges));
+        let c = failed_check(&p, &edges).unwrap();
+        assert_eq!((c.second.pattern.as_str(), c.second.window), (CLUE, (0, 0)));
+        // A dead end in the corner.
+        let mut edges = vec![Edge::Unknown; p.edge_count()];
+        edges[top] = Edge::Filled;
+        edges[left] = Edge::Empty;
+        assert!(contradicts(&p, &edges));
+        let c = failed_check(&p, &edges).unwrap();
+        assert_eq!((c.edge, c.first.pattern.as_str()), (left, GIVEN));
+        assert_eq!((c.second.pattern.as_str(), c.second.filled), (VERTEX, true));
+    }
+
+    #[test]
+    fn settles_what_patterns_cannot() {
+        let p = Puzzle::from_grid(parse::from_string("5x5:33a2a2b21a113a12a2c12a").unwrap());
+        let set = PatternSet::default();
+        let patterns = find_facts(&p, &set).unwrap();
+        assert!(patterns.len() < p.edge_count());
+        let limits = Lookahead::default();
+        let facts = find_facts_lookahead(&p, &set, &limits).unwrap();
+        for (e, v) in &patterns {
+            assert_eq!(facts.get(e), Some(v));
+        }
+        assert_eq!(facts.len(), p.edge_count());
+        let (traced, steps) = find_facts_lookahead_traced(&p, &set, &limits).unwrap();
+        assert_eq!(traced, facts);
+        assert!(steps.iter().all(|s| s.edges.iter().all(|(e, v)| facts[e] == *v)));
+    }
+
+    #[test]
+    fn contradictions_on_the_board_are_errors() {
+        // No solution, but the patterns agree on what they find.
+        let p = Puzzle::from_grid(parse::from_string("3x2:1b233").unwrap());
+        let set = PatternSet::default();
+        assert!(find_facts(&p, &set).is_ok());
+        let limits = Lookahead::default();
+        let c = find_facts_lookahead(&p, &set, &limits).unwrap_err();
+        assert_eq!(find_facts_lookahead_traced(&p, &set, &limits).unwrap_err(), c);
+        // The patterns closed a loop too early, so blame the one that drew its last edge.
+        assert!(c.first.pattern.starts_with("forced edge turn-"), "{c}");
+        assert_eq!((c.second.pattern.as_str(), c.second.filled), (LOOP_CLOSURE, false));
+    }
+
+    #[test]
+    fn trials_blame_the_checks_that_failed() {
+        let p = Puzzle::from_grid(vec![
+            vec![-1, 1, 1, -1],
+            vec![-1, -1, 1, 1],
+            vec![-1, 2, -1, 1],
+            vec![-1; 4],
+        ]);
+        let set = PatternSet::empty();
+        let facts = find_facts(&p, &set).unwrap();
+        assert!(!contradicts(&p, &facts_to_edges(&p, &facts)));
+        let c = find_facts_lookahead(&p, &set, &Lookahead::default()).unwrap_err();
+        let loop_closure = |filled| Deduction {
+            pattern: String::from(LOOP_CLOSURE),
+            window: (0, 4),
+            filled,
+        };
+        assert_eq!(c.edge, 0);
+        assert_eq!(c.first, loop_closure(false));
+        assert_eq!(c.second, loop_closure(true));
+    }
+
+    #[test]
+    fn depth_zero_is_just_patterns() {
+        let p = Puzzle::from(&[[-1; 3]; 3]);
+        let limits = Lookahead {
+            depth: 0,
+            ..Lookahead::default()
+        };
+        let set = PatternSet::default();
+        assert_eq!(find_facts_lookahead(&p, &set, &limits), find_facts(&p, &set));
+    }
+}
@@ -1,414 +1,386 @@
-mod adapter;
-mod data;
-mod parse;
-mod patterns;
-mod solve_common;
-mod solve_splr;
-mod solve_varisat;
-mod solve_z3;
-
-use std::collections::HashMap;
-use std::env;
-use std::sync::mpsc;
-use std::thread;
-use std::time::{Duration, Instant};
+mod cli;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+#[cfg(feature = "varisat")]
+use solver::discover::Discovery;
+use solver::lookahead::Lookahead;
+use solver::patterns::PatternSet;
+use solver::{Backend, SolveOptions, REGISTRY};
+
+/// Solve Slitherlink puzzles.
+///
+/// Puzzles are given in the https://slitherlink.neocities.org format, e.g.
+/// `10x10d2:3a2223a32b...`. Every PUZZLE argument may be a puzzle string, a path to a
+/// file with one puzzle per line, or `-` for stdin. Without any PUZZLE, stdin is read.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
 
-use data::pattern::Edge;
-use data::puzzle::Puzzle;
-use data::solution::{
-    format_puzzle, format_puzzle_diff, format_side_by_side, Solution, ANSI_RED, ANSI_YELLOW_BG,
-};
-use parse::from_string;
-use patterns::find_facts;
-use solve_splr::solve_splr;
-use solve_varisat::solve;
-use solve_z3::solve_z3;
+#[derive(Subcommand)]
+enum Command {
+    /// Solve puzzles with one backend, or race several and keep the first answer
+    Solve(SolveArgs),
+    /// Run every backend with and without pre-solve, and compare their answers
+    Compare(CompareArgs),
+    /// Show what the pattern engine deduces without SAT
+    Deduce(DeduceArgs),
+    /// Suggest the simplest next deduction from a partly solved board
+    Hint(HintArgs),
+    /// Grade puzzles by the hardest deduction technique they need
+    Grade(GradeArgs),
+    /// Show how often each pattern fires over the puzzles, and which never do
+    PatternStats(PatternStatsArgs),
+    /// Draw puzzles without solving them
+    Render(InputArgs),
+    /// Print a timing matrix of backends and pre-solve for each puzzle
+    Bench(BenchArgs),
+    /// Check that puzzle strings are well-formed
+    Validate(InputArgs),
+    /// Check that puzzles have exactly one solution
+    Verify(VerifyArgs),
+    /// List every distinct solution of each puzzle
+    Enumerate(EnumerateArgs),
+    /// Search for patterns the pattern set doesn't have yet, and print them as a pattern file
+    #[cfg(feature = "varisat")]
+    Discover(DiscoverArgs),
+}
 
-use crate::solve_common::single_loop_edge;
+#[derive(Args)]
+struct InputArgs {
+    /// Puzzle strings, files with one puzzle per line, directories of such files, or `-` for
+    /// stdin
+    puzzles: Vec<String>,
+}
 
-const TIMEOUT_SECS: u64 = 180;
-const DEFAULT_PUZZLE: &str =
-// "15x15:b3a11222c3b3b1111b11b22e312a3b31d32c22g22a22c3a1a12b1b3a11b32a3e1a212c23a31a2a2a2b21221a210b22d3e0a331a22b1b1a3b12b2a32a3d3b2e1b3a222b2a23b3b21c3a2c22d3a1c21";
-//    "10x10d2:3a2223a32b211a3c3a1a12a23c2b3d33c02b2c20a21a1a3b1a12a112a3e221a3k2c2a";
-// "10x10d0:b1a2a22a32a1b22b2b2a23b212a22d222a31b2c12c2d331d013e1a2c2122a1b2a3b13a02a";
-// "2x2:0002";
-// "2x2:32";
-// "10x10d2:3a2223a32b211a3c3a1a12a23c2b3d33c02b2c20a21a1a3b1a12a112a3e221a3k2c2a", ;
-// "10x10d0:b1a2a22a32a1b22b2b2a23b212a22d222a31b2c12c2d331d013e1a2c2122a1b2a3b13a02a";
-// "20x20d0:3b2a2a1121f12c1222a0b2212a3d02b2b2a0f0212a2a23a11d31232a2231311b3a122c12i1b22d22a2b1320b23d2a221123a2d12a0a30212b13d2a3c1a223c3c3112i1a2c230c1c332b123a3c1b3a3e2b2b31c122223h22a213b31c3a2233a1a2a1a3a3c1b3a12a1a0c2c3222a2a2d3e11a0g2b2d11e121a33b1201b22032a3a3a13a3c32b11a22e";
-// "30x30d0:c333b3a1a2b3b2g2a1e0a2b102c213c3b121a11a220a3d0a21e32a2c11a22b1a1f1c2221a123b1a3231c2a2a22121a20c201c21a3b2b2a3a1a230c3a12a3b3a2a232a3a2c2c1b2b3a31a2a32d1a2a23a02a2d3a3d3b0a233b0d2b1c1a2b21b2b3b21c2b2b2b2122b01a322a2b2a1112a1b33a1b3b02a32c1a22b13a213221a121a123c01a32a21a22222c23c0a222a3a12a2b232a2f1b3b3a22a3b21a3h2b32b11a2c2a1b212c21b21f2f2b3c223a233d223a112c22221l2a20d11a21023a3b2a2120a32e2c3f2i1a3b12d2e33a22223b232a1a222a3c1b22i332b2132323a232b3a33a2d21a1a3b2a1a1b2c2f3a0c212f21b320a11a233a1a23a3c3a3a1a21a01a2a21a22i3a2c2g11f13c3a1222a11d233b3c2d22a3d3c21c12c1222b11212a32c2a11a23b2d1a13f1a110b3b3a3a2321b3213a33a3a2a2a1a";
-// "30x30d0:d233a2a22e3a11a3a3a3c2a3e21d221b1e212a12210c23a202a331b2e2b012a1a3a32a2a20a1a2e23f2a2b2c1021d2a02312c3a2b2a2b21b2a3b3a02b1b1023231b3c2b21c2a2c222a1a2e322a22d112a232111a113b22f1a1g0a1b1a21g2c2b3321d0c11a2b2b1a31b132d3e1g22a12d2a12d03a1a3b3112a2c2a2a2132d1132a2a12a1c322a3a322d02c2c2b1120a3a2b1b2a2b2a3c21223201a23233c22222a2a12a3e2b2212b1a33d02a3b2312b223d2a3a1b1113b2e1c00a2g1111a3a212b111d2a32a222a3f1a223a2b13b2d3c1d2a21a22a0011b12b1212a32d21j2a3c22a2b2b0a3a22b2111a21231a0322c31e12a2b3a3a3a2m2c1a21a223a3a12b23c1a21a2b3a12a32a221a1b2c33b321a3b211a2d2d3113a1a3a111d1c231a1a3b2c21b2a1a122c31a2a23a3b2h1d2b3d2b22b1b2a33221a332a2b";
-"45x45d3:a0a3a3a2a2a3a312a33a3a3a3b31c22b3a323b333e2a32c1b0c2b132d1b21c1a21a21a22a3a2222b0c2j02a21a3a2a1a22b2e2b2b22e13a3b11e1c2c1b0b1312e323b02a3a3a3c233a11b3221a101a1311a3a223a12d3a1c13a2b2a12a211a311d22a33f1b12022a2a2d20c2b1j121b3c2b22d2c2e2b2112a01b13c2b3a2a2a2a112a3b2a2222b0a0g3a132a1a1a2c13b2h1d213b1c3b2c21a2d22a2a2a011b23a0b322d31b1b1222a1a12a21033a2a1c33a2a3b1d2a3a2a2a3a3b1a3d3d2b3g22a2a232c21a11b2a1a1b1a2a1a22b3b232c2a32a21a12a2a22a2b3b2a212a2b22a2a2121223a2f22d20g1a22b2b2c12c2b21b1a33a12a21a22b3122a2a21f32a3a1c22b3a21c1a22e3a1b312b213120a123a2212131e2a323b3b1a12c1d2a3b3d12c1a1c3a12a1a2b2e22a2a3a12b2c232e22b22d2a1a23b22a1a2a2h3a2c2c21a1313b3a2111a3a3c220b2c02211a11b2b3a2b1c2d122c023a1a12c2a2a233a1b131a2a1b21b23a2a2a1a2e2a123a112b12a3a1a22a13a22a23a2a2a3b2a2a1b3b1b2j3a2f32a1a1a2b0b3a1a2b02c20b22113a3a2b2e1a2a22b3a32a2h2b2b122a2b3a10a2a3e22b1d2a002c3a2a1a2c2a3b3c3b1323b11a1c2a10b3b2a3a3b3e2123a322a21a0b1d31d22a1a1c1a0a0b1b2b22c32a1c2a2213a31a2b11a1b3d2a2a22a2b1a2a2d32a2c2b2f3a2b22a23a2c1221a2a1a3a1c21e1a3a2133a1c0a2c3b1a2c11a2c3131c23112f2a2b3a2132b32b2d2a322c31a2d3a3a2a23e1d2b222b21c2h2a212g22a2e32e2a32d31a2e2a3c2h2a0g11b21b22a2a3b2221a1b3a11a12c2222b12a32b121a1a1a122b2a13212c32b13g22a3d1b3a3c22d2b13b312c221b33b1a3b3b312d0b12b2201b2f22a2b2b221a3b3a21b3c23a31a1b3b2a222322a231a11b3a32f1c2a2c21b3c2a2a12b2c1a2b2a22a3b13a33a0b23a3a2d3b2231a2a31a21a1a1a2c122d12c222a3a2a1a2c3a3c2a3b21b213b23a32a222b23c1132b23e23a23b3a3e13a2b";
+#[derive(Args)]
+struct BackendArgs {
+    /// Backend to use; repeat to use several
+    #[arg(short, long = "backend", value_parser = parse_backend)]
+    backends: Vec<Backend>,
+    /// Give up on a puzzle after this many seconds
+    #[arg(short, long, default_value_t = 180)]
+    timeout: u64,
+}
 
-// (solver_label, pre_solve, solutions, elapsed)
-type SolveResult = (&'static str, bool, Vec<Solution>, Duration);
+#[derive(Args)]
+struct PatternArgs {
+    /// Pattern file to load on top of the baked-in patterns, replacing those of the same
+    /// name; repeat to load several
+    #[arg(long = "patterns", value_name = "FILE")]
+    files: Vec<PathBuf>,
+    /// Leave out the baked-in patterns and use only those of `--patterns`
+    #[arg(long)]
+    no_builtin_patterns: bool,
+}
 
-pub fn main() {
-    let puzzle_str = {
-        let args: Vec<String> = env::args().collect();
-        if args.len() > 1 {
-            args[1].clone()
-        } else {
-            DEFAULT_PUZZLE.to_string()
-        }
-    };
+#[derive(Args)]
+struct SolveArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    #[command(flatten)]
+    backend: BackendArgs,
+    #[command(flatten)]
+    patterns: PatternArgs,
+    /// Skip the pattern-based pre-solve step
+    #[arg(long)]
+    no_pre_solve: bool,
+    /// Encode loop connectivity in the formula instead of blocking multi-loop models
+    #[arg(long)]
+    connectivity: bool,
+    /// Add subtour-elimination cuts when blocking multi-loop models
+    #[arg(long)]
+    subtour_cuts: bool,
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Print solver progress
+    #[arg(short, long)]
+    verbose: bool,
+}
 
-    println!("Puzzle: {puzzle_str}\n");
+#[derive(Args)]
+struct CompareArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    #[command(flatten)]
+    backend: BackendArgs,
+}
 
-    let grid = from_string(&puzzle_str).unwrap();
+#[derive(Args)]
+struct DeduceArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// Show every pattern match that set an edge, in order
+    #[arg(long)]
+    steps: bool,
+    #[command(flatten)]
+    patterns: PatternArgs,
+    /// Once the patterns are stuck, try each unknown edge both ways this many assumptions
+    /// deep and keep the value that doesn't lead to a contradiction. 0 turns it off
+    #[arg(long, default_value_t = 0)]
+    lookahead: usize,
+    /// Stop trial and error on a puzzle after this many seconds
+    #[arg(short, long, default_value_t = 10)]
+    timeout: u64,
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
 
-    let (tx, rx) = mpsc::channel::<SolveResult>();
-    // Dedicated channel for pre_solve edges from the varisat-pre thread.
-    let (pre_edges_tx, pre_edges_rx) = mpsc::channel::<Vec<Edge>>();
+#[derive(Args)]
+struct GradeArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    #[command(flatten)]
+    patterns: PatternArgs,
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
 
-    // Thread 1: varisat, no pre-solve
-    {
-        let tx = tx.clone();
-        let grid = grid.clone();
-        thread::spawn(move || {
-            let prefix = "[varisat / no-pre] ";
-            println!("{prefix}Starting...");
-            let t0 = Instant::now();
-            if let Some(sols) = solve(grid, false, prefix) {
-                tx.send((prefix, false, sols, t0.elapsed())).ok();
-            }
-        });
-    }
+#[derive(Args)]
+struct PatternStatsArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    #[command(flatten)]
+    patterns: PatternArgs,
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
 
-    // Thread 2: varisat, pre-solve (also sends pre_edges for comparison section)
-    {
-        let tx = tx.clone();
-        let grid = grid.clone();
-        let xsize = grid.len();
-        let ysize = grid[0].len();
-        thread::spawn(move || {
-            let prefix = "[varisat / pre   ] ";
-            println!("{prefix}Starting...");
-            let t0 = Instant::now();
-            let p = Puzzle {
-                cells: grid.clone(),
-                xsize,
-                ysize,
-            };
-            let facts = find_facts(&p);
-            let total = (1 + xsize) * ysize + (1 + ysize) * xsize;
-            let mut pre_edges = vec![Edge::Unknown; total];
-            for (&k, &v) in &facts {
-                pre_edges[k] = if v { Edge::Filled } else { Edge::Empty };
-            }
-            pre_edges_tx.send(pre_edges).ok();
-            if let Some(sols) = solve(grid, true, prefix) {
-                tx.send((prefix, true, sols, t0.elapsed())).ok();
-            }
-        });
-    }
+#[derive(Args)]
+struct HintArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// Edges marked so far, `1` for a line, `0` for a cross and `?` for unknown, in edge
+    /// index order (the `edges` field of the JSON output). Empty board when left out
+    #[arg(short, long)]
+    edges: Option<String>,
+    #[command(flatten)]
+    patterns: PatternArgs,
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
 
-    // Thread 3: splr, no pre-solve
-    {
-        let tx = tx.clone();
-        let grid = grid.clone();
-        thread::spawn(move || {
-            let prefix = "[splr    / no-pre] ";
-            println!("{prefix}Starting...");
-            let t0 = Instant::now();
-            if let Some(sols) = solve_splr(grid, false, prefix) {
-                tx.send((prefix, false, sols, t0.elapsed())).ok();
-            }
-        });
-    }
+#[derive(Args)]
+struct BenchArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    #[command(flatten)]
+    backend: BackendArgs,
+    #[command(flatten)]
+    patterns: PatternArgs,
+    /// Encode loop connectivity in the formula instead of blocking multi-loop models
+    #[arg(long)]
+    connectivity: bool,
+    /// Add subtour-elimination cuts when blocking multi-loop models
+    #[arg(long)]
+    subtour_cuts: bool,
+}
 
-    // Thread 4: splr, pre-solve
-    {
-        let tx = tx.clone();
-        let grid = grid.clone();
-        thread::spawn(move || {
-            let prefix = "[splr    / pre   ] ";
-            println!("{prefix}Starting...");
-            let t0 = Instant::now();
-            if let Some(sols) = solve_splr(grid, true, prefix) {
-                tx.send((prefix, true, sols, t0.elapsed())).ok();
-            }
-        });
-    }
+#[derive(Args)]
+struct VerifyArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    #[command(flatten)]
+    backend: BackendArgs,
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
 
-    // Thread 5: z3, no pre-solve
-    {
-        let tx = tx.clone();
-        let grid = grid.clone();
-        thread::spawn(move || {
-            let prefix = "[z3      / no-pre] ";
-            println!("{prefix}Starting...");
-            let t0 = Instant::now();
-            if let Some(sols) = solve_z3(grid, false, prefix) {
-                tx.send((prefix, false, sols, t0.elapsed())).ok();
-            }
-        });
-    }
+#[derive(Args)]
+struct EnumerateArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// Backend to use
+    #[arg(short, long, value_parser = parse_backend, default_value_t = Backend::default())]
+    backend: Backend,
+    /// Stop after this many solutions
+    #[arg(short = 'n', long, default_value_t = 100)]
+    limit: usize,
+    /// Give up on a puzzle after this many seconds
+    #[arg(short, long, default_value_t = 180)]
+    timeout: u64,
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
 
-    // Thread 6: z3, pre-solve
-    {
-        let tx = tx.clone();
-        let grid = grid.clone();
-        thread::spawn(move || {
-            let prefix = "[z3      / pre   ] ";
-            println!("{prefix}Starting...");
-            let t0 = Instant::now();
-            if let Some(sols) = solve_z3(grid, true, prefix) {
-                tx.send((prefix, true, sols, t0.elapsed())).ok();
-            }
-        });
-    }
-    drop(tx);
+#[cfg(feature = "varisat")]
+#[derive(Args)]
+struct DiscoverArgs {
+    /// Most clues in a pattern
+    #[arg(long, default_value_t = Discovery::default().clues)]
+    clues: usize,
+    /// Most known edges in a pattern
+    #[arg(long, default_value_t = Discovery::default().edges)]
+    edges: usize,
+    #[command(flatten)]
+    patterns: PatternArgs,
+}
 
-    // Collect pre_solve edges — available almost instantly.
-    let pre_solve_edges = pre_edges_rx.recv_timeout(Duration::from_secs(10)).ok();
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Human-readable board drawings
+    Text,
+    /// One JSON object per puzzle, one per line
+    Json,
+}
 
-    let deadline = Instant::now() + Duration::from_secs(TIMEOUT_SECS);
-    // (solver_label, pre_solve) -> (solutions, elapsed)
-    let mut results: HashMap<(&'static str, bool), (Vec<Solution>, Duration)> = HashMap::new();
+fn parse_backend(s: &str) -> Result<Backend, String> {
+    Backend::from_name(s).ok_or_else(|| {
+        let names: Vec<&str> = REGISTRY.iter().map(|b| b.name()).collect();
+        format!("unknown backend {s:?}, expected one of: {}", names.join(", "))
+    })
+}
 
-    for _ in 0..6 {
-        let remaining = deadline.saturating_duration_since(Instant::now());
-        if remaining.is_zero() {
-            println!("Timeout reached, proceeding with available results.");
-            break;
-        }
-        match rx.recv_timeout(remaining) {
-            Ok((label, pre, sols, elapsed)) => {
-                println!("{label}Completed: {} solution(s).", sols.len());
-                results.insert((label, pre), (sols, elapsed));
-            }
-            Err(_) => {
-                println!("Timeout reached, proceeding with available results.");
-                break;
-            }
+impl BackendArgs {
+    /// Selected backends, or `default` when none were given.
+    fn or(&self, default: &[Backend]) -> Vec<Backend> {
+        if self.backends.is_empty() {
+            default.to_vec()
+        } else {
+            self.backends.clone()
         }
     }
 
-    // Helper to look up by solver/pre combination
-    let varisat_no_pre = "[varisat / no-pre] ";
-    let varisat_pre = "[varisat / pre   ] ";
-    let splr_no_pre = "[splr    / no-pre] ";
-    let splr_pre = "[splr    / pre   ] ";
-    let z3_no_pre = "[z3      / no-pre] ";
-    let z3_pre = "[z3      / pre   ] ";
-
-    let get_time = |label: &'static str, pre: bool| -> String {
-        match results.get(&(label, pre)) {
-            Some((_, d)) => format!("{:.2?}", d),
-            None => "timed out".to_string(),
-        }
-    };
-
-    'analysis: {
-        // For the comparison section use varisat results as reference.
-        let sols_no_pre = results.get(&(varisat_no_pre, false)).map(|(s, _)| s);
-        let sols_pre = results.get(&(varisat_pre, true)).map(|(s, _)| s);
-
-        println!("\n=== Result ===\n");
-
-        // Primary display: varisat/pre shows pre-solve deductions and final solution together.
-        // Fall back to varisat/no-pre if not available.
-        let primary = sols_pre
-            .and_then(|v| v.first())
-            .or_else(|| sols_no_pre.and_then(|v| v.first()));
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+}
 
-        match sols_no_pre.and_then(|v| v.first()) {
-            Some(sol) => {
-                let mut s0 = String::new();
-                s0.push_str("Input puzzle:\n");
-                s0.push_str(format_puzzle(&sol.puzzle, &sol.edges_pre_solve).as_str());
-                println!("Input puzzle pre pre-solve: {}", s0)
-            }
-            None => println!("could not find raw puzzle :("),
-        }
-        match primary {
-            Some(sol) => print!("{}", sol),
-            None => println!("No solution found (solvers timed out or found no solutions)."),
+impl PatternArgs {
+    fn load(&self) -> Result<PatternSet, Box<dyn std::error::Error>> {
+        let mut patterns = if self.no_builtin_patterns {
+            PatternSet::empty()
+        } else {
+            PatternSet::baked_in()
+        };
+        for file in &self.files {
+            patterns.load(file)?;
         }
+        Ok(patterns)
+    }
+}
 
-        // Reference edges (varisat/no-pre) for comparing other solvers.
-        let ref_edges = sols_no_pre.and_then(|v| v.first()).map(|s| &s.edges);
-
-        // Print other solver results only when they differ from the reference.
-        for &(label, pre) in &[
-            (splr_no_pre, false),
-            (splr_pre, true),
-            (z3_no_pre, false),
-            (z3_pre, true),
-        ] {
-            match results.get(&(label, pre)) {
-                Some((sols, _)) if !sols.is_empty() => {
-                    if ref_edges.is_none_or(|r| *r != sols[0].edges) {
-                        println!("\n{} differs from reference:\n{}", label.trim(), sols[0]);
-                    }
-                }
-                Some(_) if ref_edges.is_some() => {
-                    println!(
-                        "{} found no solutions (reference has a solution).",
-                        label.trim()
-                    );
-                }
-                _ => {}
-            }
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let inputs = match &cli.command {
+        Command::Solve(a) => Some(&a.input),
+        Command::Compare(a) => Some(&a.input),
+        Command::Deduce(a) => Some(&a.input),
+        Command::Hint(a) => Some(&a.input),
+        Command::Grade(a) => Some(&a.input),
+        Command::PatternStats(a) => Some(&a.input),
+        Command::Render(a) | Command::Validate(a) => Some(a),
+        Command::Bench(a) => Some(&a.input),
+        Command::Verify(a) => Some(&a.input),
+        Command::Enumerate(a) => Some(&a.input),
+        #[cfg(feature = "varisat")]
+        Command::Discover(_) => None,
+    };
+    let puzzles = match inputs.map_or(Ok(vec![]), |i| cli::read_inputs(&i.puzzles)) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
         }
+    };
 
-        // Ground truth: first valid single-loop from varisat / no-pre.
-        let sol_false = match sols_no_pre.and_then(|v| v.first()) {
-            // TODO: add this check back in: commented bc I expect solution vector here now instead
-            Some(s) if single_loop_edge(&s.puzzle, &s.edges) => s,
-            _ => {
-                println!("Cannot compare: varisat/no-pre did not produce a valid loop.");
-                break 'analysis;
-            }
-        };
-        let puzzle = &sol_false.puzzle;
-        let col_width = 2 * puzzle.ysize + 1;
-
-        // Determine whether varisat / pre-solve produced a valid loop.
-        let pre_true_has_loop = sols_pre
-            .and_then(|v| v.first())
-            .is_some_and(|s| single_loop_edge(&s.puzzle, &s.edges));
-
-        if pre_true_has_loop {
-            let sol_true = sols_pre.unwrap().first().unwrap();
-
-            // Level A: compare final SAT solutions between the two runs.
-            let highlights_a: HashMap<usize, &'static str> = sol_false
-                .edges
-                .iter()
-                .enumerate()
-                .filter(|(i, e)| sol_true.edges.get(*i) != Some(e))
-                .map(|(i, _)| (i, ANSI_RED))
-                .collect();
-
-            if highlights_a.is_empty() {
-                println!("\n=== Level A: Final solutions are identical. ===\n");
-            } else {
-                println!(
-                    "\n=== Level A: Final solutions differ on {} edge(s) (red = mismatch) ===\n",
-                    highlights_a.len()
-                );
-                let left = format_puzzle_diff(puzzle, &sol_false.edges, &highlights_a);
-                let right = format_puzzle_diff(puzzle, &sol_true.edges, &highlights_a);
-                print!(
-                    "{}",
-                    format_side_by_side(
-                        &left,
-                        &right,
-                        "SAT only (reference)",
-                        "with pre_solve",
-                        col_width
-                    )
-                );
-            }
-
-            // Level B: compare pre_solve deductions against the ground-truth final solution.
-            let highlights_b: HashMap<usize, &'static str> = sol_true
-                .edges_pre_solve
-                .iter()
-                .enumerate()
-                .filter(|(_, &e)| e != Edge::Unknown)
-                .filter(|(i, &e)| sol_false.edges.get(*i) != Some(&e))
-                .map(|(i, _)| (i, ANSI_RED))
-                .collect();
-
-            if highlights_b.is_empty() {
-                println!("\n=== Level B: All pre_solve deductions match the ground truth. ===\n");
-            } else {
-                println!(
-                "\n=== Level B: pre_solve made {} wrong deduction(s) vs ground truth (red = wrong) ===\n",
-                highlights_b.len()
-            );
-                let left = format_puzzle_diff(puzzle, &sol_false.edges, &highlights_b);
-                let right = format_puzzle_diff(puzzle, &sol_true.edges_pre_solve, &highlights_b);
-                print!(
-                    "{}",
-                    format_side_by_side(
-                        &left,
-                        &right,
-                        "Ground truth (SAT only)",
-                        "Pre-solve deductions",
-                        col_width
-                    )
-                );
-            }
-        } else {
-            // varisat/pre reached a dead end (UNSAT, non-loop fallback, or timed out).
-            // Compare pre_solve deductions against ground truth with two-color highlighting:
-            //   red            = wrong deduction (pre_solve asserted the wrong state)
-            //   yellow bg      = missing deduction (ground truth Filled, pre_solve Unknown)
-            match &pre_solve_edges {
-                None => println!("\nCannot compare: pre_solve edges not available."),
-                Some(pre_edges) => {
-                    let mut highlights_left: HashMap<usize, &'static str> = HashMap::new();
-                    let mut highlights_right: HashMap<usize, &'static str> = HashMap::new();
-                    let mut wrong_count = 0usize;
-                    let mut missing_count = 0usize;
-
-                    for (i, &pre_e) in pre_edges.iter().enumerate() {
-                        let ground = sol_false.edges.get(i).copied().unwrap_or(Edge::Unknown);
-                        if pre_e != Edge::Unknown && pre_e != ground {
-                            highlights_left.insert(i, ANSI_RED);
-                            highlights_right.insert(i, ANSI_RED);
-                            wrong_count += 1;
-                        } else if ground == Edge::Filled && pre_e == Edge::Unknown {
-                            highlights_left.insert(i, ANSI_YELLOW_BG);
-                            highlights_right.insert(i, ANSI_YELLOW_BG);
-                            missing_count += 1;
-                        }
-                    }
-
-                    let summary = match (wrong_count, missing_count) {
-                        (0, 0) => {
-                            println!("\n=== Dead-end analysis: pre_solve deductions are all correct (dead end has another cause). ===\n");
-                            break 'analysis;
-                        }
-                        (w, 0) => format!("{w} wrong (red)"),
-                        (0, m) => format!("{m} missing (yellow bg)"),
-                        (w, m) => format!("{w} wrong (red), {m} missing (yellow bg)"),
-                    };
-                    println!(
-                        "\n=== Dead-end analysis: pre_solve vs ground truth — {summary} ===\n"
-                    );
-                    let left = format_puzzle_diff(puzzle, &sol_false.edges, &highlights_left);
-                    let right = format_puzzle_diff(puzzle, pre_edges, &highlights_right);
-                    print!(
-                        "{}",
-                        format_side_by_side(
-                            &left,
-                            &right,
-                            "Ground truth (SAT only)",
-                            "Pre-solve output",
-                            col_width
-                        )
-                    );
-                }
-            }
+    let pattern_args = match &cli.command {
+        Command::Solve(a) => Some(&a.patterns),
+        Command::Deduce(a) => Some(&a.patterns),
+        Command::Hint(a) => Some(&a.patterns),
+        Command::Grade(a) => Some(&a.patterns),
+        Command::PatternStats(a) => Some(&a.patterns),
+        Command::Bench(a) => Some(&a.patterns),
+        #[cfg(feature = "varisat")]
+        Command::Discover(a) => Some(&a.patterns),
+        _ => None,
+    };
+    let patterns = match pattern_args.map_or(Ok(PatternSet::default()), PatternArgs::load) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
         }
-    } // end 'analysis
+    };
 
-    println!("\n=== Timing Matrix ===");
-    println!(
-        "  {:<22} | {:<12} | {:<12}",
-        "", "no pre-solve", "with pre-solve"
-    );
-    println!(
-        "  {:<22} | {:<12} | {:<12}",
-        "varisat",
-        get_time(varisat_no_pre, false),
-        get_time(varisat_pre, true)
-    );
-    println!(
-        "  {:<22} | {:<12} | {:<12}",
-        "splr",
-        get_time(splr_no_pre, false),
-        get_time(splr_pre, true)
-    );
-    println!(
-        "  {:<22} | {:<12} | {:<12}",
-        "z3",
-        get_time(z3_no_pre, false),
-        get_time(z3_pre, true)
-    );
+    let ok = match &cli.command {
+        Command::Solve(a) => cli::solve(
+            &puzzles,
+            &a.backend.or(&[Backend::default()]),
+            &SolveOptions {
+                pre_solve: !a.no_pre_solve,
+                patterns,
+                connectivity: a.connectivity,
+                subtour_cuts: a.subtour_cuts,
+                ..SolveOptions::default()
+            },
+            a.backend.timeout(),
+            a.format,
+            a.verbose,
+        ),
+        Command::Compare(a) => cli::compare::compare(
+            &puzzles,
+            &a.backend.or(REGISTRY),
+            a.backend.timeout(),
+        ),
+        Command::Deduce(a) => cli::deduce(
+            &puzzles,
+            &patterns,
+            a.steps,
+            &Lookahead {
+                depth: a.lookahead,
+                time_limit: Duration::from_secs(a.timeout),
+            },
+            a.format,
+        ),
+        Command::Hint(a) => cli::hint(&puzzles, &patterns, a.edges.as_deref(), a.format),
+        Command::Grade(a) => cli::grade(&puzzles, &patterns, a.format),
+        Command::PatternStats(a) => cli::pattern_stats(&puzzles, &patterns, a.format),
+        Command::Render(_) => cli::render(&puzzles),
+        Command::Bench(a) => cli::bench(
+            &puzzles,
+            &a.backend.or(REGISTRY),
+            &SolveOptions {
+                patterns,
+                connectivity: a.connectivity,
+                subtour_cuts: a.subtour_cuts,
+                ..SolveOptions::default()
+            },
+            a.backend.timeout(),
+        ),
+        Command::Validate(_) => cli::validate(&puzzles),
+        Command::Verify(a) => cli::verify(
+            &puzzles,
+            &a.backend.or(&[Backend::default()]),
+            a.backend.timeout(),
+            a.format,
+        ),
+        Command::Enumerate(a) => cli::enumerate(
+            &puzzles,
+            a.backend,
+            a.limit,
+            Duration::from_secs(a.timeout),
+            a.format,
+        ),
+        #[cfg(feature = "varisat")]
+        Command::Discover(a) => cli::discover(
+            &patterns,
+            &Discovery {
+                clues: a.clues,
+                edges: a.edges,
+            },
+        ),
+    };
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
 }
@@ -6,11 +6,11 @@ pub type Cell = i8;
 type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
 #[derive(Debug, Clone)]
-struct ParseError;
+struct ParseError(String);
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "String did not match expected pattern")
+        f.write_str(&self.0)
     }
 }
 
@@ -26,7 +26,9 @@ fn parse_str(inp: &str) -> Result<(usize, usize, String)> {
             let s = String::from(&c[4]);
             Ok((xs?, ys?, s))
         },
-        None => Err(Box::new(ParseError {}))
+        None => Err(Box::new(ParseError(String::from(
+            "String did not match expected pattern",
+        ))))
     }
 }
 
@@ -38,6 +40,11 @@ pub fn from_string(inp: &str) -> Result<Vec<Vec<Cell>>> {
     let mut ix: usize = 0;
     for c in encoded.chars() {
         if ('0'..='4').contains(&c) {
+            if ix >= xsize * ysize {
+                return Err(Box::new(ParseError(format!(
+                    "Clue at position {ix} is outside of {xsize}x{ysize} grid"
+                ))));
+            }
             let i: usize = ix / ysize;
             let j: usize = ix % ysize;
             res[i][j] = char::to_digit(c, 10).unwrap() as i8;
@@ -51,9 +58,40 @@ pub fn from_string(inp: &str) -> Result<Vec<Vec<Cell>>> {
     Ok(res)
 }
 
+/// The difficulty the source gave the puzzle, the `N` of a `dN` after the size, if any.
+pub fn difficulty(inp: &str) -> Option<u32> {
+    let r = Regex::new(r"^\d+x\d+d(\d+):").unwrap();
+    r.captures(inp).and_then(|c| c[1].parse().ok())
+}
+
+/// Stricter than [`from_string`]: the encoding must cover the grid exactly, and only
+/// contain clues `0-3` and lowercase skips. Returns the grid size.
+pub fn validate(inp: &str) -> Result<(usize, usize)> {
+    let (xsize, ysize, encoded) = parse_str(inp)?;
+    let mut ix: usize = 0;
+    for (pos, c) in encoded.chars().enumerate() {
+        if ('0'..='3').contains(&c) {
+            ix += 1;
+        } else if c.is_ascii_lowercase() {
+            ix += ((c as usize) - ('a' as usize)) + 1;
+        } else {
+            return Err(Box::new(ParseError(format!(
+                "Unexpected character {c:?} at position {pos}"
+            ))));
+        }
+    }
+    if ix != xsize * ysize {
+        return Err(Box::new(ParseError(format!(
+            "Encoding covers {ix} cells, expected {}",
+            xsize * ysize
+        ))));
+    }
+    Ok((xsize, ysize))
+}
+
 #[cfg(test)]
 mod test {
-    use super::from_string;
+    use super::{difficulty, from_string, validate};
     #[test]
     fn simple_zero_case() {
         let result = from_string("2x2:0000").unwrap();
@@ -106,6 +144,20 @@ mod test {
         assert!(from_string("2x2:").is_err());
     }
 
+    #[test]
+    fn returns_error_on_overflowing_string() {
+        assert!(from_string("2x2:00000").is_err());
+    }
+
+    #[test]
+    fn validates_exact_cover() {
+        assert_eq!(validate("2x2:0a2a").unwrap(), (2, 2));
+        assert_eq!(validate("3x5:1b2c3d2b").unwrap(), (3, 5));
+        assert!(validate("2x2:c4").is_err());
+        assert!(validate("2x2:a").is_err());
+        assert!(validate("2x2:0a2b").is_err());
+    }
+
     #[test]
     fn adapts_size() {
         let result = from_string("3x5:1b2c3d4").unwrap();
@@ -116,4 +168,10 @@ mod test {
         ];
         assert_eq!(result, expected)
     }
+
+    #[test]
+    fn reads_difficulty() {
+        assert_eq!(difficulty("10x10d2:3a2223a"), Some(2));
+        assert_eq!(difficulty("5x5:3322121021211311212201123"), None);
+    }
 }
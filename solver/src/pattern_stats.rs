@@ -0,0 +1,140 @@
+//! What each pattern of a [`PatternSet`] does over a corpus of puzzles, to tell the patterns
+//! that matter from dead weight.
+//!
+//! Patterns are counted by name, with all of their rotations together. The global rules
+//! ([`crate::patterns::LOOP_CLOSURE`], [`crate::patterns::COLOURING`]) are always on, so they
+//! aren't counted, but they can make up for a pattern that is left out.
+use std::collections::BTreeMap;
+
+use crate::data::puzzle::Puzzle;
+use crate::patterns::{find_facts, find_facts_traced, pattern_name, Contradiction, PatternSet};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatternStats {
+    /// Matches that set an edge.
+    pub fired: usize,
+    /// Edges those matches set.
+    pub facts: usize,
+    /// Puzzles, by the order they were added in, on which the engine deduces less without
+    /// this pattern: nothing else gets past the point where it fired.
+    pub only_way: Vec<usize>,
+}
+
+/// Pattern statistics over the puzzles added so far.
+pub struct Usage {
+    patterns: PatternSet,
+    /// `patterns` without each pattern that has fired, by name.
+    without: BTreeMap<String, PatternSet>,
+    /// Every pattern of the set, by name.
+    pub stats: BTreeMap<String, PatternStats>,
+    /// Puzzles added without a contradiction.
+    pub puzzles: usize,
+}
+
+impl Usage {
+    pub fn new(patterns: &PatternSet) -> Usage {
+        Usage {
+            patterns: patterns.clone(),
+            without: BTreeMap::new(),
+            stats: patterns
+                .names()
+                .into_iter()
+                .map(|name| (name.to_string(), PatternStats::default()))
+                .collect(),
+            puzzles: 0,
+        }
+    }
+
+    /// Run the engine over `puzzle`, and again without each pattern that fired on it, and
+    /// count what every pattern did. A [`Contradiction`] means the puzzle has no solution,
+    /// and nothing is counted.
+    pub fn add(&mut self, puzzle: &Puzzle) -> Result<(), Contradiction> {
+        let (facts, steps) = find_facts_traced(puzzle, &self.patterns)?;
+        let mut fired: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+        for step in &steps {
+            let name = pattern_name(&step.pattern);
+            if self.stats.contains_key(name) {
+                let (matches, edges) = fired.entry(name.to_string()).or_default();
+                *matches += 1;
+                *edges += step.edges.len();
+            }
+        }
+        for (name, (matches, edges)) in fired {
+            let without = self.without.entry(name.clone()).or_insert_with(|| {
+                let mut patterns = self.patterns.clone();
+                patterns.remove(&name);
+                patterns
+            });
+            // Leaving out a pattern can only lose facts.
+            let only_way = find_facts(puzzle, without).is_ok_and(|f| f.len() < facts.len());
+            let stats = self.stats.get_mut(&name).expect("fired patterns are in the set");
+            stats.fired += matches;
+            stats.facts += edges;
+            if only_way {
+                stats.only_way.push(self.puzzles);
+            }
+        }
+        self.puzzles += 1;
+        Ok(())
+    }
+
+    /// Names of the patterns that didn't fire on any puzzle.
+    pub fn never_fired(&self) -> impl Iterator<Item = &str> {
+        self.stats
+            .iter()
+            .filter(|(_, stats)| stats.fired == 0)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::pattern::PatternSolution;
+
+    #[test]
+    fn counts_by_name_and_finds_the_only_way() {
+        let zero = PatternSet::baked_in()
+            .iter()
+            .find(|(name, _)| *name == "zero")
+            .unwrap()
+            .1
+            .clone();
+        let mut patterns = PatternSet::empty();
+        patterns.add(vec![
+            (String::from("zero"), zero.clone()),
+            (String::from("nought"), zero),
+            (String::from("threes"), PatternSolution::parse("3*3", "3|3")),
+            (String::from("ones"), PatternSolution::parse("1*1", "1x1")),
+        ]);
+        let mut usage = Usage::new(&patterns);
+        usage
+            .add(&Puzzle::from_grid(vec![vec![0, -1, -1], vec![-1; 3], vec![-1; 3]]))
+            .unwrap();
+        usage
+            .add(&Puzzle::from_grid(vec![
+                vec![-1; 4],
+                vec![3, 3, -1, -1],
+                vec![-1; 4],
+                vec![-1, -1, -1, 1],
+            ]))
+            .unwrap();
+        assert_eq!(usage.puzzles, 2);
+
+        let threes = PatternStats {
+            fired: 1,
+            facts: 1,
+            only_way: vec![1],
+        };
+        assert_eq!(usage.stats["threes"], threes);
+        // Either zero pattern does the other's job.
+        let (zero, nought) = (&usage.stats["zero"], &usage.stats["nought"]);
+        assert_eq!(zero.fired + nought.fired, 1);
+        assert_eq!(zero.facts + nought.facts, 4);
+        assert!(zero.only_way.is_empty() && nought.only_way.is_empty());
+
+        let never: Vec<&str> = usage.never_fired().collect();
+        assert_eq!(never.len(), 2);
+        assert!(never.contains(&"ones"));
+    }
+}
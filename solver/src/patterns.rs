@@ -1,13 +1,23 @@
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
 
-use crate::data::baked_in_patterns::patterns;
+use crate::colouring::{colouring_edges, Conflict};
+use crate::data::baked_in_patterns::{patterns, push};
 use crate::data::pattern::Cell;
 use crate::data::pattern::CellWindow;
 use crate::data::pattern::Edge;
 use crate::data::pattern::Horizontals;
+use crate::data::pattern::Pattern;
 use crate::data::pattern::PatternSolution;
 use crate::data::pattern::Verticals;
+use crate::data::pattern_file::parse_patterns;
 use crate::data::puzzle::Puzzle;
+use crate::solve_common::satisfies_clues;
 
 #[allow(unused_imports)]
 use crate::data::solution::format_puzzle;
@@ -17,119 +27,1077 @@ use crate::data::solution::format_puzzle;
 /// like this: |3|3|. This is a non-bruteforce part of the solution, where we are only limited
 /// by how advanced the patterns are. Ideally, any puzzle that does not contain bifurcation,
 /// should be solved only by deducing these facts.
-pub fn find_facts(puzzle: &Puzzle) -> HashMap<usize, bool> {
-    #[allow(unused_variables)]
-    let mut facts_map = HashMap::new();
-
-    let patterns = patterns();
-
-    let horizontal_edge_count = (1 + puzzle.xsize) * puzzle.ysize;
-    let vertical_edge_count = puzzle.xsize * (1 + puzzle.ysize);
-
-    let mut options = vec![Edge::Unknown; horizontal_edge_count + vertical_edge_count];
-
-    let mut found_facts = true;
-    let mut ctr = 0;
-    while found_facts && ctr < 1000 {
-        found_facts = false;
-        ctr += 1;
-
-        for i in -1..puzzle.xsize as isize {
-            for j in -1..puzzle.ysize as isize {
-                let window = cell_window(puzzle, i, j);
-                let hor_edges = horizontal_edge_window(puzzle, &options, i, j);
-                let vert_edges = vertical_edge_window(puzzle, &options, i, j);
-
-                for (pattern_name, pattern_solution) in &patterns {
-                    let _ = pattern_name;
-                    if pattern_solution.try_match(&window, &hor_edges, &vert_edges) {
-                        let current_size = facts_map.len();
-                        remember_facts(
-                            &mut facts_map,
-                            &mut options,
-                            pattern_solution,
-                            puzzle,
-                            i,
-                            j,
-                        );
-                        if facts_map.len() > current_size {
-                            // println!("{prefix}found new {pattern_name} at {i} {j}");
-                            found_facts = true;
-
-                            let mut base_edges =
-                                vec![Edge::Unknown; horizontal_edge_count + vertical_edge_count];
-                            for (&k, &v) in facts_map.iter() {
-                                base_edges[k] = if v { Edge::Filled } else { Edge::Empty };
-                            }
+///
+/// A [`Contradiction`] means the puzzle has no solution, or one of the patterns is wrong.
+pub fn find_facts(
+    puzzle: &Puzzle,
+    patterns: &PatternSet,
+) -> Result<HashMap<usize, bool>, Contradiction> {
+    extend_facts(puzzle, patterns, &vec![Edge::Unknown; puzzle.edge_count()])
+}
 
-                            // println!("{prefix}after this step:\n{}", format_puzzle(puzzle, &base_edges));
-                        }
-                    };
+/// Like [`find_facts`], but starts from a partly solved board. The known edges of `edges`
+/// are part of the returned facts.
+pub fn extend_facts(
+    puzzle: &Puzzle,
+    patterns: &PatternSet,
+    edges: &[Edge],
+) -> Result<HashMap<usize, bool>, Contradiction> {
+    run_patterns(puzzle, patterns, edges, usize::MAX, None)
+}
+
+/// The patterns the engine matches, under `<name>-<n>` for every rotation and reflection
+/// `n` of pattern `<name>`. The default is the baked-in set; clones share the patterns.
+#[derive(Clone)]
+pub struct PatternSet {
+    patterns: Arc<BTreeMap<String, PatternSolution>>,
+    /// [`Matcher`]s of `patterns`, in the same order, made when the engine first runs.
+    matchers: Arc<OnceLock<Vec<Matcher>>>,
+}
+
+impl PatternSet {
+    /// The patterns of [`crate::data::baked_in_patterns`], parsed once: the native backend
+    /// runs the pattern engine at every search node.
+    pub fn baked_in() -> PatternSet {
+        static PATTERNS: OnceLock<PatternSet> = OnceLock::new();
+        PATTERNS
+            .get_or_init(|| PatternSet::new(patterns()))
+            .clone()
+    }
+
+    pub fn empty() -> PatternSet {
+        PatternSet::new(BTreeMap::new())
+    }
+
+    fn new(patterns: BTreeMap<String, PatternSolution>) -> PatternSet {
+        PatternSet {
+            patterns: Arc::new(patterns),
+            matchers: Arc::default(),
+        }
+    }
+
+    /// Add each named pattern with all of its rotations. A pattern replaces the one of the
+    /// same name already in the set.
+    pub fn add(&mut self, named: Vec<(String, PatternSolution)>) {
+        let map = Arc::make_mut(&mut self.patterns);
+        for (name, pattern) in named {
+            map.retain(|key, _| {
+                *key != name && key.rsplit_once('-').is_none_or(|(base, _)| base != name)
+            });
+            push(map, &name, &pattern);
+        }
+        self.matchers = Arc::default();
+    }
+
+    /// Add the patterns of the file at `path`, see [`crate::data::pattern_file`].
+    pub fn load(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let named = parse_patterns(&text).map_err(|e| format!("{}:{e}", path.display()))?;
+        self.add(named);
+        Ok(())
+    }
+
+    /// Leave out the pattern `name` with all of its rotations.
+    pub fn remove(&mut self, name: &str) {
+        Arc::make_mut(&mut self.patterns).retain(|key, _| pattern_name(key) != name);
+        self.matchers = Arc::default();
+    }
+
+    /// Every rotation of every pattern, by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &PatternSolution)> {
+        self.patterns.iter()
+    }
+
+    /// The name of every pattern, once for all of its rotations.
+    pub fn names(&self) -> BTreeSet<&str> {
+        self.patterns.keys().map(|key| pattern_name(key)).collect()
+    }
+
+    fn matchers(&self) -> &[Matcher] {
+        self.matchers
+            .get_or_init(|| self.patterns.values().map(Matcher::new).collect())
+    }
+}
+
+/// A pattern the way [`run_patterns`] matches it, against windows from [`fill_cells`] and
+/// [`fill_edges`].
+struct Matcher {
+    size: Size,
+    /// The cells that aren't `Any`, by their place in the cells of a window, row by row.
+    cells: Vec<(usize, Cell)>,
+    /// The input edges that aren't `Any`, by their place in the edges of a window.
+    inputs: Vec<(usize, Edge)>,
+    /// See [`set_edges`].
+    outputs: Vec<(WindowEdge, bool)>,
+    /// See [`small_loop`].
+    small_loop: bool,
+}
+
+impl Matcher {
+    fn new(pattern: &PatternSolution) -> Matcher {
+        let input = &pattern.input;
+        let edges = input.horizontals.iter().chain(&input.verticals).flatten();
+        Matcher {
+            size: pattern.size(),
+            cells: pattern
+                .cells
+                .iter()
+                .flatten()
+                .copied()
+                .enumerate()
+                .filter(|&(_, cell)| cell != Cell::Any)
+                .collect(),
+            inputs: edges
+                .copied()
+                .enumerate()
+                .filter(|&(_, edge)| edge != Edge::Any)
+                .collect(),
+            outputs: set_edges(pattern),
+            small_loop: small_loop(pattern),
+        }
+    }
+
+    /// The clues of a window of the same size match. See [`PatternSolution::cells_match`].
+    fn cells_match(&self, cells: &[Cell]) -> bool {
+        self.cells.iter().all(|&(k, cell)| cell.matches(&cells[k]))
+    }
+
+    /// The input edges match the edges of a window of the same size, whatever its clues.
+    /// See [`PatternSolution::matches_input`].
+    fn edges_match(&self, edges: &[Edge]) -> bool {
+        self.inputs.iter().all(|&(k, input)| input.matches(&edges[k]))
+    }
+}
+
+impl Default for PatternSet {
+    fn default() -> Self {
+        PatternSet::baked_in()
+    }
+}
+
+impl fmt::Debug for PatternSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PatternSet({} patterns)", self.patterns.len())
+    }
+}
+
+/// Name of the known edges of the board a run starts from, in a [`Contradiction`].
+pub const GIVEN: &str = "given";
+
+/// One rule giving an edge a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deduction {
+    /// Pattern name, [`GIVEN`], or the name of a global rule.
+    pub pattern: String,
+    /// Same as [`Step::window`]; the top or left end of the edge for [`GIVEN`].
+    pub window: (isize, isize),
+    pub filled: bool,
+}
+
+/// Two deductions that disagree on one edge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contradiction {
+    pub edge: usize,
+    /// The one that set the edge.
+    pub first: Deduction,
+    /// The one that wanted the other value.
+    pub second: Deduction,
+}
+
+impl fmt::Display for Contradiction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = |d: &Deduction| if d.filled { "filled" } else { "empty" };
+        let (first, second) = (&self.first, &self.second);
+        write!(
+            f,
+            "edge {}: {:?} at {:?} says {}, but {:?} at {:?} says {}",
+            self.edge,
+            first.pattern,
+            first.window,
+            value(first),
+            second.pattern,
+            second.window,
+            value(second)
+        )
+    }
+}
+
+impl Error for Contradiction {}
+
+/// One pattern match that set new edges.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub pattern: String,
+    /// Centre cell of the matched window, `(row, column)`, the later of the two middle rows
+    /// or columns when there is an even number of them. `-1` is the row or column just
+    /// outside the grid. For [`LOOP_CLOSURE`] and [`COLOURING`], the vertex at the top or left
+    /// end of the edge.
+    pub window: (isize, isize),
+    /// The edges this step set, with their values.
+    pub edges: Vec<(usize, bool)>,
+    pub before: Vec<Edge>,
+    pub after: Vec<Edge>,
+}
+
+/// Like [`find_facts`], but also returns every step that led to the facts, in order.
+pub fn find_facts_traced(
+    puzzle: &Puzzle,
+    patterns: &PatternSet,
+) -> Result<(HashMap<usize, bool>, Vec<Step>), Contradiction> {
+    let mut steps = vec![];
+    let facts = run_patterns(
+        puzzle,
+        patterns,
+        &vec![Edge::Unknown; puzzle.edge_count()],
+        usize::MAX,
+        Some(&mut steps),
+    )?;
+    Ok((facts, steps))
+}
+
+/// A deduction to show a player next, see [`next_hint`].
+#[derive(Debug, Clone)]
+pub struct Hint {
+    pub pattern: String,
+    /// Centre cell of the matched window, as in [`Step::window`].
+    pub window: (isize, isize),
+    /// The edges the player can settle, with their values.
+    pub edges: Vec<(usize, bool)>,
+    /// [`PatternSolution::constraints`] of the pattern: how hard the hint is to spot.
+    pub constraints: usize,
+    pub text: String,
+}
+
+/// The simplest deduction the patterns allow from the player's `current_edges`: the match
+/// with the fewest [`PatternSolution::constraints`] that settles an unknown edge, or else an
+/// edge [`loop_closure_edges`] or the colouring rules settle, whichever ranks lower. Only
+/// `Filled` and `Empty` edges count as marked. `None` when the patterns are stuck.
+pub fn next_hint(puzzle: &Puzzle, patterns: &PatternSet, current_edges: &[Edge]) -> Option<Hint> {
+    let board: Vec<Edge> = current_edges
+        .iter()
+        .map(|&e| match e {
+            Edge::Filled => Edge::Filled,
+            Edge::Empty | Edge::EmptyStrict => Edge::Empty,
+            _ => Edge::Unknown,
+        })
+        .collect();
+
+    let mut best: Option<(usize, Hint)> = None;
+    let sizes: BTreeSet<Size> = patterns.iter().map(|(_, pattern)| pattern.size()).collect();
+    let span = clue_span(puzzle);
+    for size in sizes {
+        for centre in centres(puzzle, size) {
+            let window = cell_window(puzzle, centre, size);
+            let hor_edges = horizontal_edge_window(puzzle, &board, centre, size);
+            let vert_edges = vertical_edge_window(puzzle, &board, centre, size);
+            let fits = loop_fits(span, centre, size);
+            for ((pattern_name, pattern_solution), matcher) in
+                patterns.iter().zip(patterns.matchers())
+            {
+                let cost = pattern_solution.constraints();
+                if pattern_solution.size() != size
+                    || (fits && matcher.small_loop)
+                    || best.as_ref().is_some_and(|(c, _)| *c <= cost)
+                    || !pattern_solution.try_match(&window, &hor_edges, &vert_edges)
+                {
+                    continue;
                 }
+                let edges: Vec<(usize, bool)> = pattern_outputs(pattern_solution, puzzle, centre)
+                    .into_iter()
+                    .filter(|&(e, _)| board[e] == Edge::Unknown)
+                    .collect();
+                if edges.is_empty() {
+                    continue;
+                }
+                let hint = Hint {
+                    text: hint_text(pattern_name, centre, &edges),
+                    pattern: pattern_name.clone(),
+                    window: centre,
+                    edges,
+                    constraints: cost,
+                };
+                best = Some((cost, hint));
             }
         }
     }
+    if best
+        .as_ref()
+        .is_none_or(|(c, _)| *c > LOOP_CLOSURE_CONSTRAINTS)
+    {
+        if let Some(&e) = loop_closure_edges(puzzle, &board).first() {
+            let ((i0, j0), (i1, j1)) = puzzle.edge_endpoints(e);
+            let hint = Hint {
+                pattern: String::from(LOOP_CLOSURE),
+                window: (i0 as isize, j0 as isize),
+                edges: vec![(e, false)],
+                constraints: LOOP_CLOSURE_CONSTRAINTS,
+                text: format!(
+                    "A line from ({i0}, {j0}) to ({i1}, {j1}) would close a loop that leaves \
+                     something out, so cross it out."
+                ),
+            };
+            best = Some((LOOP_CLOSURE_CONSTRAINTS, hint));
+        }
+    }
+    if best.is_none() {
+        let colouring = colouring_edges(puzzle, &board).unwrap_or_default();
+        if let Some(&(e, filled)) = colouring.first() {
+            let ((i0, j0), (i1, j1)) = puzzle.edge_endpoints(e);
+            let (sides, action) = if filled {
+                ("different sides", "draw it")
+            } else {
+                ("the same side", "cross it out")
+            };
+            let hint = Hint {
+                pattern: String::from(COLOURING),
+                window: (i0 as isize, j0 as isize),
+                edges: vec![(e, filled)],
+                constraints: COLOURING_CONSTRAINTS,
+                text: format!(
+                    "The cells along the edge from ({i0}, {j0}) to ({i1}, {j1}) are on {sides} \
+                     of the loop, so {action}."
+                ),
+            };
+            best = Some((COLOURING_CONSTRAINTS, hint));
+        }
+    }
+    best.map(|(_, hint)| hint)
+}
+
+/// The name of the pattern that `key` in a [`PatternSet`] is a rotation of: every rotation
+/// is named `<name>-<n>`.
+pub fn pattern_name(key: &str) -> &str {
+    key.rsplit_once('-').map_or(key, |(name, _)| name)
+}
+
+fn hint_text(key: &str, (i, j): (isize, isize), edges: &[(usize, bool)]) -> String {
+    let name = pattern_name(key);
+    let lines = edges.iter().filter(|(_, filled)| *filled).count();
+    let crosses = edges.len() - lines;
+    let action = match (lines, crosses) {
+        (0, c) => format!("cross out {c} edge(s)"),
+        (l, 0) => format!("draw {l} line(s)"),
+        (l, c) => format!("draw {l} line(s) and cross out {c} edge(s)"),
+    };
+    format!("Look around cell ({i}, {j}): {name}, so {action}.")
+}
+
+/// Match every pattern with at most `max_constraints` [`PatternSolution::constraints`] at
+/// every window until nothing new turns up, recording each match that set an edge in `steps`.
+///
+/// Every window is checked once, and after that only windows around edges that changed are
+/// checked again. The global rules run whenever the windows have nothing left to say.
+///
+/// A pattern whose clues and inputs match while one of its outputs is already known to have
+/// the other value stops the run with a [`Contradiction`].
+pub(crate) fn run_patterns(
+    puzzle: &Puzzle,
+    patterns: &PatternSet,
+    edges: &[Edge],
+    max_constraints: usize,
+    steps: Option<&mut Vec<Step>>,
+) -> Result<HashMap<usize, bool>, Contradiction> {
+    Engine::new(puzzle, patterns, max_constraints).run(edges, steps)
+}
+
+/// [`run_patterns`] set up for one puzzle, to run on board after board of it.
+pub(crate) struct Engine<'a> {
+    pub(crate) puzzle: &'a Puzzle,
+    windows: Windows<'a>,
+    max_constraints: usize,
+}
+
+impl<'a> Engine<'a> {
+    pub(crate) fn new(
+        puzzle: &'a Puzzle,
+        patterns: &'a PatternSet,
+        max_constraints: usize,
+    ) -> Engine<'a> {
+        Engine {
+            puzzle,
+            windows: Windows::new(puzzle, patterns, max_constraints),
+            max_constraints,
+        }
+    }
+
+    /// See [`run_patterns`].
+    pub(crate) fn run(
+        &self,
+        edges: &[Edge],
+        steps: Option<&mut Vec<Step>>,
+    ) -> Result<HashMap<usize, bool>, Contradiction> {
+        let mut worklist = Worklist::new(self.windows.places.len());
+        self.run_from(edges, &mut worklist, steps, false)
+    }
+
+    /// Like [`Engine::run`], on a board the engine was done with until the edges of
+    /// `changed` were set: only the windows around them are checked at first.
+    pub(crate) fn rerun(
+        &self,
+        edges: &[Edge],
+        changed: &[usize],
+        steps: Option<&mut Vec<Step>>,
+    ) -> Result<HashMap<usize, bool>, Contradiction> {
+        let mut unchecked = Unchecked(Worklist::empty(self.windows.places.len()));
+        self.changed(&mut unchecked, changed);
+        self.run_from(edges, &mut unchecked.0, steps, false)
+    }
+
+    /// Every window of the puzzle, for [`Engine::first_step`].
+    pub(crate) fn unchecked(&self) -> Unchecked {
+        Unchecked(Worklist::new(self.windows.places.len()))
+    }
+
+    /// The windows around `edges`, which changed on the board, need checking again.
+    pub(crate) fn changed(&self, unchecked: &mut Unchecked, edges: &[usize]) {
+        for &e in edges {
+            unchecked.0.extend(self.windows.around_edge(self.puzzle, e));
+        }
+    }
+
+    /// The first step [`Engine::run`] takes on `edges`, `None` when nothing matches. Only
+    /// the `unchecked` windows are looked at, in order, and those that don't match are
+    /// checked off. The rest have to be windows nothing matched in since their edges were
+    /// last set.
+    pub(crate) fn first_step(
+        &self,
+        edges: &[Edge],
+        unchecked: &mut Unchecked,
+    ) -> Result<Option<Step>, Contradiction> {
+        unchecked.0.queue.make_contiguous().sort_unstable();
+        let mut steps = vec![];
+        self.run_from(edges, &mut unchecked.0, Some(&mut steps), true)?;
+        Ok(steps.pop())
+    }
+
+    /// Check the windows of `worklist`, and those around every edge set on the way, until
+    /// none is left. With `first_only`, stops after the first step, and leaves the windows it
+    /// didn't get to in `worklist`.
+    fn run_from(
+        &self,
+        edges: &[Edge],
+        worklist: &mut Worklist,
+        mut steps: Option<&mut Vec<Step>>,
+        first_only: bool,
+    ) -> Result<HashMap<usize, bool>, Contradiction> {
+        let (puzzle, windows, max_constraints) = (self.puzzle, &self.windows, self.max_constraints);
+        let mut facts_map: HashMap<usize, bool> = edges
+            .iter()
+            .enumerate()
+            .filter(|(_, &e)| e == Edge::Filled || e == Edge::Empty)
+            .map(|(ix, &e)| (ix, e == Edge::Filled))
+            .collect();
+        let mut set_by: Vec<(&str, (isize, isize))> = (0..edges.len())
+            .map(|e| {
+                let ((i, j), _) = puzzle.edge_endpoints(e);
+                (GIVEN, (i as isize, j as isize))
+            })
+            .collect();
+
+        let mut options = edges.to_vec();
+        let mut window_edges = vec![];
+
+        loop {
+            while let Some(w) = worklist.pop() {
+                let (centre, size) = windows.places[w];
+                fill_edges(puzzle, &options, centre, size, &mut window_edges);
+
+                for &(pattern_name, matcher) in &windows.candidates[windows.kind[w]] {
+                    if !matcher.edges_match(&window_edges) {
+                        continue;
+                    }
+                    let before = steps.as_ref().map(|_| options.clone());
+                    let new_facts = remember_facts(
+                        &mut facts_map,
+                        &mut options,
+                        &mut set_by,
+                        (pattern_name, matcher),
+                        puzzle,
+                        centre,
+                    )?;
+                    if new_facts.is_empty() {
+                        continue;
+                    }
+                    for &(e, _) in &new_facts {
+                        worklist.extend(windows.around_edge(puzzle, e));
+                    }
+                    if let (Some(steps), Some(before)) = (steps.as_mut(), before) {
+                        steps.push(Step {
+                            pattern: pattern_name.clone(),
+                            window: centre,
+                            edges: new_facts,
+                            before,
+                            after: options.clone(),
+                        });
+                    }
+                    if first_only {
+                        return Ok(facts_map);
+                    }
+                }
+            }
+
+            for (name, rank, rule) in GLOBAL_RULES {
+                if max_constraints < rank || !worklist.is_empty() {
+                    continue;
+                }
+                let settled = rule(puzzle, &options)
+                    .map_err(|c| broken_rule(puzzle, &options, &set_by, name, c.edge))?;
+                for (e, filled) in settled {
+                    let before = steps.as_ref().map(|_| options.clone());
+                    facts_map.insert(e, filled);
+                    options[e] = if filled { Edge::Filled } else { Edge::Empty };
+                    worklist.extend(windows.around_edge(puzzle, e));
+                    let ((i, j), _) = puzzle.edge_endpoints(e);
+                    set_by[e] = (name, (i as isize, j as isize));
+                    if let (Some(steps), Some(before)) = (steps.as_mut(), before) {
+                        steps.push(Step {
+                            pattern: String::from(name),
+                            window: (i as isize, j as isize),
+                            edges: vec![(e, filled)],
+                            before,
+                            after: options.clone(),
+                        });
+                    }
+                    if first_only {
+                        return Ok(facts_map);
+                    }
+                }
+            }
+            if worklist.is_empty() {
+                break;
+            }
+        }
+
+        Ok(facts_map)
+    }
+}
+
+/// Windows an [`Engine`] has yet to check on a board.
+pub(crate) struct Unchecked(Worklist);
+
+/// Windows left to check, each at most once at a time.
+struct Worklist {
+    queue: VecDeque<usize>,
+    queued: Vec<bool>,
+}
+
+impl Worklist {
+    /// Every one of `windows` windows, in order.
+    fn new(windows: usize) -> Worklist {
+        Worklist {
+            queue: (0..windows).collect(),
+            queued: vec![true; windows],
+        }
+    }
+
+    /// None of `windows` windows.
+    fn empty(windows: usize) -> Worklist {
+        Worklist {
+            queue: VecDeque::new(),
+            queued: vec![false; windows],
+        }
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        let w = self.queue.pop_front()?;
+        self.queued[w] = false;
+        Some(w)
+    }
+
+    fn extend(&mut self, windows: impl Iterator<Item = usize>) {
+        for w in windows {
+            if !self.queued[w] {
+                self.queued[w] = true;
+                self.queue.push_back(w);
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+/// Every window of a puzzle, for every size of pattern there is, with the patterns its clues
+/// allow. The windows of one size are in the row-major order of [`centres`], and come after
+/// those of smaller sizes. The clues never change, so only the edges are left to match.
+struct Windows<'a> {
+    /// The centre and size of each window.
+    places: Vec<((isize, isize), Size)>,
+    /// Index into `candidates` for each window. Windows with the same clues share a list.
+    kind: Vec<usize>,
+    candidates: Vec<Vec<(&'a String, &'a Matcher)>>,
+    /// Each window size, with the index of its first window.
+    sizes: Vec<(Size, usize)>,
+}
+
+impl<'a> Windows<'a> {
+    fn new(puzzle: &Puzzle, patterns: &'a PatternSet, max_constraints: usize) -> Windows<'a> {
+        let patterns: Vec<_> = patterns
+            .iter()
+            .zip(patterns.matchers())
+            .filter(|((_, pattern), _)| {
+                // The unbounded run is the hot path of the native backend.
+                max_constraints == usize::MAX || pattern.constraints() <= max_constraints
+            })
+            .map(|((name, _), matcher)| (name, matcher))
+            .collect();
+        let sizes: BTreeSet<Size> = patterns.iter().map(|(_, matcher)| matcher.size).collect();
+        let span = clue_span(puzzle);
+        let mut places = vec![];
+        let mut kind = vec![];
+        let mut candidates = vec![];
+        let sizes = sizes
+            .into_iter()
+            .map(|size| {
+                let first = places.len();
+                let mut kinds: HashMap<(Vec<Cell>, bool), usize> = HashMap::new();
+                let mut window = vec![];
+                for centre in centres(puzzle, size) {
+                    places.push((centre, size));
+                    fill_cells(puzzle, centre, size, &mut window);
+                    let key = (window, loop_fits(span, centre, size));
+                    if let Some(&k) = kinds.get(&key) {
+                        kind.push(k);
+                        window = key.0;
+                        continue;
+                    }
+                    kind.push(candidates.len());
+                    candidates.push(
+                        patterns
+                            .iter()
+                            .copied()
+                            .filter(|(_, matcher)| {
+                                matcher.size == size
+                                    && !(key.1 && matcher.small_loop)
+                                    && matcher.cells_match(&key.0)
+                            })
+                            .collect(),
+                    );
+                    window = key.0.clone();
+                    kinds.insert(key, candidates.len() - 1);
+                }
+                (size, first)
+            })
+            .collect();
+        Windows {
+            places,
+            kind,
+            candidates,
+            sizes,
+        }
+    }
+
+    /// Windows whose edge windows contain edge `e`.
+    fn around_edge(&self, puzzle: &Puzzle, e: usize) -> impl Iterator<Item = usize> + '_ {
+        let ((r, c), (r1, _)) = puzzle.edge_endpoints(e);
+        let horizontal = r == r1;
+        let (r, c) = (r as isize, c as isize);
+        let (xsize, ysize) = (puzzle.xsize as isize, puzzle.ysize as isize);
+        self.sizes.iter().flat_map(move |&((rows, cols), first)| {
+            let (rows, cols) = (rows as isize, cols as isize);
+            // A horizontal edge sits between two rows of a window, in any of its columns, and
+            // a vertical one between two columns, in any of its rows.
+            let (tops, lefts) = if horizontal {
+                ((r + 1 - rows, r - 1), (c + 1 - cols, c))
+            } else {
+                ((r + 1 - rows, r), (c + 1 - cols, c - 1))
+            };
+            // Windows reach at most all but one row or column past the grid.
+            let tops = tops.0.max(1 - rows)..=tops.1.min(xsize - 1);
+            let lefts = lefts.0.max(1 - cols)..=lefts.1.min(ysize - 1);
+            let width = ysize + cols - 1;
+            tops.flat_map(move |t| {
+                lefts
+                    .clone()
+                    .map(move |l| first + ((t + rows - 1) * width + l + cols - 1) as usize)
+            })
+        })
+    }
+}
+
+/// Name of the cell colouring rules (see [`crate::colouring`]) in [`Step`]s and [`Hint`]s.
+pub const COLOURING: &str = "colouring";
+
+/// Where colouring ranks next to [`PatternSolution::constraints`]: it takes keeping track
+/// of both sides of the loop across the board, the hardest technique the engine has.
+pub const COLOURING_CONSTRAINTS: usize = 6;
+
+/// A rule that looks at the whole board rather than one window, returning the unknown edges
+/// it settles.
+type GlobalRule = fn(&Puzzle, &[Edge]) -> Result<Vec<(usize, bool)>, Conflict>;
+
+/// The global rules, with their names and ranks, in the order the engine tries them once no
+/// window matches.
+const GLOBAL_RULES: [(&str, usize, GlobalRule); 2] = [
+    (LOOP_CLOSURE, LOOP_CLOSURE_CONSTRAINTS, |p, edges| {
+        Ok(loop_closure_edges(p, edges)
+            .into_iter()
+            .map(|e| (e, false))
+            .collect())
+    }),
+    (COLOURING, COLOURING_CONSTRAINTS, colouring_edges),
+];
+
+/// Name of the global loop-closure rule in [`Step`]s and [`Hint`]s.
+pub const LOOP_CLOSURE: &str = "loop closure";
+
+/// Where the loop-closure rule ranks next to [`PatternSolution::constraints`]: following
+/// whole paths across the board is harder than spotting any local pattern.
+pub const LOOP_CLOSURE_CONSTRAINTS: usize = 5;
+
+/// Unknown edges that would close a path of filled edges into a loop that is not the whole
+/// solution: either some filled edge is left off that loop, or some clue is not satisfied
+/// by it. Such an edge has to be empty.
+pub fn loop_closure_edges(puzzle: &Puzzle, edges: &[Edge]) -> Vec<usize> {
+    let cols = puzzle.ysize + 1;
+    let vertex = |(i, j): (usize, usize)| i * cols + j;
+    let mut paths = Paths::new((puzzle.xsize + 1) * cols);
+    let mut filled = 0;
+    for e in (0..edges.len()).filter(|&e| edges[e] == Edge::Filled) {
+        let (a, b) = puzzle.edge_endpoints(e);
+        paths.join(vertex(a), vertex(b));
+        filled += 1;
+    }
+
+    let mut res = vec![];
+    for e in (0..edges.len()).filter(|&e| edges[e] == Edge::Unknown) {
+        let (a, b) = puzzle.edge_endpoints(e);
+        let (a, b) = (paths.find(vertex(a)), paths.find(vertex(b)));
+        if a != b {
+            continue;
+        }
+        let whole_solution = paths.edges[a] == filled && {
+            let mut closed = edges.to_vec();
+            closed[e] = Edge::Filled;
+            satisfies_clues(puzzle, &closed)
+        };
+        if !whole_solution {
+            res.push(e);
+        }
+    }
+    res
+}
+
+/// Union-find over vertices, counting the filled edges of each path fragment.
+pub(crate) struct Paths {
+    parent: Vec<usize>,
+    /// Filled edges of the fragment, kept at its root.
+    pub(crate) edges: Vec<usize>,
+}
+
+impl Paths {
+    pub(crate) fn new(vertices: usize) -> Paths {
+        Paths {
+            parent: (0..vertices).collect(),
+            edges: vec![0; vertices],
+        }
+    }
 
-    facts_map
+    pub(crate) fn find(&mut self, x: usize) -> usize {
+        let p = self.parent[x];
+        if p == x {
+            return x;
+        }
+        let root = self.find(p);
+        self.parent[x] = root;
+        root
+    }
+
+    /// Add the filled edge between `a` and `b`.
+    pub(crate) fn join(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            self.parent[ra] = rb;
+            self.edges[rb] += self.edges[ra];
+        }
+        self.edges[rb] += 1;
+    }
 }
 
-fn remember_facts(
+/// Materialize a facts map into a full edge vector, leaving unknown edges as [`Edge::Unknown`].
+pub fn facts_to_edges(puzzle: &Puzzle, facts: &HashMap<usize, bool>) -> Vec<Edge> {
+    let mut edges = vec![Edge::Unknown; puzzle.edge_count()];
+    for (&k, &v) in facts {
+        edges[k] = if v { Edge::Filled } else { Edge::Empty };
+    }
+    edges
+}
+
+/// Copy the outputs of the pattern `matcher` matched at `centre` into the facts. Returns the
+/// edges that were not known yet, or the first output that disagrees with a known edge.
+fn remember_facts<'a>(
     // map <edge_ix> -> <value>
     facts_map: &mut HashMap<usize, bool>,
     // list of all edges state
     opts: &mut [Edge],
-    pattern: &PatternSolution,
+    // which rule set each edge, and where
+    set_by: &mut [(&'a str, (isize, isize))],
+    (name, matcher): (&'a str, &Matcher),
     puzzle: &Puzzle,
-    i: isize,
-    j: isize,
-) {
-    for i_w in 0..2 {
-        for j_w in 0..3 {
-            let hor_j_ix = j + j_w - 1;
-            let hor_edge = pattern.output.horizontals[i_w as usize][j_w as usize];
-            if hor_edge != Edge::Empty && hor_edge != Edge::Filled {
-                continue;
+    centre: (isize, isize),
+) -> Result<Vec<(usize, bool)>, Contradiction> {
+    let mut new_facts = vec![];
+    let at = top_left(centre, matcher.size);
+    for (edge_ix, filled) in place_outputs(&matcher.outputs, puzzle, at) {
+        match facts_map.entry(edge_ix) {
+            Entry::Vacant(e) => {
+                e.insert(filled);
+                opts[edge_ix] = if filled { Edge::Filled } else { Edge::Empty };
+                set_by[edge_ix] = (name, centre);
+                new_facts.push((edge_ix, filled));
             }
-            if hor_j_ix >= 0 && (i + i_w) >= 0 && (hor_j_ix as usize) < puzzle.ysize {
-                let edge_ix = puzzle.edge_ix((i + i_w) as usize, hor_j_ix as usize, true);
-                if let std::collections::hash_map::Entry::Vacant(e) = facts_map.entry(edge_ix) {
-                    e.insert(hor_edge == Edge::Filled);
-                    opts[edge_ix] = hor_edge;
-                }
+            Entry::Occupied(e) if *e.get() != filled => {
+                let (first, window) = set_by[edge_ix];
+                return Err(Contradiction {
+                    edge: edge_ix,
+                    first: Deduction {
+                        pattern: String::from(first),
+                        window,
+                        filled: !filled,
+                    },
+                    second: Deduction {
+                        pattern: String::from(name),
+                        window: centre,
+                        filled,
+                    },
+                });
             }
+            Entry::Occupied(_) => {}
         }
     }
+    Ok(new_facts)
+}
 
-    for i_w in 0..3 {
-        for j_w in 0..2 {
-            let ver_ix = i + i_w - 1;
+/// Global rule `name` can't make sense of `edges` at edge `e`. When `e` is known, the rule
+/// wanted the other value; otherwise neither value fits.
+fn broken_rule(
+    puzzle: &Puzzle,
+    edges: &[Edge],
+    set_by: &[(&str, (isize, isize))],
+    name: &str,
+    e: usize,
+) -> Contradiction {
+    let ((i, j), _) = puzzle.edge_endpoints(e);
+    let rule = |filled| Deduction {
+        pattern: String::from(name),
+        window: (i as isize, j as isize),
+        filled,
+    };
+    let (first, second) = match edges[e] {
+        Edge::Filled | Edge::Empty | Edge::EmptyStrict => {
+            let (pattern, window) = set_by[e];
+            let filled = edges[e] == Edge::Filled;
+            let first = Deduction {
+                pattern: String::from(pattern),
+                window,
+                filled,
+            };
+            (first, rule(!filled))
+        }
+        _ => (rule(true), rule(false)),
+    };
+    Contradiction {
+        edge: e,
+        first,
+        second,
+    }
+}
 
-            let ver_edge = pattern.output.verticals[i_w as usize][j_w as usize];
-            if ver_edge != Edge::Empty && ver_edge != Edge::Filled {
-                continue;
-            }
-            if ver_edge == Edge::OutOfBounds {
-                continue;
+/// The edges `pattern` sets when it matches at `centre`, with their values. Outputs that
+/// fall outside the grid are left out.
+fn pattern_outputs(
+    pattern: &PatternSolution,
+    puzzle: &Puzzle,
+    centre: (isize, isize),
+) -> Vec<(usize, bool)> {
+    place_outputs(&set_edges(pattern), puzzle, top_left(centre, pattern.size()))
+}
+
+/// An edge of a window, `(row, column, horizontal)` counted from its top left cell the way
+/// [`grid_edge`] counts from the grid's.
+type WindowEdge = (isize, isize, bool);
+
+/// The outputs of `pattern` that are `Filled` or `Empty`, with their values.
+fn set_edges(pattern: &PatternSolution) -> Vec<(WindowEdge, bool)> {
+    let output = &pattern.output;
+    let horizontals = output.horizontals.iter().enumerate().flat_map(|(i_w, row)| {
+        row.iter()
+            .enumerate()
+            .map(move |(j_w, &e)| ((1 + i_w as isize, j_w as isize, true), e))
+    });
+    let verticals = output.verticals.iter().enumerate().flat_map(|(i_w, row)| {
+        row.iter()
+            .enumerate()
+            .map(move |(j_w, &e)| ((i_w as isize, 1 + j_w as isize, false), e))
+    });
+    horizontals
+        .chain(verticals)
+        .filter(|&(_, e)| e == Edge::Empty || e == Edge::Filled)
+        .map(|(at, e)| (at, e == Edge::Filled))
+        .collect()
+}
+
+/// The grid edges of `outputs` in the window with its top left cell at `(top, left)`, with
+/// their values. Outputs that fall outside the grid are left out.
+fn place_outputs(
+    outputs: &[(WindowEdge, bool)],
+    puzzle: &Puzzle,
+    (top, left): (isize, isize),
+) -> Vec<(usize, bool)> {
+    outputs
+        .iter()
+        .filter_map(|&((i, j, is_horizontal), filled)| {
+            grid_edge(puzzle, top + i, left + j, is_horizontal).map(|edge_ix| (edge_ix, filled))
+        })
+        .collect()
+}
+
+/// Rows and columns of cells of a window, as in [`PatternSolution::size`].
+type Size = (usize, usize);
+
+/// The top left cell of the window of `size` around `centre`. With an even number of rows or
+/// columns, the centre is the later of the two middle ones.
+fn top_left((i, j): (isize, isize), (rows, cols): Size) -> (isize, isize) {
+    (i - (rows / 2) as isize, j - (cols / 2) as isize)
+}
+
+/// The first and last rows and columns with a clue above 0, `None` when there is none.
+type ClueSpan = Option<((isize, isize), (isize, isize))>;
+
+fn clue_span(p: &Puzzle) -> ClueSpan {
+    let mut span: ClueSpan = None;
+    for i in 0..p.xsize {
+        for j in 0..p.ysize {
+            if p.cells[i][j] > 0 {
+                let (i, j) = (i as isize, j as isize);
+                let ((top, bottom), (left, right)) = span.unwrap_or(((i, i), (j, j)));
+                span = Some(((top.min(i), bottom.max(i)), (left.min(j), right.max(j))));
             }
+        }
+    }
+    span
+}
 
-            if ver_ix >= 0 && j + j_w >= 0 && (ver_ix as usize) < puzzle.xsize {
-                let edge_ix = puzzle.edge_ix(ver_ix as usize, (j + j_w) as usize, false);
-                if let std::collections::hash_map::Entry::Vacant(e) = facts_map.entry(edge_ix) {
-                    e.insert(ver_edge == Edge::Filled);
-                    opts[edge_ix] = ver_edge;
+/// Whether the whole loop could be a loop around cells of the window of `size` around
+/// `centre`: every clue above 0 is in the window or next to it. Patterns with a
+/// [`small_loop`] aren't matched there. Only tiny or nearly empty puzzles have such windows.
+fn loop_fits(span: ClueSpan, centre: (isize, isize), (rows, cols): Size) -> bool {
+    let (top, left) = top_left(centre, (rows, cols));
+    span.is_none_or(|((first_row, last_row), (first_col, last_col))| {
+        first_row >= top - 1
+            && last_row <= top + rows as isize
+            && first_col >= left - 1
+            && last_col <= left + cols as isize
+    })
+}
+
+/// Some loop around cells of the window fits the clues and inputs of `pattern` but not its
+/// outputs, so the pattern is wrong on a puzzle whose whole loop is that one, like `1x2:33`
+/// for two 3s side by side. Windows of more than 16 cells aren't searched and count as
+/// having such a loop.
+pub(crate) fn small_loop(pattern: &PatternSolution) -> bool {
+    let (rows, cols) = pattern.size();
+    if rows * cols > 16 {
+        return true;
+    }
+    // Each loop is the border of the cells it goes around.
+    (1..1u32 << (rows * cols)).any(|set| {
+        let inside = |i: isize, j: isize| {
+            (0..rows as isize).contains(&i)
+                && (0..cols as isize).contains(&j)
+                && set >> (i as usize * cols + j as usize) & 1 == 1
+        };
+        let below = |i: isize, j: isize| inside(i, j) != inside(i + 1, j);
+        let right = |i: isize, j: isize| inside(i, j) != inside(i, j + 1);
+
+        let vertex = |r: isize, c: isize| r as usize * (cols + 1) + c as usize;
+        let mut paths = Paths::new((rows + 1) * (cols + 1));
+        for r in 0..=rows as isize {
+            for c in 0..=cols as isize {
+                let degree = [below(r - 1, c - 1), below(r - 1, c), right(r - 1, c - 1)]
+                    .into_iter()
+                    .chain([right(r, c - 1)])
+                    .filter(|&filled| filled)
+                    .count();
+                if degree > 2 {
+                    return false;
+                }
+                if c < cols as isize && below(r - 1, c) {
+                    paths.join(vertex(r, c), vertex(r, c + 1));
+                }
+                if r < rows as isize && right(r, c - 1) {
+                    paths.join(vertex(r, c), vertex(r + 1, c));
                 }
             }
         }
-    }
+        let mut roots: Vec<usize> = (0..(rows + 1) * (cols + 1)).map(|v| paths.find(v)).collect();
+        roots.sort();
+        roots.dedup();
+        if roots.iter().filter(|&&r| paths.edges[r] > 0).count() != 1 {
+            return false;
+        }
+
+        let clues_fit = (0..rows as isize).all(|i| {
+            (0..cols as isize).all(|j| {
+                let around = [below(i - 1, j), below(i, j), right(i, j - 1), right(i, j)];
+                let count = around.iter().filter(|&&filled| filled).count();
+                match pattern.cells[i as usize][j as usize] {
+                    Cell::OutOfBounds => !inside(i, j),
+                    Cell::Zero => count == 0,
+                    Cell::One => count == 1,
+                    Cell::Two => count == 2,
+                    Cell::Three => count == 3,
+                    Cell::Any | Cell::Nothing => true,
+                }
+            })
+        });
+        let edges = |side: &Pattern| {
+            let horizontals = side.horizontals.iter().enumerate().flat_map(|(i, row)| {
+                row.iter().enumerate().map(move |(j, &e)| (e, below(i as isize, j as isize)))
+            });
+            let verticals = side.verticals.iter().enumerate().flat_map(|(i, row)| {
+                row.iter().enumerate().map(move |(j, &e)| (e, right(i as isize, j as isize)))
+            });
+            horizontals.chain(verticals).collect::<Vec<_>>()
+        };
+        let inputs_fit = edges(&pattern.input).into_iter().all(|(e, filled)| match e {
+            Edge::Filled => filled,
+            Edge::Empty | Edge::EmptyStrict | Edge::OutOfBounds => !filled,
+            Edge::Unknown | Edge::Any => true,
+        });
+        let outputs_hold = edges(&pattern.output).into_iter().all(|(e, filled)| match e {
+            Edge::Filled => filled,
+            Edge::Empty => !filled,
+            _ => true,
+        });
+        clues_fit && inputs_fit && !outputs_hold
+    })
+}
+
+/// The centre of every window of `size` with at least one cell in the grid, in row-major
+/// order.
+fn centres(p: &Puzzle, (rows, cols): Size) -> impl Iterator<Item = (isize, isize)> {
+    let first_i = (rows / 2) as isize + 1 - rows as isize;
+    let first_j = (cols / 2) as isize + 1 - cols as isize;
+    let width = p.ysize + cols - 1;
+    (0..(p.xsize + rows - 1) * width)
+        .map(move |w| (first_i + (w / width) as isize, first_j + (w % width) as isize))
+}
+
+fn cell_window(p: &Puzzle, centre: (isize, isize), size: Size) -> CellWindow {
+    let mut cells = vec![];
+    fill_cells(p, centre, size, &mut cells);
+    cells.chunks(size.1).map(<[Cell]>::to_vec).collect()
 }
 
-fn cell_window(p: &Puzzle, i: isize, j: isize) -> CellWindow {
-    let mut window = [[Cell::Any; 3]; 3];
-    for i_w in 0..window.len() {
-        for j_w in 0..window[0].len() {
-            let i_ix = i + i_w as isize - 1;
-            let j_ix = j + j_w as isize - 1;
-            window[i_w][j_w] = if i_ix >= 0 && j_ix >= 0 {
+/// The cells of the window of `size` around `centre` into `window`, row by row.
+fn fill_cells(p: &Puzzle, centre: (isize, isize), (rows, cols): Size, window: &mut Vec<Cell>) {
+    let (top, left) = top_left(centre, (rows, cols));
+    window.clear();
+    for i_ix in top..top + rows as isize {
+        for j_ix in left..left + cols as isize {
+            window.push(if i_ix >= 0 && j_ix >= 0 {
                 let (i, j) = (i_ix as usize, j_ix as usize);
                 if i >= p.xsize || j >= p.ysize {
                     Cell::OutOfBounds
@@ -145,18 +1113,15 @@ fn cell_window(p: &Puzzle, i: isize, j: isize) -> CellWindow {
                 }
             } else {
                 Cell::OutOfBounds
-            }
+            });
         }
     }
-    window
 }
 
-/// Similar to regular fetching edge by index from edges list, but in case of outbound
-/// returns Edge::OutOfBounds value. Useful for creating windows over puzzle that are reaching
-/// over the edge of puzzle.
-fn window_safe_edge(p: &Puzzle, edges: &[Edge], i: isize, j: isize, is_horizontal: bool) -> Edge {
+/// Index of the edge at `(i, j)`, or `None` when the grid has no such edge.
+fn grid_edge(p: &Puzzle, i: isize, j: isize, is_horizontal: bool) -> Option<usize> {
     if i < 0 || j < 0 {
-        return Edge::OutOfBounds;
+        return None;
     }
     let (ui, uj) = (i as usize, j as usize);
     // Horizontal edges: i in [0, xsize], j in [0, ysize)
@@ -166,38 +1131,69 @@ fn window_safe_edge(p: &Puzzle, edges: &[Edge], i: isize, j: isize, is_horizonta
     } else {
         ui >= p.xsize || uj > p.ysize
     };
-    if out_of_bounds {
-        return Edge::OutOfBounds;
-    }
-    edges[p.edge_ix(ui, uj, is_horizontal)]
+    (!out_of_bounds).then(|| p.edge_ix(ui, uj, is_horizontal))
 }
 
-/// Make a window out of horizontal edges in the puzzle, with center at [i][j]
-fn horizontal_edge_window(p: &Puzzle, edges: &[Edge], i: isize, j: isize) -> Horizontals {
-    let mut res = [[Edge::Unknown; 3]; 2];
-    res[0][0] = window_safe_edge(p, edges, i, j - 1, true);
-    res[0][1] = window_safe_edge(p, edges, i, j, true);
-    res[0][2] = window_safe_edge(p, edges, i, j + 1, true);
-
-    res[1][0] = window_safe_edge(p, edges, i + 1, j - 1, true);
-    res[1][1] = window_safe_edge(p, edges, i + 1, j, true);
-    res[1][2] = window_safe_edge(p, edges, i + 1, j + 1, true);
-
-    res
+/// Similar to regular fetching edge by index from edges list, but in case of outbound
+/// returns Edge::OutOfBounds value. Useful for creating windows over puzzle that are reaching
+/// over the edge of puzzle.
+fn window_safe_edge(p: &Puzzle, edges: &[Edge], i: isize, j: isize, is_horizontal: bool) -> Edge {
+    grid_edge(p, i, j, is_horizontal).map_or(Edge::OutOfBounds, |e| edges[e])
 }
 
-/// Make a window out of vertical edges in the puzzle, with center at [i][j]
-fn vertical_edge_window(p: &Puzzle, edges: &[Edge], i: isize, j: isize) -> Verticals {
-    let mut res = [[Edge::Unknown; 2]; 3];
+/// Make a window of `size` out of horizontal edges in the puzzle, with center at `centre`
+fn horizontal_edge_window(
+    p: &Puzzle,
+    edges: &[Edge],
+    centre: (isize, isize),
+    size: Size,
+) -> Horizontals {
+    let (top, left) = top_left(centre, size);
+    (0..size.0 as isize - 1)
+        .map(|i_w| {
+            (0..size.1 as isize)
+                .map(|j_w| window_safe_edge(p, edges, top + 1 + i_w, left + j_w, true))
+                .collect()
+        })
+        .collect()
+}
 
-    res[0][0] = window_safe_edge(p, edges, i - 1, j, false);
-    res[0][1] = window_safe_edge(p, edges, i - 1, j + 1, false);
-    res[1][0] = window_safe_edge(p, edges, i, j, false);
-    res[1][1] = window_safe_edge(p, edges, i, j + 1, false);
-    res[2][0] = window_safe_edge(p, edges, i + 1, j, false);
-    res[2][1] = window_safe_edge(p, edges, i + 1, j + 1, false);
+/// Make a window of `size` out of vertical edges in the puzzle, with center at `centre`
+fn vertical_edge_window(
+    p: &Puzzle,
+    edges: &[Edge],
+    centre: (isize, isize),
+    size: Size,
+) -> Verticals {
+    let (top, left) = top_left(centre, size);
+    (0..size.0 as isize)
+        .map(|i_w| {
+            (0..size.1 as isize - 1)
+                .map(|j_w| window_safe_edge(p, edges, top + i_w, left + 1 + j_w, false))
+                .collect()
+        })
+        .collect()
+}
 
-    res
+/// The horizontal and then the vertical edges of the window of `size` around `centre` into
+/// `window`, each row by row, as [`Matcher`] has them. Refilling one vector rather than
+/// making new windows keeps allocation off the hot path.
+fn fill_edges(
+    p: &Puzzle,
+    edges: &[Edge],
+    centre: (isize, isize),
+    (rows, cols): Size,
+    window: &mut Vec<Edge>,
+) {
+    let (top, left) = top_left(centre, (rows, cols));
+    let (rows, cols) = (rows as isize, cols as isize);
+    window.clear();
+    for i in top + 1..top + rows {
+        window.extend((left..left + cols).map(|j| window_safe_edge(p, edges, i, j, true)));
+    }
+    for i in top..top + rows {
+        window.extend((left + 1..left + cols).map(|j| window_safe_edge(p, edges, i, j, false)));
+    }
 }
 
 #[cfg(test)]
@@ -209,37 +1205,447 @@ mod test {
     fn update_arbitrary_thing() {
         let threes_ortho = PatternSolution {
             output: Pattern {
-                horizontals: [
-                    [Edge::Any, Edge::Filled, Edge::Any],
-                    [Edge::Any, Edge::Filled, Edge::Any],
+                horizontals: vec![
+                    vec![Edge::Any, Edge::Filled, Edge::Any],
+                    vec![Edge::Any, Edge::Filled, Edge::Any],
                 ],
-                verticals: [
-                    [Edge::Any, Edge::Any],
-                    [Edge::Empty, Edge::Any],
-                    [Edge::Any, Edge::Any],
+                verticals: vec![
+                    vec![Edge::Any, Edge::Any],
+                    vec![Edge::Empty, Edge::Any],
+                    vec![Edge::Any, Edge::Any],
                 ],
             },
             input: Pattern {
-                horizontals: [[Edge::Any; 3]; 2],
-                verticals: [[Edge::Any; 2]; 3],
+                horizontals: vec![vec![Edge::Any; 3]; 2],
+                verticals: vec![vec![Edge::Any; 2]; 3],
             },
-            cells: [
-                [Cell::Any, Cell::Three, Cell::Any],
-                [Cell::Any, Cell::Three, Cell::Any],
-                [Cell::Any, Cell::Any, Cell::Any],
+            cells: vec![
+                vec![Cell::Any, Cell::Three, Cell::Any],
+                vec![Cell::Any, Cell::Three, Cell::Any],
+                vec![Cell::Any, Cell::Any, Cell::Any],
             ],
         };
 
         let mut h: HashMap<usize, bool> = HashMap::new();
         let mut edges = vec![Edge::Empty; 220];
+        let mut set_by = vec![(GIVEN, (0, 0)); 220];
         let p = Puzzle::from(&[[-1; 10]; 10]);
-        remember_facts(&mut h, &mut edges, &threes_ortho, &p, 3, 4);
+        let matcher = Matcher::new(&threes_ortho);
+        let pattern = ("threes", &matcher);
+        remember_facts(&mut h, &mut edges, &mut set_by, pattern, &p, (3, 4)).unwrap();
         assert_eq!(HashMap::from([(44, true), (34, true), (147, false)]), h);
         h.clear();
-        remember_facts(&mut h, &mut edges, &threes_ortho, &p, 0, 0);
+        remember_facts(&mut h, &mut edges, &mut set_by, pattern, &p, (0, 0)).unwrap();
         assert_eq!(HashMap::from([(0, true), (10, true), (110, false)]), h);
     }
 
+    #[test]
+    fn reports_conflicting_patterns() {
+        let p = Puzzle::from(&[[0, 0], [0, 2]]);
+        let c = find_facts(&p, &PatternSet::default()).unwrap_err();
+        assert_ne!(c.first.filled, c.second.filled);
+        assert!(c.to_string().contains(&c.second.pattern), "{c}");
+    }
+
+    #[test]
+    fn tiny_loops_are_no_contradiction() {
+        use crate::{solve, Backend, SolveOptions};
+
+        // The whole loop goes around both 3s, and is the only solution.
+        for s in ["1x2:33", "2x1:33", "2x2:3131"] {
+            let p = Puzzle::from_grid(crate::parse::from_string(s).unwrap());
+            let options = SolveOptions {
+                backend: Backend::from_name("native").unwrap(),
+                pre_solve: false,
+                ..SolveOptions::default()
+            };
+            let solution = solve(&p, &options).into_best().unwrap();
+            let facts = find_facts(&p, &PatternSet::default()).unwrap();
+            for (e, filled) in facts {
+                assert_eq!(solution.edges[e] == Edge::Filled, filled, "edge {e} of {s}");
+            }
+        }
+    }
+
+    #[test]
+    fn given_edges_can_conflict() {
+        let p = Puzzle::from(&[[0, -1], [-1, -1]]);
+        let mut edges = vec![Edge::Unknown; p.edge_count()];
+        let (top, ..) = p.edges_around_cell(0, 0);
+        edges[top] = Edge::Filled;
+        let c = extend_facts(&p, &PatternSet::default(), &edges).unwrap_err();
+        assert_eq!(c.edge, top);
+        let given = Deduction {
+            pattern: String::from(GIVEN),
+            window: (0, 0),
+            filled: true,
+        };
+        assert_eq!(c.first, given);
+        assert!(!c.second.filled);
+    }
+
+    #[test]
+    fn colouring_can_conflict() {
+        // No pattern to catch the filled edge next to the 0, but it can't be coloured.
+        let p = Puzzle::from(&[[0, -1], [-1, -1]]);
+        let mut edges = vec![Edge::Unknown; p.edge_count()];
+        let (top, ..) = p.edges_around_cell(0, 0);
+        edges[top] = Edge::Filled;
+        let c = extend_facts(&p, &PatternSet::empty(), &edges).unwrap_err();
+        assert_eq!(c.edge, top);
+        assert_eq!(c.first.pattern, GIVEN);
+        let colouring = Deduction {
+            pattern: String::from(COLOURING),
+            window: (0, 0),
+            filled: false,
+        };
+        assert_eq!(c.second, colouring);
+    }
+
+    #[test]
+    fn added_patterns_replace_those_of_the_same_name() {
+        let zero = PatternSet::baked_in()
+            .iter()
+            .find(|(name, _)| *name == "zero")
+            .unwrap()
+            .1
+            .clone();
+        let mut patterns = PatternSet::empty();
+        patterns.add(vec![(String::from("lone zero"), zero.clone())]);
+        let p = Puzzle::from(&[[0, -1], [-1, -1]]);
+        let hint = next_hint(&p, &patterns, &vec![Edge::Unknown; p.edge_count()]).unwrap();
+        assert!(hint.pattern.starts_with("lone zero-"), "{}", hint.pattern);
+
+        let mut patterns = PatternSet::baked_in();
+        patterns.add(vec![(String::from("two threes diagonally"), zero.clone())]);
+        let replaced: Vec<_> = patterns
+            .iter()
+            .filter(|(name, _)| name.starts_with("two threes diagonally-"))
+            .map(|(_, pattern)| pattern.clone())
+            .collect();
+        assert_eq!(replaced, zero.rotations());
+        // The shared baked-in set is left alone.
+        assert!(PatternSet::baked_in()
+            .iter()
+            .any(|(name, pattern)| name == "two threes diagonally-0" && *pattern != zero));
+        patterns.add(vec![(String::from("zero"), zero)]);
+        assert!(patterns.iter().all(|(name, _)| name != "zero"));
+    }
+
+    #[test]
+    fn edges_requeue_every_window_that_sees_them() {
+        let p = Puzzle::from_grid(vec![vec![-1; 4]; 3]);
+        let mut patterns = PatternSet::default();
+        patterns.add(vec![(String::from("threes"), PatternSolution::parse("3*3", "3|3"))]);
+        let windows = Windows::new(&p, &patterns, usize::MAX);
+        for e in 0..p.edge_count() {
+            let mut board = vec![Edge::Unknown; p.edge_count()];
+            board[e] = Edge::Filled;
+            let mut expected: Vec<usize> = (0..windows.places.len())
+                .filter(|&w| {
+                    let (centre, size) = windows.places[w];
+                    let hor = horizontal_edge_window(&p, &board, centre, size);
+                    let vert = vertical_edge_window(&p, &board, centre, size);
+                    hor.iter()
+                        .chain(&vert)
+                        .flatten()
+                        .any(|&x| x == Edge::Filled)
+                })
+                .collect();
+            let mut found: Vec<usize> = windows.around_edge(&p, e).collect();
+            expected.sort();
+            found.sort();
+            assert_eq!(found, expected, "edge {e}");
+        }
+    }
+
+    #[test]
+    fn patterns_of_any_size_match_at_the_border() {
+        let mut patterns = PatternSet::empty();
+        patterns.add(vec![(String::from("threes"), PatternSolution::parse("3*3", "3|3"))]);
+        let p = Puzzle::from_grid(vec![
+            vec![3, -1, -1, -1],
+            vec![3, -1, -1, -1],
+            vec![-1; 4],
+            vec![-1, -1, 3, 3],
+        ]);
+        let (_, steps) = find_facts_traced(&p, &patterns).unwrap();
+        let (_, below, ..) = p.edges_around_cell(0, 0);
+        let (.., right) = p.edges_around_cell(3, 2);
+        let mut matched: Vec<_> = steps
+            .iter()
+            .filter(|step| step.pattern.starts_with("threes-"))
+            .map(|step| (step.window, step.edges.clone()))
+            .collect();
+        matched.sort();
+        assert_eq!(matched, [((1, 0), vec![(below, true)]), ((3, 3), vec![(right, true)])]);
+    }
+
+    #[test]
+    fn trace_explains_every_fact() {
+        let p = Puzzle::from_grid(crate::parse::from_string("5x5:3322121021211311212201123").unwrap());
+        let patterns = PatternSet::default();
+        let (facts, steps) = find_facts_traced(&p, &patterns).unwrap();
+        assert_eq!(facts, find_facts(&p, &patterns).unwrap());
+        assert!(!steps.is_empty());
+
+        let mut board = vec![Edge::Unknown; p.edge_count()];
+        for step in &steps {
+            assert_eq!(step.before, board);
+            for &(e, filled) in &step.edges {
+                assert_eq!(board[e], Edge::Unknown, "{} set edge {e} twice", step.pattern);
+                board[e] = if filled { Edge::Filled } else { Edge::Empty };
+            }
+            assert_eq!(step.after, board);
+        }
+        assert_eq!(board, facts_to_edges(&p, &facts));
+    }
+
+    #[test]
+    fn hints_walk_to_the_same_facts() {
+        let p = Puzzle::from_grid(crate::parse::from_string("5x5:3322121021211311212201123").unwrap());
+        let patterns = PatternSet::default();
+        let facts = find_facts(&p, &patterns).unwrap();
+        let mut board = vec![Edge::Unknown; p.edge_count()];
+        while let Some(hint) = next_hint(&p, &patterns, &board) {
+            assert!(!hint.edges.is_empty());
+            for (e, filled) in hint.edges {
+                assert_eq!(board[e], Edge::Unknown);
+                assert_eq!(facts.get(&e), Some(&filled), "{} disagrees on {e}", hint.pattern);
+                board[e] = if filled { Edge::Filled } else { Edge::Empty };
+            }
+        }
+        assert_eq!(board, facts_to_edges(&p, &facts));
+    }
+
+    #[test]
+    fn hint_starts_from_player_marks() {
+        // A lone 0 rules out its four edges, and nothing else.
+        let p = Puzzle::from(&[[0, -1], [-1, -1]]);
+        let patterns = PatternSet::default();
+        let hint = next_hint(&p, &patterns, &vec![Edge::Unknown; p.edge_count()]).unwrap();
+        assert!(hint.edges.iter().all(|&(_, filled)| !filled));
+        assert!(hint.text.contains("cross out"), "{}", hint.text);
+
+        let (a, b, c, d) = p.edges_around_cell(0, 0);
+        let mut board = vec![Edge::Unknown; p.edge_count()];
+        for e in [a, b, c, d] {
+            board[e] = Edge::Empty;
+        }
+        if let Some(hint) = next_hint(&p, &patterns, &board) {
+            assert!(hint.edges.iter().all(|&(e, _)| ![a, b, c, d].contains(&e)));
+        }
+    }
+
+    #[test]
+    fn loop_closure_empties_early_loops() {
+        let p = Puzzle::from(&[[-1; 3]; 3]);
+        let (top, bottom, left, right) = p.edges_around_cell(0, 0);
+        let mut edges = vec![Edge::Unknown; p.edge_count()];
+        for e in [top, left, bottom] {
+            edges[e] = Edge::Filled;
+        }
+        // Closing the three sides around the empty grid is a whole solution.
+        assert!(loop_closure_edges(&p, &edges).is_empty());
+
+        // Not with a filled edge elsewhere, or with a clue the loop misses.
+        let mut other = edges.clone();
+        other[p.edge_ix(3, 2, true)] = Edge::Filled;
+        assert_eq!(loop_closure_edges(&p, &other), vec![right]);
+        let clued = Puzzle::from(&[[-1, -1, -1], [-1, -1, -1], [-1, -1, 1]]);
+        assert_eq!(loop_closure_edges(&clued, &edges), vec![right]);
+    }
+
+    #[test]
+    fn facts_agree_with_solution() {
+        use crate::{solve, Backend, SolveOptions};
+
+        for s in [
+            "10x10d2:3a2223a32b211a3c3a1a12a23c2b3d33c02b2c20a21a1a3b1a12a112a3e221a3k2c2a",
+            "10x10d0:b1a2a22a32a1b22b2b2a23b212a22d222a31b2c12c2d331d013e1a2c2122a1b2a3b13a02a",
+        ] {
+            let p = Puzzle::from_grid(crate::parse::from_string(s).unwrap());
+            let options = SolveOptions {
+                backend: Backend::from_name("native").unwrap(),
+                pre_solve: false,
+                ..SolveOptions::default()
+            };
+            let solution = solve(&p, &options).into_best().unwrap();
+            let (facts, steps) = find_facts_traced(&p, &PatternSet::default()).unwrap();
+            assert!(steps.iter().any(|step| step.pattern == LOOP_CLOSURE));
+            for (e, filled) in facts {
+                assert_eq!(solution.edges[e] == Edge::Filled, filled, "edge {e} of {s}");
+            }
+        }
+    }
+
+    /// A sparse 45x45 with a long pattern run.
+    const P45: &str = concat!(
+        "45x45d3:a0a3a3a2a2a3a312a33a3a3a3b31c22b3a323b333e2a32c1b0c2b132d1b21c1a21a21a22a3a222",
+        "2b0c2j02a21a3a2a1a22b2e2b2b22e13a3b11e1c2c1b0b1312e323b02a3a3a3c233a11b3221a101a1311a3",
+        "a223a12d3a1c13a2b2a12a211a311d22a33f1b12022a2a2d20c2b1j121b3c2b22d2c2e2b2112a01b13c2b3",
+        "a2a2a2a112a3b2a2222b0a0g3a132a1a1a2c13b2h1d213b1c3b2c21a2d22a2a2a011b23a0b322d31b1b122",
+        "2a1a12a21033a2a1c33a2a3b1d2a3a2a2a3a3b1a3d3d2b3g22a2a232c21a11b2a1a1b1a2a1a22b3b232c2a",
+        "32a21a12a2a22a2b3b2a212a2b22a2a2121223a2f22d20g1a22b2b2c12c2b21b1a33a12a21a22b3122a2a2",
+        "1f32a3a1c22b3a21c1a22e3a1b312b213120a123a2212131e2a323b3b1a12c1d2a3b3d12c1a1c3a12a1a2b",
+        "2e22a2a3a12b2c232e22b22d2a1a23b22a1a2a2h3a2c2c21a1313b3a2111a3a3c220b2c02211a11b2b3a2b",
+        "1c2d122c023a1a12c2a2a233a1b131a2a1b21b23a2a2a1a2e2a123a112b12a3a1a22a13a22a23a2a2a3b2a",
+        "2a1b3b1b2j3a2f32a1a1a2b0b3a1a2b02c20b22113a3a2b2e1a2a22b3a32a2h2b2b122a2b3a10a2a3e22b1",
+        "d2a002c3a2a1a2c2a3b3c3b1323b11a1c2a10b3b2a3a3b3e2123a322a21a0b1d31d22a1a1c1a0a0b1b2b22",
+        "c32a1c2a2213a31a2b11a1b3d2a2a22a2b1a2a2d32a2c2b2f3a2b22a23a2c1221a2a1a3a1c21e1a3a2133a",
+        "1c0a2c3b1a2c11a2c3131c23112f2a2b3a2132b32b2d2a322c31a2d3a3a2a23e1d2b222b21c2h2a212g22a",
+        "2e32e2a32d31a2e2a3c2h2a0g11b21b22a2a3b2221a1b3a11a12c2222b12a32b121a1a1a122b2a13212c32",
+        "b13g22a3d1b3a3c22d2b13b312c221b33b1a3b3b312d0b12b2201b2f22a2b2b221a3b3a21b3c23a31a1b3b",
+        "2a222322a231a11b3a32f1c2a2c21b3c2a2a12b2c1a2b2a22a3b13a33a0b23a3a2d3b2231a2a31a21a1a1a",
+        "2c122d12c222a3a2a1a2c3a3c2a3b21b213b23a32a222b23c1132b23e23a23b3a3e13a2b",
+    );
+
+    /// The engine as it was before the worklist: rescan every window for every pattern until
+    /// nothing matches, then try the global rules. Panics on a contradiction.
+    fn rescan(
+        puzzle: &Puzzle,
+        patterns: &PatternSet,
+        max_constraints: usize,
+    ) -> HashMap<usize, bool> {
+        let mut board = vec![Edge::Unknown; puzzle.edge_count()];
+        let mut sizes: BTreeMap<Size, Vec<_>> = BTreeMap::new();
+        for ((name, pattern), matcher) in patterns.iter().zip(patterns.matchers()) {
+            if pattern.constraints() <= max_constraints {
+                sizes.entry(pattern.size()).or_default().push((name, pattern, matcher));
+            }
+        }
+        let span = clue_span(puzzle);
+        let mut found = true;
+        while found {
+            found = false;
+            for (&size, patterns) in &sizes {
+                for centre in centres(puzzle, size) {
+                    let window = cell_window(puzzle, centre, size);
+                    let fits = loop_fits(span, centre, size);
+                    let hor = horizontal_edge_window(puzzle, &board, centre, size);
+                    let vert = vertical_edge_window(puzzle, &board, centre, size);
+                    for &(name, pattern, matcher) in patterns {
+                        if (fits && matcher.small_loop) || !pattern.try_match(&window, &hor, &vert)
+                        {
+                            continue;
+                        }
+                        for (e, filled) in pattern_outputs(pattern, puzzle, centre) {
+                            let value = if filled { Edge::Filled } else { Edge::Empty };
+                            match board[e] {
+                                Edge::Unknown => found = true,
+                                known => assert_eq!(known, value, "{name} at {centre:?}"),
+                            }
+                            board[e] = value;
+                        }
+                    }
+                }
+            }
+            for (_, rank, rule) in GLOBAL_RULES {
+                if max_constraints < rank || found {
+                    continue;
+                }
+                for (e, filled) in rule(puzzle, &board).unwrap() {
+                    board[e] = if filled { Edge::Filled } else { Edge::Empty };
+                    found = true;
+                }
+            }
+        }
+        board
+            .iter()
+            .enumerate()
+            .filter(|(_, &e)| e != Edge::Unknown)
+            .map(|(e, &v)| (e, v == Edge::Filled))
+            .collect()
+    }
+
+    /// The edges [`rescan`] would set for `step`'s pattern at its window on its board. A global
+    /// rule sets everything one call of it returns, one step at a time, so `batch` keeps the
+    /// last call until its edges are all set.
+    fn rescan_step(
+        puzzle: &Puzzle,
+        patterns: &PatternSet,
+        max_constraints: usize,
+        step: &Step,
+        batch: &mut Vec<(usize, bool)>,
+    ) -> Vec<(usize, bool)> {
+        let board = &step.before;
+        let global = GLOBAL_RULES.iter().find(|(name, ..)| *name == step.pattern);
+        if let Some(&(_, rank, rule)) = global {
+            assert!(rank <= max_constraints);
+            let &[(e, filled)] = step.edges.as_slice() else {
+                panic!("{} set {:?}", step.pattern, step.edges);
+            };
+            let ((i, j), _) = puzzle.edge_endpoints(e);
+            assert_eq!(step.window, (i as isize, j as isize));
+            if !batch.contains(&(e, filled)) {
+                *batch = rule(puzzle, board).unwrap();
+            }
+            assert!(batch.contains(&(e, filled)), "{} at {:?}", step.pattern, step.window);
+            return step.edges.clone();
+        }
+        batch.clear();
+        let (pattern, matcher) = patterns
+            .iter()
+            .zip(patterns.matchers())
+            .find(|((name, _), _)| **name == step.pattern)
+            .map(|((_, pattern), matcher)| (pattern, matcher))
+            .unwrap();
+        let (centre, size) = (step.window, pattern.size());
+        assert!(pattern.constraints() <= max_constraints);
+        assert!(!(loop_fits(clue_span(puzzle), centre, size) && matcher.small_loop));
+        let hor = horizontal_edge_window(puzzle, board, centre, size);
+        let vert = vertical_edge_window(puzzle, board, centre, size);
+        assert!(pattern.try_match(&cell_window(puzzle, centre, size), &hor, &vert));
+        pattern_outputs(pattern, puzzle, centre)
+            .into_iter()
+            .filter(|&(e, _)| board[e] == Edge::Unknown)
+            .collect()
+    }
+
+    #[test]
+    fn worklist_matches_a_full_rescan() {
+        let mut custom = PatternSet::default();
+        custom.add(vec![
+            (String::from("threes"), PatternSolution::parse("3*3", "3|3")),
+            (String::from("three threes"), PatternSolution::parse("3*3*3", "3|3|3")),
+        ]);
+        let sets = [PatternSet::default(), custom];
+        let puzzles = [
+            "10x10d2:3a2223a32b211a3c3a1a12a23c2b3d33c02b2c20a21a1a3b1a12a112a3e221a3k2c2a",
+            "10x10d0:b1a2a22a32a1b22b2b2a23b212a22d222a31b2c12c2d331d013e1a2c2122a1b2a3b13a02a",
+            P45,
+        ];
+        for s in puzzles {
+            let p = Puzzle::from_grid(crate::parse::from_string(s).unwrap());
+            // The custom set has all of the default one, so it covers both on the big puzzle,
+            // which takes the rescan a while.
+            let sets = if p.xsize > 10 { &sets[1..] } else { &sets[..] };
+            for patterns in sets {
+                for max_constraints in [2, usize::MAX] {
+                    let facts = rescan(&p, patterns, max_constraints);
+                    let mut steps = vec![];
+                    let empty = vec![Edge::Unknown; p.edge_count()];
+                    let worklist =
+                        run_patterns(&p, patterns, &empty, max_constraints, Some(&mut steps));
+                    assert_eq!(worklist.unwrap(), facts, "{s}");
+                    // The steps come in another order, so other patterns may get to an edge
+                    // first, but each one has to be one the rescan could have taken.
+                    let (mut board, mut batch) = (empty, vec![]);
+                    for step in &steps {
+                        assert_eq!(step.before, board);
+                        let expected = rescan_step(&p, patterns, max_constraints, step, &mut batch);
+                        assert_eq!(step.edges, expected, "{} at {:?}", step.pattern, step.window);
+                        for &(e, filled) in &step.edges {
+                            board[e] = if filled { Edge::Filled } else { Edge::Empty };
+                        }
+                        assert_eq!(step.after, board);
+                    }
+                    assert_eq!(board, facts_to_edges(&p, &facts));
+                }
+            }
+        }
+    }
+
     fn edges3x3() -> Vec<Edge> {
         Puzzle::edges(
             &[
@@ -265,35 +1671,35 @@ mod test {
     #[test]
     fn edge_vertical_window_simple() {
         assert_eq!(
-            vertical_edge_window(&puzzle3x3(), edges3x3().as_slice(), 0, 0),
-            Verticals::from([
+            vertical_edge_window(&puzzle3x3(), edges3x3().as_slice(), (0, 0), (3, 3)),
+            [
                 [Edge::OutOfBounds, Edge::OutOfBounds],
                 [Edge::Filled, Edge::Any],
                 [Edge::Any, Edge::Filled]
-            ])
+            ]
         )
     }
 
     #[test]
     fn edge_horizontal_window_simple() {
         assert_eq!(
-            horizontal_edge_window(&puzzle3x3(), edges3x3().as_slice(), 0, 0),
-            Horizontals::from([
+            horizontal_edge_window(&puzzle3x3(), edges3x3().as_slice(), (0, 0), (3, 3)),
+            [
                 [Edge::OutOfBounds, Edge::Empty, Edge::Unknown],
                 [Edge::OutOfBounds, Edge::Unknown, Edge::Empty]
-            ])
+            ]
         )
     }
 
     #[test]
     fn test_cell_window() {
         assert_eq!(
-            cell_window(&puzzle3x3(), 0, 0),
-            CellWindow::from([
+            cell_window(&puzzle3x3(), (0, 0), (3, 3)),
+            [
                 [Cell::OutOfBounds, Cell::OutOfBounds, Cell::OutOfBounds],
                 [Cell::OutOfBounds, Cell::One, Cell::Two],
                 [Cell::OutOfBounds, Cell::Three, Cell::One],
-            ])
+            ]
         )
     }
 
@@ -366,7 +1772,7 @@ mod test {
             ysize,
         };
 
-        let facts = find_facts(&p);
+        let facts = find_facts(&p, &PatternSet::default()).unwrap();
 
         // Both edges are Filled in the true (SAT) solution.  The pre-solve must not
         // assert them as Empty (false) — that would make the SAT phase reach UNSAT.
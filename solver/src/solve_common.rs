@@ -1,9 +1,10 @@
 use crate::adapter::{SlitherlinkerFormula, SlitherlinkerLit};
+use crate::connectivity::{connectivity_clauses, VarAlloc};
 use crate::data::pattern::Edge;
 use crate::data::puzzle::Puzzle;
 use crate::data::solution::{format_puzzle, Solution};
-use crate::parse::Cell;
-use crate::patterns::find_facts;
+use crate::patterns::{facts_to_edges, find_facts};
+use crate::{SolveOptions, SolveOutcome};
 use std::collections::HashMap;
 use std::{
     collections::{HashSet, LinkedList},
@@ -176,6 +177,164 @@ pub fn single_loop_edge(puzzle: &Puzzle, edges: &[Edge]) -> bool {
     find_loops_edges(puzzle, edges).len() == 1
 }
 
+/// A clause over edges: `(edge index, filled)` literals, satisfied when any edge has that value.
+pub type EdgeClause = Vec<(usize, bool)>;
+
+/// Every clue cell has exactly as many filled edges as its number.
+pub fn satisfies_clues(puzzle: &Puzzle, edges: &[Edge]) -> bool {
+    (0..puzzle.xsize).all(|i| {
+        (0..puzzle.ysize).all(|j| {
+            let clue = puzzle.cells[i][j];
+            let (a, b, c, d) = puzzle.edges_around_cell(i, j);
+            let filled = [a, b, c, d]
+                .iter()
+                .filter(|&&e| edges[e] == Edge::Filled)
+                .count();
+            clue < 0 || filled == clue as usize
+        })
+    })
+}
+
+/// The single-loop solution hidden in the model `edges`, if any: either the model is a single
+/// loop already, or one of its loops satisfies every clue on its own and the others are noise.
+pub fn single_loop_solution(puzzle: &Puzzle, edges: &[Edge]) -> Option<Vec<Edge>> {
+    let loops = find_loops_edges(puzzle, edges);
+    if loops.len() == 1 {
+        return Some(edges.to_vec());
+    }
+    loops.iter().find_map(|lp| {
+        let mut candidate = vec![Edge::Empty; edges.len()];
+        for &e in lp {
+            candidate[e] = Edge::Filled;
+        }
+        satisfies_clues(puzzle, &candidate).then_some(candidate)
+    })
+}
+
+/// Clauses that rule out the multi-loop model `edges`: the blocking clause of every loop
+/// (see [`blocking_clause_edge_groups`]), plus a subtour-elimination cut per loop where one
+/// can be found when `cuts` is set (see [`subtour_cut`]).
+pub fn blocking_clauses(puzzle: &Puzzle, edges: &[Edge], cuts: bool) -> Vec<EdgeClause> {
+    let mut clauses: Vec<EdgeClause> = blocking_clause_edge_groups(puzzle, edges.len(), edges)
+        .iter()
+        .map(|g| g.iter().map(|&e| (e, edges[e] != Edge::Filled)).collect())
+        .collect();
+    if cuts {
+        for lp in find_loops_edges(puzzle, edges) {
+            clauses.extend(subtour_cut(puzzle, &lp));
+        }
+    }
+    clauses
+}
+
+/// Cuts with more literals than this are skipped: they slow the SAT solver down more than
+/// they save.
+const MAX_CUT_LITERALS: usize = 20_000;
+
+/// Cells enclosed by the closed loop `lp`, as a `[row][column]` mask.
+fn inside_cells(puzzle: &Puzzle, lp: &[usize]) -> Vec<Vec<bool>> {
+    let on_loop: HashSet<usize> = lp.iter().copied().collect();
+    let (rows, cols) = (puzzle.xsize, puzzle.ysize);
+    // Flood the outside from every border side the loop does not run along.
+    let mut outside = vec![vec![false; cols]; rows];
+    let mut queue: LinkedList<(usize, usize)> = LinkedList::new();
+    for i in 0..rows {
+        for j in 0..cols {
+            let (top, bottom, left, right) = puzzle.edges_around_cell(i, j);
+            let border = [
+                (i == 0, top),
+                (i + 1 == rows, bottom),
+                (j == 0, left),
+                (j + 1 == cols, right),
+            ];
+            if border.iter().any(|&(b, e)| b && !on_loop.contains(&e)) {
+                queue.push_back((i, j));
+            }
+        }
+    }
+    while let Some((i, j)) = queue.pop_front() {
+        if outside[i][j] {
+            continue;
+        }
+        outside[i][j] = true;
+        let (top, bottom, left, right) = puzzle.edges_around_cell(i, j);
+        let mut next = vec![];
+        if i > 0 {
+            next.push((top, (i - 1, j)));
+        }
+        if i + 1 < rows {
+            next.push((bottom, (i + 1, j)));
+        }
+        if j > 0 {
+            next.push((left, (i, j - 1)));
+        }
+        if j + 1 < cols {
+            next.push((right, (i, j + 1)));
+        }
+        for (e, (a, b)) in next {
+            if !on_loop.contains(&e) && !outside[a][b] {
+                queue.push_back((a, b));
+            }
+        }
+    }
+    outside
+        .iter()
+        .map(|row| row.iter().map(|o| !o).collect())
+        .collect()
+}
+
+/// Subtour-elimination cut for the loop `lp` of a multi-loop model.
+///
+/// Let S be the vertices of the cells `lp` encloses. If some clue cell has no edge within S,
+/// no solution fits inside S, so a solution using an edge within S has to cross the boundary
+/// of S too: for every such edge `e` add `!e || (one of the boundary edges)`. The model has no
+/// boundary edge filled, so this cuts off `lp` along with every other loop confined to S.
+fn subtour_cut(puzzle: &Puzzle, lp: &[usize]) -> Vec<EdgeClause> {
+    let inside = inside_cells(puzzle, lp);
+    let cols = puzzle.ysize + 1;
+    let mut in_s = vec![false; (puzzle.xsize + 1) * cols];
+    for (i, row) in inside.iter().enumerate() {
+        for (j, _) in row.iter().enumerate().filter(|(_, &c)| c) {
+            for (a, b) in [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)] {
+                in_s[a * cols + b] = true;
+            }
+        }
+    }
+    let in_s_edge = |e: usize| {
+        let ((i0, j0), (i1, j1)) = puzzle.edge_endpoints(e);
+        (in_s[i0 * cols + j0], in_s[i1 * cols + j1])
+    };
+    let unreachable_clue = (0..puzzle.xsize).any(|i| {
+        (0..puzzle.ysize).any(|j| {
+            let (a, b, c, d) = puzzle.edges_around_cell(i, j);
+            puzzle.cells[i][j] > 0 && [a, b, c, d].iter().all(|&e| in_s_edge(e) != (true, true))
+        })
+    });
+    if !unreachable_clue {
+        return vec![];
+    }
+    let boundary: Vec<usize> = (0..puzzle.edge_count())
+        .filter(|&e| {
+            let (a, b) = in_s_edge(e);
+            a != b
+        })
+        .collect();
+    let premises: Vec<usize> = (0..puzzle.edge_count())
+        .filter(|&e| in_s_edge(e) == (true, true))
+        .collect();
+    if premises.len() * boundary.len() > MAX_CUT_LITERALS {
+        return vec![];
+    }
+    premises
+        .iter()
+        .map(|&e| {
+            let mut clause = vec![(e, false)];
+            clause.extend(boundary.iter().map(|&b| (b, true)));
+            clause
+        })
+        .collect()
+}
+
 pub fn cell_clauses<T: SlitherlinkerLit + Not<Output = T> + Copy>(
     p: &Puzzle,
     facts: &HashMap<usize, bool>,
@@ -255,41 +414,38 @@ pub fn edge_clauses<T: SlitherlinkerLit + Not<Output = T> + Copy>(
     }
 }
 
-/// Using grid of cells,
-/// 1. create an instance of Puzzle
-/// 2. Find "facts" using patterns (only if pre_solve is true) as hashmap <edge-index: value>
-/// 3. Use facts and cell-edge input to mutate input boolean formula
+/// The pattern engine's facts when `pre_solve` is on, `None` when it is off. A
+/// [`crate::patterns::Contradiction`] is logged and turns pre-solve off: every backend then
+/// finds out on its own whether the puzzle has no solution, so a wrong pattern can't make a
+/// good puzzle unsolvable.
+pub fn pre_solve_facts(p: &Puzzle, options: &SolveOptions) -> Option<HashMap<usize, bool>> {
+    if !options.pre_solve {
+        return None;
+    }
+    find_facts(p, &options.patterns)
+        .inspect_err(|c| options.log(format_args!("pattern engine contradiction: {c}")))
+        .ok()
+}
+
+/// Using the puzzle's clues,
+/// 1. Find "facts" using patterns (only if pre_solve is true) as hashmap <edge-index: value>
+/// 2. Use facts and cell-edge input to mutate input boolean formula
+/// 3. Add connectivity clauses (only if connectivity is true)
 /// 4. Return the "base edges" vector - basically a materialized facts hashmap
 pub fn solve_form_conditions<T: SlitherlinkerLit + Not<Output = T> + Copy>(
-    grid: Vec<Vec<Cell>>,
-    pre_solve: bool,
+    p: &Puzzle,
+    options: &SolveOptions,
     formula: &mut impl SlitherlinkerFormula<T>,
-    prefix: &str,
-) -> (Puzzle, HashMap<usize, bool>, Vec<Edge>) {
-    let xsize = grid.len();
-    let ysize = grid[0].len();
-
-    let p = Puzzle {
-        cells: grid,
-        xsize,
-        ysize,
-    };
+) -> (HashMap<usize, bool>, Vec<Edge>) {
+    let prefix = options.log_prefix.as_deref().unwrap_or_default();
+    let facts = pre_solve_facts(p, options).unwrap_or_default();
 
-    let facts = if pre_solve {
-        find_facts(&p)
-    } else {
-        HashMap::new()
-    };
-
-    let mut base_edges = vec![Edge::Unknown; (1 + xsize) * ysize + (1 + ysize) * xsize];
-    for (&k, &v) in facts.iter() {
-        base_edges[k] = if v { Edge::Filled } else { Edge::Empty };
-    }
+    let base_edges = facts_to_edges(p, &facts);
 
     if prefix == "varisat" {
         println!(
             "{prefix}After simplify:\n{}",
-            format_puzzle(&p, &base_edges)
+            format_puzzle(p, &base_edges)
         );
     }
 
@@ -298,39 +454,280 @@ pub fn solve_form_conditions<T: SlitherlinkerLit + Not<Output = T> + Copy>(
         formula.append_clause(vec![if v { l } else { l.invert() }]);
     }
 
-    cell_clauses(&p, &facts, formula, prefix);
-    edge_clauses(&p, &facts, formula, prefix);
-    (p, facts, base_edges)
+    cell_clauses(p, &facts, formula, prefix);
+    edge_clauses(p, &facts, formula, prefix);
+    if options.connectivity {
+        connectivity_clauses(p, &facts, formula, &mut VarAlloc::new(p));
+    }
+    (facts, base_edges)
+}
+
+/// A SAT model, sorted by whether it is a valid answer.
+pub enum Model {
+    /// A single loop satisfying every clue.
+    Loop(Solution),
+    /// Several loops, or none.
+    MultiLoop(Solution),
 }
 
-pub fn handle_ok_2<T: SlitherlinkerLit>(
+/// Turn a model into a solution and the clauses to add before asking for the next one. A
+/// single-loop model is blocked so the search can go on past it; any other model is blocked
+/// with [`blocking_clauses`].
+pub fn handle_ok_2(
     puzzle: &Puzzle,
     facts: &HashMap<usize, bool>,
     base_edges: &[Edge],
-    solutions: &mut Vec<Solution>,
-    solution_vector: &[T],
-    prefix: &str,
-) -> (Vec<Vec<T>>, Option<Solution>) {
-    let edges: Vec<Edge> = solution_vector.iter().map(|x| x.to_edge()).collect();
+    model: &[Edge],
+    options: &SolveOptions,
+) -> (Vec<EdgeClause>, Model) {
+    // Anything past the edge variables is an auxiliary variable of the encoding.
+    let edges = model[..puzzle.edge_count()].to_vec();
     let solution = Solution {
         puzzle: puzzle.clone(),
         edges: edges.clone(),
         edges_pre_solve: base_edges.to_vec(),
         facts: facts.clone(),
     };
-    let mut last_solution = None;
-    let loops = find_loops_edges(puzzle, &edges);
-    if loops.len() == 1 {
-        println!("{prefix}WIN! found single-loop solution!");
-        solutions.push(solution);
-    } else {
-        last_solution = Some(solution);
+
+    if let Some(loop_edges) = single_loop_solution(puzzle, &edges) {
+        options.log(format_args!("WIN! found single-loop solution!"));
+        let block = solution_blocking_clause(&loop_edges);
+        let solution = Solution {
+            edges: loop_edges,
+            ..solution
+        };
+        return (vec![block], Model::Loop(solution));
     }
+    let new_clauses = blocking_clauses(puzzle, &edges, options.subtour_cuts);
+    (new_clauses, Model::MultiLoop(solution))
+}
 
-    let groups = blocking_clause_edge_groups(puzzle, solution_vector.len(), &edges);
-    let new_clauses: Vec<Vec<T>> = groups
+/// Rules out a single-loop solution: any other loop leaves out at least one of its edges.
+pub fn solution_blocking_clause(edges: &[Edge]) -> EdgeClause {
+    edges
         .iter()
-        .map(|ixs| ixs.iter().map(|&e| solution_vector[e].invert()).collect())
-        .collect();
-    (new_clauses, last_solution)
+        .enumerate()
+        .filter(|(_, &e)| e == Edge::Filled)
+        .map(|(ix, _)| (ix, false))
+        .collect()
+}
+
+/// What a backend's blocking loop found.
+pub struct Search {
+    /// Distinct single-loop solutions, in the order they were found.
+    pub solutions: Vec<Solution>,
+    /// The last multi-loop model.
+    pub last_solution: Option<Solution>,
+    /// The solver ran out of models, so `solutions` holds every solution there is.
+    pub exhausted: bool,
+}
+
+/// A backend's blocking loop, run lazily: each `next` asks the SAT solver for models until
+/// one is a single loop, blocking every model on the way. Ends when the solver runs out of
+/// models, `max_iterations` SAT calls have been made or the search is cancelled.
+pub trait SolutionStream: Iterator<Item = Solution> {
+    /// Takes the last multi-loop model seen, the fallback when no solution turns up.
+    fn take_last_solution(&mut self) -> Option<Solution>;
+    /// The solver ran out of models, so every solution has been yielded.
+    fn exhausted(&self) -> bool;
+}
+
+/// A SAT solver kept alive across the calls of a [`BlockingLoop`].
+pub trait SatSession {
+    /// Ask for the next model, as one value per variable with the edges first. `Ok(None)`
+    /// when there is none left, `Err` when the solver gave up.
+    fn solve(&mut self) -> Result<Option<Vec<Edge>>, String>;
+    /// Add clauses over edges. `false` when they make the formula unsatisfiable.
+    fn add_clauses(&mut self, clauses: &[EdgeClause]) -> bool;
+}
+
+/// The blocking loop shared by the SAT backends: ask `session` for a model, block it with
+/// [`handle_ok_2`], and yield it when it is a single loop.
+pub struct BlockingLoop<S> {
+    puzzle: Puzzle,
+    options: SolveOptions,
+    facts: HashMap<usize, bool>,
+    base_edges: Vec<Edge>,
+    session: S,
+    counter: usize,
+    last_solution: Option<Solution>,
+    exhausted: bool,
+    /// The solver returned an error; asking again won't help.
+    failed: bool,
+}
+
+impl<S: SatSession> BlockingLoop<S> {
+    pub fn new(
+        puzzle: &Puzzle,
+        options: &SolveOptions,
+        facts: HashMap<usize, bool>,
+        base_edges: Vec<Edge>,
+        session: S,
+    ) -> Self {
+        options.log(format_args!("facts found: {}", facts.len()));
+        BlockingLoop {
+            puzzle: puzzle.clone(),
+            options: options.clone(),
+            facts,
+            base_edges,
+            session,
+            counter: 0,
+            last_solution: None,
+            exhausted: false,
+            failed: false,
+        }
+    }
+
+    /// SAT calls made so far.
+    pub fn sat_calls(&self) -> usize {
+        self.counter
+    }
+}
+
+impl<S: SatSession> Iterator for BlockingLoop<S> {
+    type Item = Solution;
+
+    fn next(&mut self) -> Option<Solution> {
+        while !self.exhausted
+            && !self.failed
+            && self.counter < self.options.max_iterations
+            && !self.options.cancelled()
+        {
+            if self.counter.is_multiple_of(500) {
+                self.options.log(format_args!("attempt {}", self.counter));
+            }
+            self.counter += 1;
+            let model = match self.session.solve() {
+                Ok(Some(model)) => model,
+                Ok(None) => {
+                    self.options.log(format_args!("No more solutions!"));
+                    self.exhausted = true;
+                    break;
+                }
+                Err(e) => {
+                    self.options.log(format_args!("error: {e}"));
+                    self.failed = true;
+                    break;
+                }
+            };
+            let (new_clauses, model) = handle_ok_2(
+                &self.puzzle,
+                &self.facts,
+                &self.base_edges,
+                &model,
+                &self.options,
+            );
+            if !self.session.add_clauses(&new_clauses) {
+                self.options.log(format_args!("No more solutions!"));
+                self.exhausted = true;
+            }
+            match model {
+                Model::Loop(solution) => return Some(solution),
+                Model::MultiLoop(solution) => self.last_solution = Some(solution),
+            }
+        }
+        None
+    }
+}
+
+impl<S: SatSession> SolutionStream for BlockingLoop<S> {
+    fn take_last_solution(&mut self) -> Option<Solution> {
+        self.last_solution.take()
+    }
+
+    fn exhausted(&self) -> bool {
+        self.exhausted
+    }
+}
+
+/// Pull up to `limit` solutions from `stream`.
+pub fn search_stream(stream: &mut dyn SolutionStream, limit: usize) -> Search {
+    let solutions = (&mut *stream).take(limit).collect();
+    Search {
+        solutions,
+        last_solution: stream.take_last_solution(),
+        exhausted: stream.exhausted(),
+    }
+}
+
+/// Turn the search result into an outcome, keeping the first single-loop solution.
+pub fn into_outcome(search: Search, options: &SolveOptions) -> SolveOutcome {
+    let Search {
+        mut solutions,
+        last_solution,
+        ..
+    } = search;
+    if solutions.is_empty() {
+        options.log(format_args!("no proper solutions, well here's last thing:"));
+        if last_solution.is_none() {
+            options.log(format_args!("oh well"));
+        }
+        SolveOutcome::Unsolved(last_solution)
+    } else {
+        SolveOutcome::Solved(solutions.swap_remove(0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A loop around the top-left 2x2 block and a unit loop around the bottom-right cell.
+    fn two_loops(p: &Puzzle) -> Vec<Edge> {
+        let mut edges = vec![Edge::Empty; p.edge_count()];
+        for ix in [
+            p.edge_ix(0, 0, true),
+            p.edge_ix(0, 1, true),
+            p.edge_ix(2, 0, true),
+            p.edge_ix(2, 1, true),
+            p.edge_ix(0, 0, false),
+            p.edge_ix(1, 0, false),
+            p.edge_ix(0, 2, false),
+            p.edge_ix(1, 2, false),
+        ] {
+            edges[ix] = Edge::Filled;
+        }
+        let (a, b, c, d) = p.edges_around_cell(3, 3);
+        for ix in [a, b, c, d] {
+            edges[ix] = Edge::Filled;
+        }
+        edges
+    }
+
+    #[test]
+    fn keeps_loop_that_satisfies_every_clue() {
+        let mut cells = vec![vec![-1; 4]; 4];
+        cells[0][0] = 2;
+        let p = Puzzle::from_grid(cells);
+        let edges = two_loops(&p);
+        assert_eq!(find_loops_edges(&p, &edges).len(), 2);
+
+        let solution = single_loop_solution(&p, &edges).unwrap();
+        assert!(single_loop_edge(&p, &solution));
+        assert_eq!(solution.iter().filter(|&&e| e == Edge::Filled).count(), 8);
+    }
+
+    #[test]
+    fn subtour_cuts_are_violated_by_the_model() {
+        let mut cells = vec![vec![-1; 4]; 4];
+        cells[0][0] = 2;
+        cells[2][3] = 1;
+        let p = Puzzle::from_grid(cells);
+        let edges = two_loops(&p);
+        assert!(single_loop_solution(&p, &edges).is_none());
+
+        let plain = blocking_clauses(&p, &edges, false);
+        let with_cuts = blocking_clauses(&p, &edges, true);
+        assert_eq!(plain.len(), 2);
+        // The plain clauses plus one cut per loop edge rule out the model.
+        let violated = with_cuts
+            .iter()
+            .filter(|clause| {
+                clause
+                    .iter()
+                    .all(|&(e, filled)| (edges[e] == Edge::Filled) != filled)
+            })
+            .count();
+        assert_eq!(violated, 2 + 12);
+    }
 }
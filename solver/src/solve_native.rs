@@ -0,0 +1,607 @@
+//! Backtracking search over the edge board, with no SAT library behind it.
+//!
+//! Every node propagates the clue and vertex rules and a few loop-aware ones: an edge that
+//! would close a path into a loop stays empty unless that loop is the whole solution, the
+//! loop stays connected, and every cell is either inside or outside it. With `pre_solve` the
+//! pattern engine ([`extend_facts`]) runs at every node as well. Before branching, every
+//! unknown edge is tried both ways: an edge that fails one way is settled, and the search
+//! branches on the edge that settles the most others.
+use std::collections::HashMap;
+
+use crate::backend::{Capabilities, SolverBackend};
+use crate::colouring::{edge_cells, Colours};
+use crate::data::pattern::Edge;
+use crate::data::puzzle::Puzzle;
+use crate::data::solution::Solution;
+use crate::patterns::{extend_facts, facts_to_edges};
+use crate::solve_common::{pre_solve_facts, satisfies_clues, SolutionStream};
+use crate::SolveOptions;
+
+pub struct Native;
+
+impl SolverBackend for Native {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    /// Every board it finishes is a single loop already: there is nothing to block, and no
+    /// connectivity encoding to switch on.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            blocking: false,
+            connectivity: false,
+        }
+    }
+
+    fn solutions(&self, puzzle: &Puzzle, options: &SolveOptions) -> Box<dyn SolutionStream> {
+        Box::new(solutions_native(puzzle, options))
+    }
+}
+
+/// The board can't be completed.
+struct Conflict;
+
+/// What [`Board::probe`] found.
+enum Probe {
+    /// Some edge got set.
+    Settled,
+    /// Nothing to set; branch on this edge, if any is left.
+    Branch(Option<usize>),
+}
+
+/// Who touches whom on the grid, worked out once per puzzle.
+struct Grid {
+    /// Clue and edges of every clue cell.
+    clues: Vec<(usize, [usize; 4])>,
+    /// Indices into `clues` of the clue cells next to each edge.
+    edge_clues: Vec<Vec<usize>>,
+    /// The two vertices of each edge.
+    edge_vertices: Vec<(usize, usize)>,
+    vertex_edges: Vec<Vec<usize>>,
+    /// The two cells on either side of each edge. Everything beyond the border is one cell,
+    /// `cell_count - 1`.
+    edge_cells: Vec<(usize, usize)>,
+    cell_count: usize,
+}
+
+impl Grid {
+    fn new(p: &Puzzle) -> Grid {
+        let cols = p.ysize + 1;
+        let mut clues = vec![];
+        let mut edge_clues = vec![vec![]; p.edge_count()];
+        for i in 0..p.xsize {
+            for j in 0..p.ysize {
+                if p.cells[i][j] < 0 {
+                    continue;
+                }
+                let (a, b, c, d) = p.edges_around_cell(i, j);
+                for e in [a, b, c, d] {
+                    edge_clues[e].push(clues.len());
+                }
+                clues.push((p.cells[i][j] as usize, [a, b, c, d]));
+            }
+        }
+        let edge_vertices = (0..p.edge_count())
+            .map(|e| {
+                let ((i0, j0), (i1, j1)) = p.edge_endpoints(e);
+                (i0 * cols + j0, i1 * cols + j1)
+            })
+            .collect();
+        let vertex_edges = (0..(p.xsize + 1) * cols)
+            .map(|v| p.edges_around_point(v / cols, v % cols))
+            .collect();
+        Grid {
+            clues,
+            edge_clues,
+            edge_vertices,
+            vertex_edges,
+            edge_cells: edge_cells(p),
+            cell_count: p.xsize * p.ysize + 1,
+        }
+    }
+
+    /// The edge joining vertices `a` and `b`, if they are neighbours.
+    fn edge_between(&self, a: usize, b: usize) -> Option<usize> {
+        self.vertex_edges[a].iter().copied().find(|&e| {
+            let (u, v) = self.edge_vertices[e];
+            (u, v) == (a, b) || (u, v) == (b, a)
+        })
+    }
+}
+
+/// A partly solved board and the paths its filled edges form.
+#[derive(Clone)]
+struct Board {
+    edges: Vec<Edge>,
+    /// Filled edges at each vertex.
+    degree: Vec<u8>,
+    /// For a vertex at the end of a path, the vertex at the other end.
+    other_end: Vec<usize>,
+    /// For a vertex at the end of a path, the number of edges on it.
+    path_len: Vec<usize>,
+    filled: usize,
+    unknown: usize,
+    /// The filled edges form a closed loop.
+    closed: bool,
+    /// Edges set since the last propagation.
+    queue: Vec<usize>,
+    /// How to take back what [`Board::set`] did, while [`Board::probe`] is trying an edge.
+    trail: Option<Vec<Undo>>,
+}
+
+/// One change recorded on the trail.
+#[derive(Clone)]
+enum Undo {
+    Set(usize),
+    /// Paths joined at vertices `u` and `v`, with ends `a` and `b` as they were before.
+    Fill {
+        u: usize,
+        v: usize,
+        a: (usize, usize, usize),
+        b: (usize, usize, usize),
+        closed: bool,
+    },
+}
+
+impl Board {
+    fn new(grid: &Grid) -> Board {
+        let vertices = grid.vertex_edges.len();
+        Board {
+            edges: vec![Edge::Unknown; grid.edge_vertices.len()],
+            degree: vec![0; vertices],
+            other_end: (0..vertices).collect(),
+            path_len: vec![0; vertices],
+            filled: 0,
+            unknown: grid.edge_vertices.len(),
+            closed: false,
+            // Every rule gets checked once at the start.
+            queue: (0..grid.edge_vertices.len()).collect(),
+            trail: None,
+        }
+    }
+
+    /// Take back everything on the trail.
+    fn undo(&mut self) {
+        while let Some(step) = self.trail.as_mut().and_then(Vec::pop) {
+            match step {
+                Undo::Set(e) => {
+                    self.edges[e] = Edge::Unknown;
+                    self.unknown += 1;
+                }
+                Undo::Fill { u, v, a, b, closed } => {
+                    self.filled -= 1;
+                    self.degree[u] -= 1;
+                    self.degree[v] -= 1;
+                    for (x, other_end, path_len) in [a, b] {
+                        self.other_end[x] = other_end;
+                        self.path_len[x] = path_len;
+                    }
+                    self.closed = closed;
+                }
+            }
+        }
+        self.queue.clear();
+    }
+
+    fn set(&mut self, grid: &Grid, e: usize, value: Edge) -> Result<(), Conflict> {
+        if self.edges[e] == value {
+            return Ok(());
+        }
+        if self.edges[e] != Edge::Unknown {
+            return Err(Conflict);
+        }
+        self.edges[e] = value;
+        self.unknown -= 1;
+        self.queue.push(e);
+        if let Some(trail) = &mut self.trail {
+            trail.push(Undo::Set(e));
+        }
+        if value == Edge::Filled {
+            self.fill(grid, e)?;
+        }
+        Ok(())
+    }
+
+    /// Join the paths at both ends of the newly filled edge `e`.
+    fn fill(&mut self, grid: &Grid, e: usize) -> Result<(), Conflict> {
+        let (u, v) = grid.edge_vertices[e];
+        if self.closed || self.degree[u] == 2 || self.degree[v] == 2 {
+            return Err(Conflict);
+        }
+        let end = |x: usize| if self.degree[x] == 0 { x } else { self.other_end[x] };
+        let (a, b) = (end(u), end(v));
+        if let Some(trail) = &mut self.trail {
+            trail.push(Undo::Fill {
+                u,
+                v,
+                a: (a, self.other_end[a], self.path_len[a]),
+                b: (b, self.other_end[b], self.path_len[b]),
+                closed: self.closed,
+            });
+        }
+        self.filled += 1;
+        self.degree[u] += 1;
+        self.degree[v] += 1;
+        if a == v {
+            // Closed a loop: it has to hold every filled edge, and nothing else gets filled.
+            if self.path_len[a] + 1 != self.filled {
+                return Err(Conflict);
+            }
+            self.closed = true;
+            for x in 0..self.edges.len() {
+                if self.edges[x] == Edge::Unknown {
+                    self.set(grid, x, Edge::Empty)?;
+                }
+            }
+            return Ok(());
+        }
+        let len = self.path_len[a] + self.path_len[b] + 1;
+        self.other_end[a] = b;
+        self.other_end[b] = a;
+        self.path_len[a] = len;
+        self.path_len[b] = len;
+        // Never close the path early.
+        if let Some(x) = grid.edge_between(a, b) {
+            if self.edges[x] == Edge::Unknown && !self.closing_solves(grid, x, len) {
+                self.set(grid, x, Edge::Empty)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Filling `x` closes a path of `len` edges into a loop that satisfies every clue.
+    fn closing_solves(&self, grid: &Grid, x: usize, len: usize) -> bool {
+        len == self.filled
+            && grid.clues.iter().all(|(clue, edges)| {
+                let filled = edges
+                    .iter()
+                    .filter(|&&e| e == x || self.edges[e] == Edge::Filled)
+                    .count();
+                filled == *clue
+            })
+    }
+
+    /// Apply the clue and vertex rules until nothing changes.
+    fn propagate(&mut self, grid: &Grid) -> Result<(), Conflict> {
+        while let Some(e) = self.queue.pop() {
+            for &c in &grid.edge_clues[e] {
+                let (clue, edges) = grid.clues[c];
+                let (filled, unknown) = self.count(&edges);
+                if filled > clue || filled + unknown < clue {
+                    return Err(Conflict);
+                } else if filled == clue {
+                    self.force(grid, &edges, Edge::Empty)?;
+                } else if filled + unknown == clue {
+                    self.force(grid, &edges, Edge::Filled)?;
+                }
+            }
+            let (u, v) = grid.edge_vertices[e];
+            for x in [u, v] {
+                let edges = &grid.vertex_edges[x];
+                match (self.degree[x], self.count(edges).1) {
+                    (2, _) | (0, 1) => self.force(grid, edges, Edge::Empty)?,
+                    (1, 0) => return Err(Conflict),
+                    (1, 1) => self.force(grid, edges, Edge::Filled)?,
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// [`Board::propagate`] and [`Board::connect`] until neither changes anything.
+    fn settle(&mut self, grid: &Grid) -> Result<(), Conflict> {
+        while !self.queue.is_empty() {
+            self.propagate(grid)?;
+            self.connect(grid)?;
+            self.colour(grid)?;
+        }
+        Ok(())
+    }
+
+    /// Every cell is inside or outside the loop: cells across a filled edge differ, cells
+    /// across an empty one match. An unknown edge between two cells whose colours are
+    /// already tied together gets set.
+    fn colour(&mut self, grid: &Grid) -> Result<(), Conflict> {
+        let mut colours = Colours::new(grid.cell_count);
+        for e in 0..self.edges.len() {
+            let differ = match self.edges[e] {
+                Edge::Filled => true,
+                Edge::Empty => false,
+                _ => continue,
+            };
+            let (a, b) = grid.edge_cells[e];
+            colours.tie(a, b, differ).ok_or(Conflict)?;
+        }
+        for e in 0..self.edges.len() {
+            if self.edges[e] != Edge::Unknown {
+                continue;
+            }
+            let (a, b) = grid.edge_cells[e];
+            if let Some(differ) = colours.relation(a, b) {
+                self.set(grid, e, if differ { Edge::Filled } else { Edge::Empty })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The loop has to be connected. Edges that can't reach the filled ones through edges
+    /// that aren't empty are cleared, and every positive clue has to be reachable.
+    fn connect(&mut self, grid: &Grid) -> Result<(), Conflict> {
+        let Some(start) = self.edges.iter().position(|&e| e == Edge::Filled) else {
+            return Ok(());
+        };
+        if self.closed {
+            return Ok(());
+        }
+        let mut reached = vec![false; self.degree.len()];
+        let mut todo = vec![grid.edge_vertices[start].0];
+        reached[todo[0]] = true;
+        while let Some(x) = todo.pop() {
+            for &e in &grid.vertex_edges[x] {
+                if self.edges[e] == Edge::Empty {
+                    continue;
+                }
+                let (u, v) = grid.edge_vertices[e];
+                let y = if u == x { v } else { u };
+                if !reached[y] {
+                    reached[y] = true;
+                    todo.push(y);
+                }
+            }
+        }
+        let reachable = |e: usize| self.edges[e] != Edge::Empty && reached[grid.edge_vertices[e].0];
+        if (0..self.edges.len()).any(|e| self.edges[e] == Edge::Filled && !reachable(e))
+            || grid
+                .clues
+                .iter()
+                .any(|(clue, edges)| *clue > 0 && !edges.iter().any(|&e| reachable(e)))
+        {
+            return Err(Conflict);
+        }
+        for e in 0..self.edges.len() {
+            if self.edges[e] == Edge::Unknown && !reached[grid.edge_vertices[e].0] {
+                self.set(grid, e, Edge::Empty)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Try each unknown edge both ways. When one way runs into a conflict straight away,
+    /// the edge takes the other value. Otherwise the edge that settles the most other edges
+    /// either way is the best one to branch on.
+    fn probe(&mut self, grid: &Grid) -> Result<Probe, Conflict> {
+        let mut best = None;
+        let mut best_score = 0;
+        for e in 0..self.edges.len() {
+            if self.edges[e] != Edge::Unknown {
+                continue;
+            }
+            let mut score = 1;
+            for (value, other) in [(Edge::Filled, Edge::Empty), (Edge::Empty, Edge::Filled)] {
+                let before = self.unknown;
+                self.trail = Some(vec![]);
+                let result = self.set(grid, e, value).and_then(|_| self.propagate(grid));
+                let settled = before - self.unknown;
+                self.undo();
+                self.trail = None;
+                if result.is_err() {
+                    self.set(grid, e, other)?;
+                    self.settle(grid)?;
+                    return Ok(Probe::Settled);
+                }
+                score *= 1 + settled;
+            }
+            if score > best_score {
+                (best, best_score) = (Some(e), score);
+            }
+        }
+        Ok(Probe::Branch(best))
+    }
+
+    /// Filled and unknown edges among `edges`.
+    fn count(&self, edges: &[usize]) -> (usize, usize) {
+        let count = |value| edges.iter().filter(|&&e| self.edges[e] == value).count();
+        (count(Edge::Filled), count(Edge::Unknown))
+    }
+
+    /// Set every unknown edge among `edges` to `value`.
+    fn force(&mut self, grid: &Grid, edges: &[usize], value: Edge) -> Result<(), Conflict> {
+        for &e in edges {
+            if self.edges[e] == Edge::Unknown {
+                self.set(grid, e, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every distinct single-loop solution, found one at a time by a depth-first search. There
+/// are no SAT calls here, so `max_iterations` doesn't apply: the search runs until it is done
+/// or cancelled.
+pub fn solutions_native(p: &Puzzle, options: &SolveOptions) -> NativeSolutions {
+    let facts = pre_solve_facts(p, options);
+    let options = SolveOptions {
+        // After a contradiction, the patterns would run into it again at every node.
+        pre_solve: facts.is_some(),
+        ..options.clone()
+    };
+    let facts = facts.unwrap_or_default();
+    options.log(format_args!("facts found: {}", facts.len()));
+    let base_edges = facts_to_edges(p, &facts);
+
+    let grid = Grid::new(p);
+    let mut board = Board::new(&grid);
+    let mut stack = vec![];
+    if facts
+        .iter()
+        .all(|(&e, &v)| board.set(&grid, e, if v { Edge::Filled } else { Edge::Empty }).is_ok())
+    {
+        stack.push((board, None));
+    }
+
+    NativeSolutions {
+        puzzle: p.clone(),
+        options,
+        facts,
+        base_edges,
+        grid,
+        stack,
+        counter: 0,
+    }
+}
+
+/// See [`solutions_native`].
+pub struct NativeSolutions {
+    puzzle: Puzzle,
+    options: SolveOptions,
+    facts: HashMap<usize, bool>,
+    base_edges: Vec<Edge>,
+    grid: Grid,
+    /// Boards left to explore, each with the decision to apply to it first.
+    stack: Vec<(Board, Option<(usize, Edge)>)>,
+    /// Search nodes visited so far.
+    counter: usize,
+}
+
+impl NativeSolutions {
+    /// Propagate `board` to a fixpoint, running the pattern engine when pre-solve is on.
+    /// Returns the edge to branch on, if any is left.
+    fn propagate(&self, board: &mut Board) -> Result<Option<usize>, Conflict> {
+        loop {
+            board.settle(&self.grid)?;
+            if board.closed {
+                return Ok(None);
+            }
+            if self.options.pre_solve {
+                let facts = extend_facts(&self.puzzle, &self.options.patterns, &board.edges)
+                    .map_err(|_| Conflict)?;
+                for (e, v) in facts {
+                    board.set(&self.grid, e, if v { Edge::Filled } else { Edge::Empty })?;
+                }
+                if !board.queue.is_empty() {
+                    continue;
+                }
+            }
+            if let Probe::Branch(e) = board.probe(&self.grid)? {
+                return Ok(e);
+            }
+        }
+    }
+}
+
+impl Iterator for NativeSolutions {
+    type Item = Solution;
+
+    fn next(&mut self) -> Option<Solution> {
+        while !self.options.cancelled() {
+            let Some((mut board, decision)) = self.stack.pop() else {
+                break;
+            };
+            if self.counter.is_multiple_of(10_000) {
+                self.options.log(format_args!("node {}", self.counter));
+            }
+            self.counter += 1;
+
+            if let Some((e, value)) = decision {
+                if board.set(&self.grid, e, value).is_err() {
+                    continue;
+                }
+            }
+            let Ok(branch) = self.propagate(&mut board) else {
+                continue;
+            };
+            match branch {
+                Some(e) => {
+                    self.stack.push((board.clone(), Some((e, Edge::Empty))));
+                    self.stack.push((board, Some((e, Edge::Filled))));
+                }
+                None if board.closed && satisfies_clues(&self.puzzle, &board.edges) => {
+                    self.options.log(format_args!("WIN! found single-loop solution!"));
+                    return Some(Solution {
+                        puzzle: self.puzzle.clone(),
+                        edges: board.edges,
+                        edges_pre_solve: self.base_edges.clone(),
+                        facts: self.facts.clone(),
+                    });
+                }
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+impl SolutionStream for NativeSolutions {
+    /// The search never settles on a multi-loop model.
+    fn take_last_solution(&mut self) -> Option<Solution> {
+        None
+    }
+
+    fn exhausted(&self) -> bool {
+        self.stack.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{solutions_native, Native};
+    use crate::data::puzzle::Puzzle;
+    use crate::{SolveOptions, SolveOutcome, SolverBackend};
+
+    fn no_pre() -> SolveOptions {
+        SolveOptions {
+            pre_solve: false,
+            ..SolveOptions::default()
+        }
+    }
+
+    #[test]
+    fn solves_simplest_2x2() {
+        let s = Native.solve(&Puzzle::from(&[[3, 2], [-1, -1]]), &no_pre());
+        assert!(s.is_solved());
+    }
+
+    #[test]
+    fn handles_bad_puzzle() {
+        let s = Native.solve(&Puzzle::from(&[[0, 0], [0, 2]]), &no_pre());
+        assert!(matches!(s, SolveOutcome::Unsolved(None)));
+    }
+
+    #[test]
+    fn enumerates_every_solution() {
+        let p = Puzzle::from(&[[2, -1], [-1, -1]]);
+        let mut stream = solutions_native(&p, &no_pre());
+        let all: Vec<_> = stream.by_ref().collect();
+        assert_eq!(all.len(), 3);
+        for (i, a) in all.iter().enumerate() {
+            assert!(all[i + 1..].iter().all(|b| b.edges != a.edges));
+        }
+    }
+
+    #[test]
+    fn never_closes_a_loop_early() {
+        // Two 4s in opposite corners can only be two separate squares.
+        let p = Puzzle::from(&[[4, -1, -1], [-1, -1, -1], [-1, -1, 4]]);
+        assert_eq!(solutions_native(&p, &no_pre()).count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "varisat")]
+    fn agrees_with_sat() {
+        use crate::parse;
+        use crate::solve_varisat::Varisat;
+
+        let grid = parse::from_string("5x5:3322121021211311212201123").unwrap();
+        let p = Puzzle::from_grid(grid);
+        let sat = Varisat.solve(&p, &SolveOptions::default()).into_best().unwrap();
+        for pre_solve in [false, true] {
+            let options = SolveOptions {
+                pre_solve,
+                ..SolveOptions::default()
+            };
+            let native = Native.solve(&p, &options).into_best().unwrap();
+            assert_eq!(sat.edges, native.edges);
+        }
+    }
+}
@@ -1,84 +1,143 @@
 use splr::solver::*;
-use crate::adapter::SplrRules;
-use crate::data::solution::Solution;
-use crate::parse::Cell;
-use crate::solve_common::{handle_ok_2, solve_form_conditions};
+use splr::{Config, SolverError};
+use crate::adapter::{SlitherlinkerLit, SplrRules};
+use crate::backend::{Capabilities, SolverBackend};
+use crate::data::pattern::Edge;
+use crate::data::puzzle::Puzzle;
+use crate::solve_common::{
+    solve_form_conditions, BlockingLoop, EdgeClause, SatSession, SolutionStream,
+};
+use crate::SolveOptions;
 
-pub fn solve_splr(grid: Vec<Vec<Cell>>, pre_solve: bool, prefix: &str) -> Option<Vec<Solution>> {
-    let mut formula: SplrRules = SplrRules::new();
+pub struct Splr;
+
+impl SolverBackend for Splr {
+    fn name(&self) -> &'static str {
+        "splr"
+    }
 
-    let (p, facts, base_edges) = solve_form_conditions(
-        grid, pre_solve, &mut formula, prefix);
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            blocking: true,
+            connectivity: true,
+        }
+    }
 
-    let mut final_formula: Vec<Vec<i32>> = formula
+    fn solutions(&self, puzzle: &Puzzle, options: &SolveOptions) -> Box<dyn SolutionStream> {
+        Box::new(solutions_splr(puzzle, options))
+    }
+}
+
+/// Every distinct single-loop solution, found one at a time on a single incremental solver.
+pub fn solutions_splr(p: &Puzzle, options: &SolveOptions) -> BlockingLoop<SplrSession> {
+    let mut formula: SplrRules = SplrRules::new();
+    let (facts, base_edges) = solve_form_conditions(p, options, &mut formula);
+
+    let clauses: Vec<Vec<i32>> = formula
         .iter()
         .map(|is| is.iter().map(|x| x.into()).collect())
         .collect();
 
-    let mut solutions = vec![];
-    let mut counter = 0;
-    let mut last_solution = None;
-    println!("{prefix}facts found: {}", facts.len());
-    while counter < 10000 {
-        if counter % 500 == 0 {
-            println!("{prefix}attempt {counter}");
-        }
-        let solve_result = Certificate::try_from(final_formula.clone());
-        match solve_result {
-            Ok(Certificate::SAT(sol)) => {
-                let (new_clauses, approx_solution) = handle_ok_2(
-                    &p,
-                    &facts,
-                    &base_edges,
-                    &mut solutions,
-                    sol.as_slice(),
-                    prefix,
-                );
-                if approx_solution.is_some() {
-                    last_solution = approx_solution;
-                }
-                final_formula.extend(new_clauses);
-                if !solutions.is_empty() { break; }
-            }
-            Ok(Certificate::UNSAT) => {
-                println!("{prefix}No more solutions!");
-                break;
-            }
-            Err(e) => {
-                println!("{prefix}error: {}", e);
-                break;
-            }
+    let session = match Solver::try_from((Config::default(), clauses.as_slice())) {
+        Ok(solver) => SplrSession::Ready(Box::new(solver)),
+        Err(Ok(_)) => SplrSession::Unsat,
+        Err(Err(e)) if proves_unsat(&e) => SplrSession::Unsat,
+        Err(Err(e)) => SplrSession::Failed(format!("{e:?}")),
+    };
+    BlockingLoop::new(p, options, facts, base_edges, session)
+}
+
+/// Splr reports some contradictions as errors rather than as `UNSAT`.
+fn proves_unsat(e: &SolverError) -> bool {
+    matches!(
+        e,
+        SolverError::EmptyClause | SolverError::Inconsistent | SolverError::RootLevelConflict(_)
+    )
+}
+
+pub enum SplrSession {
+    Ready(Box<Solver>),
+    /// The formula was found unsatisfiable while loading it.
+    Unsat,
+    /// The formula could not be loaded.
+    Failed(String),
+}
+
+impl SatSession for SplrSession {
+    fn solve(&mut self) -> Result<Option<Vec<Edge>>, String> {
+        let solver = match self {
+            SplrSession::Ready(solver) => solver,
+            SplrSession::Unsat => return Ok(None),
+            SplrSession::Failed(e) => return Err(e.clone()),
         };
-        counter += 1;
+        match solver.solve() {
+            Ok(Certificate::SAT(sol)) => Ok(Some(sol.iter().map(|l| l.to_edge()).collect())),
+            Ok(Certificate::UNSAT) => Ok(None),
+            Err(e) if proves_unsat(&e) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
     }
-    if solutions.is_empty() {
-        println!("{prefix}no proper solutions, well here's last thing:");
-        match last_solution {
-            Some(s) => solutions.push(s),
-            None => println!("{prefix}oh well"),
+
+    /// Add the clauses and get the solver ready for the next call.
+    fn add_clauses(&mut self, clauses: &[EdgeClause]) -> bool {
+        let SplrSession::Ready(solver) = self else {
+            return false;
         };
+        for clause in clauses {
+            let lits: Vec<i32> = clause
+                .iter()
+                .map(|&(e, filled)| if filled { 1 + e as i32 } else { -1 - e as i32 })
+                .collect();
+            if solver.add_clause(lits).is_err() {
+                return false;
+            }
+        }
+        solver.reset();
+        true
     }
-    Some(solutions)
 }
 
 #[cfg(test)]
 mod test {
-    use super::solve_splr;
+    use super::{solutions_splr, Splr};
+    use crate::data::puzzle::Puzzle;
+    use crate::solve_common::SolutionStream;
+    use crate::{SolveOptions, SolveOutcome, SolverBackend};
+
+    fn no_pre() -> SolveOptions {
+        SolveOptions {
+            pre_solve: false,
+            ..SolveOptions::default()
+        }
+    }
 
     #[test]
     fn solves_simplest_2x2() {
-        let s = solve_splr(vec![vec![3, 2], vec![-1, -1]], false, "");
-        assert!(s.is_some());
-        let val = s.unwrap();
-        assert_eq!(val.len(), 1);
+        let s = Splr.solve(&Puzzle::from(&[[3, 2], [-1, -1]]), &no_pre());
+        assert!(s.is_solved());
+    }
+
+    #[test]
+    fn keeps_one_solver_across_calls() {
+        let p = Puzzle::from(&[[2, -1], [-1, -1]]);
+        let mut stream = solutions_splr(&p, &no_pre());
+        let all: Vec<_> = stream.by_ref().collect();
+        assert_eq!(all.len(), 3);
+        assert!(stream.exhausted());
     }
 
     #[test]
     fn handles_bad_puzzle() {
-        let s = solve_splr(
-            vec![vec![0, 0], vec![0, 2]], false, "");
+        let s = Splr.solve(
+            &Puzzle::from(&[[0, 0], [0, 2]]), &no_pre());
+
+        assert!(matches!(s, SolveOutcome::Unsolved(None)));
+    }
 
-        assert!(s.is_some());
-        assert_eq!(s.unwrap().len(), 0);
+    #[test]
+    fn bad_puzzle_is_exhausted() {
+        let mut stream = solutions_splr(&Puzzle::from(&[[0, 0], [0, 2]]), &no_pre());
+        assert!(stream.next().is_none());
+        assert!(stream.exhausted());
     }
 }
\ No newline at end of file
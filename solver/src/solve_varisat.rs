@@ -1,78 +1,303 @@
-use varisat::{CnfFormula, ExtendFormula, Solver};
-use crate::data::solution::Solution;
-use crate::parse::Cell;
-use crate::solve_common::{handle_ok_2, solve_form_conditions};
+use varisat::{CnfFormula, ExtendFormula, Lit, Solver};
+use crate::adapter::SlitherlinkerLit;
+use crate::backend::{Capabilities, SolverBackend};
+use crate::data::pattern::Edge;
+use crate::data::puzzle::Puzzle;
+use crate::solve_common::{
+    blocking_clauses, find_loops_edges, satisfies_clues, solve_form_conditions, BlockingLoop,
+    EdgeClause, SatSession, SolutionStream,
+};
+use crate::SolveOptions;
 
-pub fn solve(grid: Vec<Vec<Cell>>, pre_solve: bool, prefix: &str) -> Option<Vec<Solution>> {
+pub struct Varisat;
+
+impl SolverBackend for Varisat {
+    fn name(&self) -> &'static str {
+        "varisat"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            blocking: true,
+            connectivity: true,
+        }
+    }
+
+    fn solutions(&self, puzzle: &Puzzle, options: &SolveOptions) -> Box<dyn SolutionStream> {
+        Box::new(solutions(puzzle, options))
+    }
+}
+
+/// Every distinct single-loop solution, found one at a time on a single incremental solver.
+pub fn solutions(puzzle: &Puzzle, options: &SolveOptions) -> BlockingLoop<VarisatSession> {
     let mut formula = CnfFormula::new();
+    let (facts, base_edges) = solve_form_conditions(puzzle, options, &mut formula);
 
+    let mut solver = Solver::default();
+    solver.add_formula(&formula);
+    BlockingLoop::new(puzzle, options, facts, base_edges, VarisatSession { solver })
+}
 
-    let (puzzle, facts, base_edges) = solve_form_conditions(
-        grid, pre_solve, &mut formula, prefix);
+/// The backbone of `puzzle`: every edge that has the same value in all of its single-loop
+/// solutions that agree with the known edges of `edges`. The other edges are `Unknown`.
+/// `Ok(None)` when there is no such solution.
+///
+/// One solution gives the candidates. Each candidate is then assumed to be the other way:
+/// when that has no solution it is in the backbone, and every solution found on the way
+/// rules out the candidates it disagrees with.
+pub fn backbone(puzzle: &Puzzle, edges: &[Edge]) -> Result<Option<Vec<Edge>>, String> {
+    // Patterns may take uniqueness for granted.
+    let options = SolveOptions {
+        pre_solve: false,
+        ..SolveOptions::default()
+    };
+    let mut formula = CnfFormula::new();
+    solve_form_conditions(puzzle, &options, &mut formula);
+    let mut session = VarisatSession {
+        solver: Solver::default(),
+    };
+    session.solver.add_formula(&formula);
+    let known: Vec<(usize, bool)> = edges
+        .iter()
+        .enumerate()
+        .filter(|(_, &e)| e == Edge::Filled || e == Edge::Empty)
+        .map(|(ix, &e)| (ix, e == Edge::Filled))
+        .collect();
+    let units: Vec<EdgeClause> = known.iter().map(|&lit| vec![lit]).collect();
+    session.add_clauses(&units);
 
-    let mut s = Solver::default();
-    s.add_formula(&formula);
+    let mut found = vec![];
+    session.find_solutions(puzzle, &known, &[], &mut found)?;
+    let Some(mut backbone) = found.pop() else {
+        return Ok(None);
+    };
+    forget_differences(&mut backbone, &mut found);
+    for ix in 0..backbone.len() {
+        while backbone[ix] != Edge::Unknown {
+            let filled = backbone[ix] == Edge::Filled;
+            let other_way = [(ix, !filled)];
+            let possible = session.find_solutions(puzzle, &known, &other_way, &mut found)?;
+            forget_differences(&mut backbone, &mut found);
+            if !possible {
+                session.add_clauses(&[vec![(ix, filled)]]);
+                break;
+            }
+        }
+    }
+    Ok(Some(backbone))
+}
 
-    let mut solutions = vec![];
-    let mut counter = 0;
-    let mut last_solution = None;
-    println!("{prefix}facts found: {}", facts.len());
-    while counter < 10000 {
-        let has_solutions = s.solve().unwrap();
-        if counter % 500 == 0 {
-            println!("{prefix}attempt {counter}");
+/// Make every edge of `backbone` that some solution of `found` disagrees with `Unknown`, and
+/// empty `found`.
+fn forget_differences(backbone: &mut [Edge], found: &mut Vec<Vec<Edge>>) {
+    for other in found.drain(..) {
+        for (edge, other) in backbone.iter_mut().zip(other) {
+            if *edge != other {
+                *edge = Edge::Unknown;
+            }
         }
-        if has_solutions {
-            let current_solution = s.model().unwrap();
-            let (new_clauses, approx_solution) = handle_ok_2(
-                &puzzle,
-                &facts,
-                &base_edges,
-                &mut solutions,
-                current_solution.as_slice(),
-                prefix,
-            );
-            if approx_solution.is_some() {
-                last_solution = approx_solution;
+    }
+}
+
+pub struct VarisatSession {
+    solver: Solver<'static>,
+}
+
+impl VarisatSession {
+    /// Ask for models under `assumptions` until one has a loop that is a solution agreeing
+    /// with them. Every loop of every model is blocked on the way, and each one that is a
+    /// solution agreeing with `known` goes to `found`, so no solution is blocked unseen: a
+    /// loop that isn't a solution can't be part of one. `Ok(false)` when the models ran out.
+    fn find_solutions(
+        &mut self,
+        puzzle: &Puzzle,
+        known: &[(usize, bool)],
+        assumptions: &[(usize, bool)],
+        found: &mut Vec<Vec<Edge>>,
+    ) -> Result<bool, String> {
+        let agrees = |solution: &[Edge], lits: &[(usize, bool)]| {
+            lits.iter()
+                .all(|&(ix, filled)| (solution[ix] == Edge::Filled) == filled)
+        };
+        let lits: Vec<Lit> = assumptions
+            .iter()
+            .map(|&(ix, filled)| Lit::from_index(ix, filled))
+            .collect();
+        loop {
+            self.solver.assume(&lits);
+            let Some(model) = self.solve()? else {
+                return Ok(false);
+            };
+            let edges = &model[..puzzle.edge_count()];
+            self.add_clauses(&blocking_clauses(puzzle, edges, false));
+            let mut hit = false;
+            for lp in find_loops_edges(puzzle, edges) {
+                let mut solution = vec![Edge::Empty; edges.len()];
+                for e in lp {
+                    solution[e] = Edge::Filled;
+                }
+                if satisfies_clues(puzzle, &solution) && agrees(&solution, known) {
+                    hit |= agrees(&solution, assumptions);
+                    found.push(solution);
+                }
             }
-            for c in &new_clauses {
-                s.add_clause(c.as_slice());
+            if hit {
+                return Ok(true);
             }
-            if !solutions.is_empty() { break; }
-        } else {
-            println!("{prefix}No more solutions!");
-            break;
         }
-        counter += 1;
     }
-    if solutions.is_empty() {
-        println!("{prefix}no proper solutions, well here's last thing:");
-        match last_solution {
-            Some(s) => solutions.push(s),
-            None => println!("{prefix}oh well"),
-        };
+}
+
+impl SatSession for VarisatSession {
+    fn solve(&mut self) -> Result<Option<Vec<Edge>>, String> {
+        if !self.solver.solve().map_err(|e| e.to_string())? {
+            return Ok(None);
+        }
+        let model = self.solver.model().unwrap_or_default();
+        Ok(Some(model.iter().map(|l| l.to_edge()).collect()))
+    }
+
+    fn add_clauses(&mut self, clauses: &[EdgeClause]) -> bool {
+        for clause in clauses {
+            let lits: Vec<Lit> = clause
+                .iter()
+                .map(|&(e, filled)| Lit::from_index(e, filled))
+                .collect();
+            self.solver.add_clause(&lits);
+        }
+        true
     }
-    Some(solutions)
 }
 
 #[cfg(test)]
 mod test {
-    use super::solve;
+    use super::{backbone, solutions, Varisat};
+    use crate::data::pattern::Edge;
+    use crate::data::puzzle::Puzzle;
+    use crate::{SolveOptions, SolveOutcome, SolverBackend};
+
+    fn no_pre() -> SolveOptions {
+        SolveOptions {
+            pre_solve: false,
+            ..SolveOptions::default()
+        }
+    }
 
     #[test]
     fn solves_simplest_2x2() {
-        let s = solve(vec![vec![3, 2], vec![-1, -1]], false, "");
-        assert!(s.is_some());
-        let val = s.unwrap();
-        assert_eq!(val.len(), 1);
+        let s = Varisat.solve(&Puzzle::from(&[[3, 2], [-1, -1]]), &no_pre());
+        assert!(s.is_solved());
+    }
+
+    #[test]
+    fn enumerates_every_solution() {
+        let p = Puzzle::from(&[[2, -1], [-1, -1]]);
+        let all: Vec<_> = solutions(&p, &no_pre()).collect();
+        // Around the whole grid, or around every cell but the 2 or the opposite corner.
+        assert_eq!(all.len(), 3);
+        for (i, a) in all.iter().enumerate() {
+            assert!(all[i + 1..].iter().all(|b| b.edges != a.edges));
+        }
+        assert_eq!(solutions(&p, &no_pre()).take(2).count(), 2);
+    }
+
+    /// The edges every solution agreeing with `edges` shares, by enumerating them.
+    fn shared_edges(p: &Puzzle, edges: &[Edge]) -> Option<Vec<Edge>> {
+        let agrees = |s: &[Edge]| {
+            edges
+                .iter()
+                .zip(s)
+                .all(|(&known, &e)| known == Edge::Unknown || known == e)
+        };
+        solutions(p, &no_pre())
+            .map(|s| s.edges)
+            .filter(|s| agrees(s))
+            .reduce(|shared, s| {
+                shared
+                    .into_iter()
+                    .zip(s)
+                    .map(|(a, b)| if a == b { a } else { Edge::Unknown })
+                    .collect()
+            })
+    }
+
+    #[test]
+    fn backbone_is_what_every_solution_shares() {
+        let puzzles = [
+            Puzzle::from(&[[2, -1], [-1, -1]]),
+            Puzzle::from(&[[-1, -1, -1], [-1, 3, -1], [-1, -1, -1]]),
+            Puzzle::from_grid(vec![vec![3, 2, -1], vec![-1, -1, 1]]),
+            Puzzle::from(&[[0, 0], [0, 2]]),
+        ];
+        for p in &puzzles {
+            let mut edges = vec![Edge::Unknown; p.edge_count()];
+            assert_eq!(backbone(p, &edges), Ok(shared_edges(p, &edges)), "{p:?}");
+            edges[0] = Edge::Filled;
+            assert_eq!(backbone(p, &edges), Ok(shared_edges(p, &edges)), "{p:?}");
+        }
+
+        let p = Puzzle::from(&[[2, -1], [-1, -1]]);
+        let shared = backbone(&p, &vec![Edge::Unknown; p.edge_count()]).unwrap().unwrap();
+        assert!(shared.contains(&Edge::Unknown) && shared.contains(&Edge::Filled));
+    }
+
+    #[test]
+    fn backbone_keeps_known_edges_of_loops_found_on_the_way() {
+        let p = Puzzle::from_grid(vec![vec![-1, -1, -1, 2], vec![-1, -1, -1, 3], vec![-1; 4]]);
+        let mut edges = vec![Edge::Unknown; p.edge_count()];
+        edges[1] = Edge::Empty;
+        edges[12] = Edge::Filled;
+        assert_eq!(backbone(&p, &edges), Ok(shared_edges(&p, &edges)));
+
+        // Without clues every loop of a model is a solution.
+        let p = Puzzle::from_grid(vec![vec![-1; 4]]);
+        let mut edges = vec![Edge::Unknown; p.edge_count()];
+        edges[10] = Edge::Filled;
+        assert_eq!(backbone(&p, &edges), Ok(shared_edges(&p, &edges)));
+    }
+
+    #[test]
+    fn backbone_agrees_with_enumeration_on_random_grids() {
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % n as u64) as usize
+        };
+        for _ in 0..150 {
+            let (rows, cols) = (1 + next(3), 1 + next(4));
+            let grid: Vec<Vec<i8>> = (0..rows)
+                .map(|_| {
+                    (0..cols)
+                        .map(|_| if next(3) == 0 { next(4) as i8 } else { -1 })
+                        .collect()
+                })
+                .collect();
+            let p = Puzzle::from_grid(grid);
+            let mut edges = vec![Edge::Unknown; p.edge_count()];
+            for _ in 0..next(3) {
+                let ix = next(edges.len());
+                edges[ix] = if next(2) == 0 { Edge::Empty } else { Edge::Filled };
+            }
+            assert_eq!(backbone(&p, &edges), Ok(shared_edges(&p, &edges)), "{p:?} {edges:?}");
+        }
+    }
+
+    #[test]
+    fn backbone_of_unique_puzzle_is_its_solution() {
+        let grid = crate::parse::from_string("5x5:3322121021211311212201123").unwrap();
+        let p = Puzzle::from_grid(grid);
+        let solution = Varisat.solve(&p, &no_pre()).into_best().unwrap();
+        let edges = vec![Edge::Unknown; p.edge_count()];
+        assert_eq!(backbone(&p, &edges), Ok(Some(solution.edges)));
     }
 
     #[test]
     fn handles_bad_puzzle() {
-        let s = solve(
-            vec![vec![0, 0], vec![0, 2]], false, "");
+        let s = Varisat.solve(
+            &Puzzle::from(&[[0, 0], [0, 2]]), &no_pre());
 
-        assert!(s.is_some());
-        assert_eq!(s.unwrap().len(), 0);
+        assert!(matches!(s, SolveOutcome::Unsolved(None)));
     }
-}
\ No newline at end of file
+}
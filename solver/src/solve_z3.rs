@@ -1,21 +1,32 @@
 use std::collections::HashMap;
 
-use z3::ast::Bool;
+use z3::ast::{Bool, Int};
 use z3::{SatResult, Solver};
 
+use crate::adapter::SlitherlinkerFormula;
+use crate::backend::{Capabilities, SolverBackend};
 use crate::data::pattern::Edge;
 use crate::data::puzzle::Puzzle;
-use crate::data::solution::Solution;
-use crate::parse::Cell;
-use crate::patterns::find_facts;
-use crate::solve_common::{blocking_clause_edge_groups, find_loops_edges};
-
-/// Z3 disjunct: literal that is true iff this edge differs from the given model value.
-fn z3_edge_differs_lit(var: &Bool, model_filled: bool) -> Bool {
-    if model_filled {
-        !var
-    } else {
-        var.clone()
+use crate::patterns::facts_to_edges;
+use crate::solve_common::{pre_solve_facts, BlockingLoop, EdgeClause, SatSession, SolutionStream};
+use crate::SolveOptions;
+
+pub struct Z3;
+
+impl SolverBackend for Z3 {
+    fn name(&self) -> &'static str {
+        "z3"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            blocking: true,
+            connectivity: true,
+        }
+    }
+
+    fn solutions(&self, puzzle: &Puzzle, options: &SolveOptions) -> Box<dyn SolutionStream> {
+        Box::new(solutions_z3(puzzle, options))
     }
 }
 
@@ -60,146 +71,177 @@ fn assert_vertex_constraints(solver: &Solver, p: &Puzzle, vars: &[Bool]) {
     }
 }
 
-/// Same blocking policy as [`crate::solve_common::handle_ok_2`]: one OR per loop component.
-fn assert_blocking_groups_z3(
+/// Assert edge clauses, such as the ones from [`crate::solve_common::blocking_clauses`].
+fn assert_edge_clauses_z3(solver: &Solver, vars: &[Bool], clauses: &[EdgeClause]) {
+    for clause in clauses {
+        let lits: Vec<Bool> = clause
+            .iter()
+            .map(|&(e, filled)| if filled { vars[e].clone() } else { !&vars[e] })
+            .collect();
+        solver.assert(Bool::or(&lits));
+    }
+}
+
+/// Assert that the filled edges form one connected loop. Every vertex gets an integer label;
+/// exactly one vertex on the loop is the root, and every other vertex on the loop needs a
+/// neighbour along a filled edge with a smaller label. Labels can't decrease forever, so
+/// every vertex on the loop reaches the root. This is the integer version of
+/// [`crate::connectivity`], and lets Z3 decide the puzzle with a single `check()`.
+fn assert_connectivity_z3(
     solver: &Solver,
+    p: &Puzzle,
     vars: &[Bool],
-    edges: &[Edge],
-    groups: &[Vec<usize>],
+    facts: &HashMap<usize, bool>,
 ) {
-    for g in groups {
-        let clause: Vec<Bool> = g
-            .iter()
-            .map(|&i| z3_edge_differs_lit(&vars[i], edges[i] == Edge::Filled))
-            .collect();
-        solver.assert(Bool::or(&clause));
+    let cols = p.ysize + 1;
+    let labels: Vec<Int> = (0..(p.xsize + 1) * cols)
+        .map(|v| Int::new_const(format!("label_{v}")))
+        .collect();
+    let mut roots = vec![];
+    for i in 0..=p.xsize {
+        for j in 0..=p.ysize {
+            let v = i * cols + j;
+            let edges: Vec<Bool> = p
+                .edges_around_point(i, j)
+                .into_iter()
+                .map(|e| vars[e].clone())
+                .collect();
+            let on_loop = Bool::or(&edges);
+            let root = Bool::new_const(format!("root_{v}"));
+            solver.assert(root.implies(&on_loop));
+
+            let parents: Vec<Bool> = p
+                .edges_around_point(i, j)
+                .into_iter()
+                .filter(|e| facts.get(e) != Some(&false))
+                .map(|e| {
+                    let (a, b) = p.edge_endpoints(e);
+                    let (ui, uj) = if a == (i, j) { b } else { a };
+                    Bool::and(&[vars[e].clone(), labels[ui * cols + uj].lt(&labels[v])])
+                })
+                .collect();
+            solver.assert(Bool::and(&[on_loop, !&root]).implies(Bool::or(&parents)));
+            roots.push(root);
+        }
     }
+    let weighted: Vec<(&Bool, i32)> = roots.iter().map(|r| (r, 1)).collect();
+    solver.assert(Bool::pb_eq(&weighted, 1));
 }
 
-/// Builds puzzle, facts, base edge paint, and one Z3 [`Bool`] per grid edge.
+/// Builds facts, base edge paint, and one Z3 [`Bool`] per grid edge.
 fn z3_slitherlink_instance(
-    grid: Vec<Vec<Cell>>,
-    pre_solve: bool,
-) -> (Puzzle, HashMap<usize, bool>, Vec<Edge>, Vec<Bool>, Solver) {
-    let xsize = grid.len();
-    let ysize = grid[0].len();
-    let p = Puzzle {
-        cells: grid,
-        xsize,
-        ysize,
-    };
-
-    let facts = if pre_solve {
-        find_facts(&p)
-    } else {
-        HashMap::new()
-    };
-
-    let num_edges = (1 + xsize) * ysize + (1 + ysize) * xsize;
-    let mut base_edges = vec![Edge::Unknown; num_edges];
-    for (&k, &v) in &facts {
-        base_edges[k] = if v { Edge::Filled } else { Edge::Empty };
-    }
-
-    let vars: Vec<Bool> = (0..num_edges)
-        .map(|i| Bool::new_const(format!("e{i}")))
-        .collect();
+    p: &Puzzle,
+    options: &SolveOptions,
+) -> (HashMap<usize, bool>, Vec<Edge>, Vec<Bool>, Solver) {
+    let facts = pre_solve_facts(p, options).unwrap_or_default();
+
+    let base_edges = facts_to_edges(p, &facts);
 
     let solver = Solver::new();
+    let vars: Vec<Bool> = (0..p.edge_count()).map(|i| solver.pure_lit(i)).collect();
+
     assert_fact_units(&solver, &vars, &facts);
-    assert_cell_constraints(&solver, &p, &vars);
-    assert_vertex_constraints(&solver, &p, &vars);
+    assert_cell_constraints(&solver, p, &vars);
+    assert_vertex_constraints(&solver, p, &vars);
+    if options.connectivity {
+        assert_connectivity_z3(&solver, p, &vars, &facts);
+    }
 
-    (p, facts, base_edges, vars, solver)
+    (facts, base_edges, vars, solver)
 }
 
-pub fn solve_z3(grid: Vec<Vec<Cell>>, pre_solve: bool, prefix: &str) -> Option<Vec<Solution>> {
-    let (p, facts, base_edges, vars, solver) = z3_slitherlink_instance(grid, pre_solve);
-
-    println!("{prefix}facts found: {}", facts.len());
+/// Every distinct single-loop solution, found one at a time on a single incremental solver.
+pub fn solutions_z3(p: &Puzzle, options: &SolveOptions) -> BlockingLoop<Z3Session> {
+    let (facts, base_edges, vars, solver) = z3_slitherlink_instance(p, options);
+    BlockingLoop::new(p, options, facts, base_edges, Z3Session { vars, solver })
+}
 
-    let num_edges = vars.len();
-    let mut solutions = vec![];
-    let mut counter = 0;
-    let mut last_solution = None;
+pub struct Z3Session {
+    vars: Vec<Bool>,
+    solver: Solver,
+}
 
-    while counter < 10000 {
-        if counter % 500 == 0 {
-            println!("{prefix}attempt {counter}");
+impl SatSession for Z3Session {
+    fn solve(&mut self) -> Result<Option<Vec<Edge>>, String> {
+        match self.solver.check() {
+            SatResult::Sat => {}
+            SatResult::Unsat => return Ok(None),
+            SatResult::Unknown => return Err(String::from("Z3 returned unknown!")),
         }
-
-        match solver.check() {
-            SatResult::Sat => {
-                let model = solver.get_model().unwrap();
-
-                let edges: Vec<Edge> = vars
-                    .iter()
-                    .map(
-                        |var| match model.eval(var, true).and_then(|b| b.as_bool()) {
-                            Some(true) => Edge::Filled,
-                            _ => Edge::Empty,
-                        },
-                    )
-                    .collect();
-
-                let solution = Solution {
-                    puzzle: p.clone(),
-                    edges: edges.clone(),
-                    edges_pre_solve: base_edges.clone(),
-                    facts: facts.clone(),
-                };
-
-                if find_loops_edges(&p, &edges).len() == 1 {
-                    println!("{prefix}WIN! found single-loop solution!");
-                    solutions.push(solution);
-                    break;
-                } else {
-                    last_solution = Some(solution);
-                }
-
-                let groups = blocking_clause_edge_groups(&p, num_edges, &edges);
-                assert_blocking_groups_z3(&solver, &vars, &edges, &groups);
-            }
-            SatResult::Unsat => {
-                println!("{prefix}No more solutions!");
-                break;
-            }
-            SatResult::Unknown => {
-                println!("{prefix}Z3 returned unknown!");
-                break;
-            }
-        }
-
-        counter += 1;
+        let model = self.solver.get_model().unwrap();
+        let edges = self
+            .vars
+            .iter()
+            .map(
+                |var| match model.eval(var, true).and_then(|b| b.as_bool()) {
+                    Some(true) => Edge::Filled,
+                    _ => Edge::Empty,
+                },
+            )
+            .collect();
+        Ok(Some(edges))
     }
 
-    if solutions.is_empty() {
-        println!("{prefix}no proper solutions, well here's last thing:");
-        match last_solution {
-            Some(s) => solutions.push(s),
-            None => println!("{prefix}oh well"),
-        };
+    fn add_clauses(&mut self, clauses: &[EdgeClause]) -> bool {
+        assert_edge_clauses_z3(&self.solver, &self.vars, clauses);
+        true
     }
-
-    Some(solutions)
 }
 
 #[cfg(test)]
 mod test {
-    use super::solve_z3;
+    use super::{solutions_z3, Z3};
+    use crate::data::puzzle::Puzzle;
+    use crate::parse;
+    use crate::solve_common::SolutionStream;
+    use crate::{SolveOptions, SolveOutcome, SolverBackend};
+
+    fn no_pre() -> SolveOptions {
+        SolveOptions {
+            pre_solve: false,
+            ..SolveOptions::default()
+        }
+    }
 
     #[test]
     fn solves_simplest_2x2() {
-        let s = solve_z3(vec![vec![3, 2], vec![-1, -1]], false, "");
-        assert!(s.is_some());
-        let val = s.unwrap();
-        assert_eq!(val.len(), 1);
+        let s = Z3.solve(&Puzzle::from(&[[3, 2], [-1, -1]]), &no_pre());
+        assert!(s.is_solved());
     }
 
     #[test]
     fn handles_bad_puzzle() {
-        let s = solve_z3(vec![vec![0, 0], vec![0, 2]], false, "");
-        assert!(s.is_some());
-        assert_eq!(s.unwrap().len(), 0);
+        let s = Z3.solve(&Puzzle::from(&[[0, 0], [0, 2]]), &no_pre());
+        assert!(matches!(s, SolveOutcome::Unsolved(None)));
+    }
+
+    #[test]
+    fn connectivity_decides_in_one_check() {
+        let grid = parse::from_string("5x5:3322121021211311212201123").unwrap();
+        let p = Puzzle::from_grid(grid);
+        let options = SolveOptions {
+            connectivity: true,
+            ..no_pre()
+        };
+        let mut stream = solutions_z3(&p, &options);
+        assert!(stream.next().is_some());
+        assert_eq!(stream.sat_calls(), 1);
+        // The second check blocks the only solution and proves it unique.
+        assert!(stream.next().is_none());
+        assert!(stream.exhausted());
+    }
+
+    #[test]
+    fn connectivity_rejects_two_forced_loops() {
+        // Two 4s in opposite corners can only be two separate squares.
+        let p = Puzzle::from(&[[4, -1, -1], [-1, -1, -1], [-1, -1, 4]]);
+        let options = SolveOptions {
+            connectivity: true,
+            ..no_pre()
+        };
+        let mut stream = solutions_z3(&p, &options);
+        assert!(stream.next().is_none());
+        assert_eq!(stream.sat_calls(), 1);
+        assert!(stream.exhausted());
     }
 }
@@ -0,0 +1,535 @@
+//! Checks with SAT that a pattern's outputs follow from its inputs.
+//!
+//! A pattern sees the cells of its window and the edges between them. Its outputs must hold
+//! in every loop that fits the inputs, whatever the clues and edges around the window are.
+//! The formula has every edge touching one of the window's vertices: the edges around the
+//! window's cells and those that leave it. It has the clue of every window cell the pattern
+//! gives, and at most two edges on each of the window's vertices, while the edges that leave
+//! the window are otherwise free. An output holds when the formula with the output flipped
+//! has no model.
+//!
+//! The grid border can cut through the window: the window's rows inside the grid are some
+//! run of its rows, and so are its columns. Each of the ways, 36 for a 3x3 window, is checked
+//! on its own. Edges with no cell of the grid on either side are empty. In the inputs,
+//! `Empty` and `OutOfBounds` both match an empty edge as well as a missing one, so both just
+//! mean "not filled", while `Filled`, `EmptyStrict` and `Unknown` need the edge to be there.
+//! Outputs on edges that aren't there are skipped, as the engine does.
+//!
+//! Only local reasoning is encoded, so a pattern that relies on the puzzle having one
+//! solution is reported even when it is right. Some patterns rule out edges that would close
+//! a loop inside the window, which is right unless the whole loop fits in the window: those
+//! are first checked with such loops ruled out, and only reported when nothing else is wrong
+//! with them, marked [`Counterexample::small_loop`]. Each loop inside the window is ruled out
+//! when a model has it, as the SAT backends do with loops that leave part of the puzzle out.
+use std::fmt;
+
+use varisat::{CnfFormula, ExtendFormula, Lit, Solver};
+
+use crate::data::pattern::{Cell, Edge, Pattern, PatternSolution};
+use crate::patterns::{PatternSet, Paths};
+use crate::solve_common::{clause_one, clause_three, clause_two, clause_zero, loop_four};
+
+/// A case where an output of a pattern doesn't follow from its inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample {
+    /// Window cells outside the grid, in the notation of the pattern's cells.
+    pub outside: Vec<Vec<bool>>,
+    /// The output that fails: `true` for one of [`crate::data::pattern::Horizontals`], with
+    /// its row and column there.
+    pub edge: (bool, usize, usize),
+    /// What the pattern says the edge is.
+    pub filled: bool,
+    /// The edge only has the other value when the whole loop is inside the window, so the
+    /// pattern is only wrong on puzzles whose solution fits in the window.
+    pub small_loop: bool,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (horizontal, i, j) = self.edge;
+        let (kind, claim, other) = match (horizontal, self.filled) {
+            (true, true) => ("horizontal", "filled", "empty"),
+            (true, false) => ("horizontal", "empty", "filled"),
+            (false, true) => ("vertical", "filled", "empty"),
+            (false, false) => ("vertical", "empty", "filled"),
+        };
+        write!(f, "{kind} edge ({i}, {j}) is said to be {claim}, but can be {other}")?;
+        let outside: Vec<String> = self
+            .outside
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| row.iter().enumerate().map(move |(j, &out)| (i, j, out)))
+            .filter(|&(_, _, out)| out)
+            .map(|(i, j, _)| format!("({i}, {j})"))
+            .collect();
+        if !outside.is_empty() {
+            write!(f, " with cells {} outside the grid", outside.join(", "))?;
+        }
+        if self.small_loop {
+            write!(f, ", when the whole loop is inside the window")?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether an edge can be part of the loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Place {
+    /// A window cell on one side is in the grid.
+    Grid,
+    /// Only cells beyond the window could be in the grid.
+    Maybe,
+    /// Neither side is in the grid.
+    Missing,
+}
+
+/// The cells of a window of `rows` by `cols` cells and the ring around it. The window is
+/// rows 1 to `rows` and columns 1 to `cols` of the ring.
+#[derive(Debug, Clone, Copy)]
+struct Ring {
+    rows: usize,
+    cols: usize,
+}
+
+impl Ring {
+    /// Variable of the edge along the top of ring cell `(i, j)`, `i` up to `rows + 2`.
+    fn horizontal(&self, i: usize, j: usize) -> usize {
+        i * (self.cols + 2) + j
+    }
+
+    /// Variable of the edge along the left of ring cell `(i, j)`, `j` up to `cols + 2`.
+    fn vertical(&self, i: usize, j: usize) -> usize {
+        (self.rows + 3) * (self.cols + 2) + i * (self.cols + 3) + j
+    }
+
+    fn variables(&self) -> usize {
+        self.vertical(self.rows + 1, self.cols + 2) + 1
+    }
+
+    /// The variables of some loop of `values` along the edges of the window's cells.
+    fn window_loop(&self, values: &[bool]) -> Option<Vec<usize>> {
+        let vertex = |i: usize, j: usize| i * (self.cols + 3) + j;
+        let horizontals = (1..=self.rows + 1).flat_map(|i| {
+            (1..=self.cols).map(move |j| (self.horizontal(i, j), vertex(i, j), vertex(i, j + 1)))
+        });
+        let verticals = (1..=self.rows).flat_map(|i| {
+            (1..=self.cols + 1).map(move |j| (self.vertical(i, j), vertex(i, j), vertex(i + 1, j)))
+        });
+        let filled: Vec<_> =
+            horizontals.chain(verticals).filter(|&(var, _, _)| values[var]).collect();
+        // Each vertex has two edges or none, so a fragment with as many edges as vertices is
+        // a loop.
+        let vertices = (self.rows + 3) * (self.cols + 3);
+        let mut paths = Paths::new(vertices);
+        let mut touched = vec![false; vertices];
+        for &(_, a, b) in &filled {
+            paths.join(a, b);
+            touched[a] = true;
+            touched[b] = true;
+        }
+        let mut size = vec![0; vertices];
+        for v in (0..vertices).filter(|&v| touched[v]) {
+            size[paths.find(v)] += 1;
+        }
+        let root = (0..vertices).find(|&r| size[r] > 0 && paths.edges[r] == size[r])?;
+        let cycle = filled
+            .iter()
+            .filter(|&&(_, a, _)| paths.find(a) == root)
+            .map(|&(var, _, _)| var)
+            .collect();
+        Some(cycle)
+    }
+}
+
+/// Every way the grid border can cut through a window of `rows` by `cols` cells, as the
+/// window cells inside the grid. Shorter runs of rows and columns come first.
+fn layouts((rows, cols): (usize, usize)) -> Vec<Vec<Vec<bool>>> {
+    let runs = |n: usize| {
+        (1..=n)
+            .flat_map(move |len| (0..=n - len).map(move |start| start..start + len))
+            .collect::<Vec<_>>()
+    };
+    let mut res = vec![];
+    for row_run in runs(rows) {
+        for col_run in runs(cols) {
+            let inside = (0..rows)
+                .map(|i| (0..cols).map(|j| row_run.contains(&i) && col_run.contains(&j)).collect())
+                .collect();
+            res.push(inside);
+        }
+    }
+    res
+}
+
+/// The first output of `pattern` that doesn't follow from its inputs, over every way the
+/// grid border can cut through the window.
+pub fn check(pattern: &PatternSolution) -> Result<(), Counterexample> {
+    for small_loop in [false, true] {
+        for inside in layouts(pattern.size()) {
+            let Some(mut encoding) = Encoding::new(pattern, &inside, small_loop) else {
+                continue;
+            };
+            for &(at, var, place) in &encoding.edges.clone() {
+                let filled = match get(&pattern.output, at) {
+                    Edge::Filled => true,
+                    Edge::Empty => false,
+                    _ => continue,
+                };
+                if place == Place::Grid && encoding.can_be(var, !filled) {
+                    return Err(Counterexample {
+                        outside: inside
+                            .iter()
+                            .map(|row| row.iter().map(|x| !x).collect())
+                            .collect(),
+                        edge: at,
+                        filled,
+                        small_loop,
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every pattern of `patterns` that [`check`] finds wrong, by name.
+pub fn unsound(patterns: &PatternSet) -> Vec<(String, Counterexample)> {
+    patterns
+        .iter()
+        .filter_map(|(name, pattern)| check(pattern).err().map(|c| (name.clone(), c)))
+        .collect()
+}
+
+/// The edges that follow from the inputs of `pattern`, as the output of a pattern that
+/// [`check`] accepts without [`Counterexample::small_loop`]. Edges the inputs already give
+/// are left out. `None` when the inputs can't all hold anywhere.
+pub fn forced(pattern: &PatternSolution) -> Option<Pattern> {
+    let at = pattern_edges(pattern.size());
+    // For each pattern edge, its value in every layout so far, `None` once it isn't forced.
+    let mut values: Vec<Option<Option<bool>>> = vec![Some(None); at.len()];
+    let mut matches = false;
+    for inside in layouts(pattern.size()) {
+        let Some(mut encoding) = Encoding::new(pattern, &inside, true) else {
+            continue;
+        };
+        let Some(model) = encoding.model() else {
+            continue;
+        };
+        matches = true;
+        for (k, &(at, var, place)) in encoding.edges.clone().iter().enumerate() {
+            let Some(value) = values[k] else {
+                continue;
+            };
+            if place != Place::Grid || get(&pattern.input, at) != Edge::Any {
+                continue;
+            }
+            let filled = model[var];
+            values[k] = match value {
+                Some(v) if v != filled => None,
+                _ if encoding.can_be(var, !filled) => None,
+                _ => Some(Some(filled)),
+            };
+        }
+    }
+    if !matches {
+        return None;
+    }
+    let (rows, cols) = pattern.size();
+    let mut output = Pattern {
+        horizontals: vec![vec![Edge::Any; cols]; rows - 1],
+        verticals: vec![vec![Edge::Any; cols - 1]; rows],
+    };
+    for (k, &at) in at.iter().enumerate() {
+        if let Some(Some(filled)) = values[k] {
+            if get(&pattern.input, at) == Edge::Any {
+                *get_mut(&mut output, at) = if filled { Edge::Filled } else { Edge::Empty };
+            }
+        }
+    }
+    Some(output)
+}
+
+/// The edges of a [`Pattern`] of `rows` by `cols` cells, horizontals first, as
+/// `(horizontal, row, column)`.
+fn pattern_edges((rows, cols): (usize, usize)) -> Vec<(bool, usize, usize)> {
+    let horizontals = (0..rows - 1).flat_map(|i| (0..cols).map(move |j| (true, i, j)));
+    let verticals = (0..rows).flat_map(|i| (0..cols - 1).map(move |j| (false, i, j)));
+    horizontals.chain(verticals).collect()
+}
+
+fn get(p: &Pattern, (horizontal, i, j): (bool, usize, usize)) -> Edge {
+    if horizontal {
+        p.horizontals[i][j]
+    } else {
+        p.verticals[i][j]
+    }
+}
+
+fn get_mut(p: &mut Pattern, (horizontal, i, j): (bool, usize, usize)) -> &mut Edge {
+    if horizontal {
+        &mut p.horizontals[i][j]
+    } else {
+        &mut p.verticals[i][j]
+    }
+}
+
+/// The inputs of a pattern in one layout, with or without loops that close inside the
+/// window.
+struct Encoding {
+    solver: Solver<'static>,
+    ring: Ring,
+    small_loop: bool,
+    /// The pattern's edges in [`pattern_edges`] order, with their variables and places.
+    edges: Vec<((bool, usize, usize), usize, Place)>,
+}
+
+impl Encoding {
+    /// `None` when the pattern can't match in this layout: a cell is on the wrong side of
+    /// the border, or an input needs an edge the grid doesn't have.
+    fn new(
+        pattern: &PatternSolution,
+        inside: &[Vec<bool>],
+        small_loop: bool,
+    ) -> Option<Encoding> {
+        let (rows, cols) = pattern.size();
+        let ring = Ring { rows, cols };
+        let cells_fit = (0..rows).all(|i| {
+            (0..cols).all(|j| match pattern.cells[i][j] {
+                Cell::Any => true,
+                Cell::OutOfBounds => !inside[i][j],
+                _ => inside[i][j],
+            })
+        });
+        if !cells_fit {
+            return None;
+        }
+
+        // A ring cell can only be in the grid when the window cell next to it is.
+        let cell = |i: usize, j: usize| {
+            let (wi, wj) = (i.clamp(1, rows) - 1, j.clamp(1, cols) - 1);
+            match (inside[wi][wj], (wi + 1, wj + 1) == (i, j)) {
+                (false, _) => Place::Missing,
+                (true, true) => Place::Grid,
+                (true, false) => Place::Maybe,
+            }
+        };
+        let place = |a: Place, b: Place| match (a, b) {
+            (Place::Grid, _) | (_, Place::Grid) => Place::Grid,
+            (Place::Maybe, _) | (_, Place::Maybe) => Place::Maybe,
+            _ => Place::Missing,
+        };
+        let horizontal_place = |i: usize, j: usize| place(cell(i - 1, j), cell(i, j));
+        let vertical_place = |i: usize, j: usize| place(cell(i, j - 1), cell(i, j));
+
+        let lit = |var: usize| Lit::from_index(var, true);
+        let mut formula = CnfFormula::new();
+        // Vertices of the window, with their edges up, down, left and right.
+        for i in 1..=rows + 1 {
+            for j in 1..=cols + 1 {
+                let around = [
+                    (ring.vertical(i - 1, j), vertical_place(i - 1, j)),
+                    (ring.vertical(i, j), vertical_place(i, j)),
+                    (ring.horizontal(i, j - 1), horizontal_place(i, j - 1)),
+                    (ring.horizontal(i, j), horizontal_place(i, j)),
+                ];
+                for &(var, place) in &around {
+                    if place == Place::Missing {
+                        formula.add_clause(&[!lit(var)]);
+                    }
+                }
+                let [a, b, c, d] = around.map(|(var, _)| lit(var));
+                for clause in loop_four(a, b, c, d) {
+                    formula.add_clause(&clause);
+                }
+            }
+        }
+        for i in 0..rows {
+            for j in 0..cols {
+                let (ri, rj) = (i + 1, j + 1);
+                let edges = (
+                    lit(ring.horizontal(ri, rj)),
+                    lit(ring.horizontal(ri + 1, rj)),
+                    lit(ring.vertical(ri, rj)),
+                    lit(ring.vertical(ri, rj + 1)),
+                );
+                let clauses = match pattern.cells[i][j] {
+                    Cell::Zero => clause_zero(edges),
+                    Cell::One => clause_one(edges),
+                    Cell::Two => clause_two(edges),
+                    Cell::Three => clause_three(edges),
+                    _ => vec![],
+                };
+                for clause in clauses {
+                    formula.add_clause(&clause);
+                }
+            }
+        }
+
+        let edges: Vec<_> = pattern_edges((rows, cols))
+            .into_iter()
+            .map(|(h, i, j)| {
+                if h {
+                    let (ri, rj) = (i + 2, j + 1);
+                    ((h, i, j), ring.horizontal(ri, rj), horizontal_place(ri, rj))
+                } else {
+                    let (ri, rj) = (i + 1, j + 2);
+                    ((h, i, j), ring.vertical(ri, rj), vertical_place(ri, rj))
+                }
+            })
+            .collect();
+        for &(at, var, place) in &edges {
+            let there = place == Place::Grid;
+            match get(&pattern.input, at) {
+                Edge::Filled if there => formula.add_clause(&[lit(var)]),
+                Edge::EmptyStrict if there => formula.add_clause(&[!lit(var)]),
+                Edge::Empty | Edge::OutOfBounds => formula.add_clause(&[!lit(var)]),
+                Edge::Unknown if there => {}
+                Edge::Any => {}
+                _ => return None,
+            }
+        }
+
+        let mut solver = Solver::new();
+        solver.add_formula(&formula);
+        Some(Encoding {
+            solver,
+            ring,
+            small_loop,
+            edges,
+        })
+    }
+
+    /// Some loop fits the inputs with `var` set to `filled`.
+    fn can_be(&mut self, var: usize, filled: bool) -> bool {
+        self.solve(&[Lit::from_index(var, filled)]).is_some()
+    }
+
+    /// The value of every variable in some loop that fits the inputs.
+    fn model(&mut self) -> Option<Vec<bool>> {
+        self.solve(&[])
+    }
+
+    /// A model under `assumptions`. Unless small loops are allowed, each loop inside the
+    /// window a model has is ruled out for good and the solver tries again.
+    fn solve(&mut self, assumptions: &[Lit]) -> Option<Vec<bool>> {
+        loop {
+            self.solver.assume(assumptions);
+            if !self.solver.solve().unwrap_or(false) {
+                return None;
+            }
+            let mut values = vec![false; self.ring.variables()];
+            for lit in self.solver.model()? {
+                values[lit.index()] = lit.is_positive();
+            }
+            if self.small_loop {
+                return Some(values);
+            }
+            let Some(cycle) = self.ring.window_loop(&values) else {
+                return Some(values);
+            };
+            let clause: Vec<Lit> = cycle.iter().map(|&var| Lit::from_index(var, false)).collect();
+            self.solver.add_clause(&clause);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::patterns::{pattern_name, small_loop};
+
+    #[test]
+    fn every_baked_in_pattern_is_sound() {
+        let patterns = PatternSet::baked_in();
+        let mut wrong = vec![];
+        let mut small = BTreeSet::new();
+        for (name, c) in unsound(&patterns) {
+            let (_, pattern) = patterns.iter().find(|(n, _)| **n == name).unwrap();
+            // The engine doesn't match these where the whole loop could fit in the window.
+            if c.small_loop && small_loop(pattern) {
+                small.insert(pattern_name(&name).to_string());
+            } else {
+                wrong.push(format!("{name}: {c}"));
+            }
+        }
+        assert!(wrong.is_empty(), "{}", wrong.join("\n"));
+        // Both cross out or fill an edge the way every loop bigger than the window needs.
+        let expected = ["simple loop", "three orthoganally"].map(String::from);
+        assert_eq!(small, BTreeSet::from(expected));
+    }
+
+    #[test]
+    fn border_cells_count() {
+        let in_corner = |cells: &str| {
+            PatternSolution::parse(
+                cells,
+                "
+                *****
+                *.-.*
+                *|***
+                *.*.*
+                *****
+            ",
+            )
+        };
+        // The corner vertex of a 3 in the grid's corner has just its two edges.
+        let corner = in_corner(
+            "
+            B*B*B
+            *.*.*
+            B*3**
+            *.*.*
+            B****
+        ",
+        );
+        assert_eq!(check(&corner), Ok(()));
+        let anywhere = in_corner(
+            "
+            *****
+            *.*.*
+            **3**
+            *.*.*
+            *****
+        ",
+        );
+        assert!(check(&anywhere).is_err_and(|c| !c.small_loop));
+    }
+
+    #[test]
+    fn loops_inside_the_window_come_last() {
+        let pattern = |name: &str| {
+            let patterns = PatternSet::baked_in();
+            let (_, pattern) = patterns.iter().find(|(n, _)| *n == name).unwrap();
+            pattern.clone()
+        };
+        // Two 3s side by side can be the whole loop, on a puzzle like 1x2:33.
+        let threes = pattern("three orthoganally-0");
+        let c = check(&threes).unwrap_err();
+        assert!(c.small_loop, "{c}");
+        assert!(small_loop(&threes));
+        assert!(!small_loop(&pattern("zero")));
+
+        let ring = Ring { rows: 1, cols: 2 };
+        let mut values = vec![false; ring.variables()];
+        let around_both = [
+            ring.horizontal(1, 1),
+            ring.horizontal(1, 2),
+            ring.horizontal(2, 1),
+            ring.horizontal(2, 2),
+            ring.vertical(1, 1),
+            ring.vertical(1, 3),
+        ];
+        for var in around_both {
+            values[var] = true;
+        }
+        let mut found = ring.window_loop(&values).unwrap();
+        let mut expected = around_both.to_vec();
+        found.sort();
+        expected.sort();
+        assert_eq!(found, expected);
+        values[ring.vertical(1, 1)] = false;
+        assert_eq!(ring.window_loop(&values), None);
+    }
+}